@@ -8,9 +8,16 @@
 extern crate unsafe_fn;
 
 pub mod gpio;
+#[cfg(feature = "panic_report")]
+pub mod panic;
 pub mod serial;
 pub mod sound;
 pub mod tempmon;
+pub mod timing;
+pub mod watchdog;
+
+#[cfg(test)]
+mod test_support;
 
 pub use gpio::{Pin, PinMode, LED_BUILTIN};
 pub use sound::Tone;
@@ -67,6 +74,44 @@ pub fn delay_microseconds(us: u32) {
     unsafe { delayMicroseconds(us) }
 }
 
+/// Pauses the program for the amount of time (in microseconds) specified by the parameter,
+/// by busy waiting on the cpu's cycle counter. This avoids most of the overhead of the
+/// other delays, for short and timing critical delays such as when bit-banging a protocol.
+///
+/// # Notes and Warnings
+/// Delays down to 1 microsecond are reliable; the function call itself takes only a
+/// few dozen cycles (well under a tenth of a microsecond at 600 MHz). Interrupts are
+/// not disabled, so an interrupt that fires during the delay will lengthen it.
+///
+/// The cycle counter overflows every 2^32 cycles (about 7 seconds at 600 MHz), so
+/// delays longer than that are cut short. Use delay() for those instead.
+pub fn delay_microseconds_precise(us: u32) {
+    extern "C" {
+        static F_CPU_ACTUAL: u32;
+    }
+
+    let cycles = cycles_for_microseconds(us, unsafe { F_CPU_ACTUAL });
+    let start = cycle_count();
+
+    while cycle_count().wrapping_sub(start) < cycles {}
+}
+
+/// The amount of cpu cycles that pass in the amount of microseconds at the
+/// clock speed, saturating at the most cycles the cycle counter can time
+fn cycles_for_microseconds(us: u32, cpu_hz: u32) -> u32 {
+    let cycles = u64::from(us) * u64::from(cpu_hz) / 1_000_000;
+
+    cycles.min(u64::from(u32::MAX)) as u32
+}
+
+/// Read the cpu's cycle counter (the DWT CYCCNT register), which is
+/// started by the teensyduino startup code
+fn cycle_count() -> u32 {
+    const DWT_CYCCNT: *const u32 = 0xE000_1004 as *const u32;
+
+    unsafe { core::ptr::read_volatile(DWT_CYCCNT) }
+}
+
 /// Basically delay microseconds, but nanoseconds
 pub fn delay_nanoseconds(ns: u32) {
     extern "C" {
@@ -118,3 +163,27 @@ pub fn micros() -> u32 {
 
     unsafe { micros() }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CPU_HZ: u32 = 600_000_000;
+
+    #[test]
+    fn cycles_are_counted_at_the_clock_speed() {
+        assert_eq!(cycles_for_microseconds(0, CPU_HZ), 0);
+        assert_eq!(cycles_for_microseconds(1, CPU_HZ), 600);
+        assert_eq!(cycles_for_microseconds(1_000_000, CPU_HZ), CPU_HZ);
+        // Rounded down, 1 microsecond at 1.5 MHz is 1.5 cycles
+        assert_eq!(cycles_for_microseconds(1, 1_500_000), 1);
+    }
+
+    #[test]
+    fn cycles_saturate_at_the_counter_limit() {
+        // 7_158_278 microseconds is the last that fits in the 32 bit cycle counter
+        assert_eq!(cycles_for_microseconds(7_158_278, CPU_HZ), 4_294_966_800);
+        assert_eq!(cycles_for_microseconds(7_158_279, CPU_HZ), u32::MAX);
+        assert_eq!(cycles_for_microseconds(u32::MAX, CPU_HZ), u32::MAX);
+    }
+}
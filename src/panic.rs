@@ -0,0 +1,151 @@
+//! Reporting of panics over the usb serial port, which would otherwise go unseen
+//!
+//! **Requires the feature `panic_report`**
+//!
+//! With the feature `serial_panic`, a panic handler is installed that [`report`]s
+//! the panic and then halts or resets the board, as chosen with [`set_behavior`].
+//! A program can only have one panic handler, so `serial_panic` can not be used
+//! alongside another one (such as the `panic-halt` crate). To keep your own panic
+//! handler, leave `serial_panic` disabled and call [`report`] from it instead.
+
+use core::{
+    fmt::{self, Display, Formatter},
+    panic::PanicInfo,
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use core::fmt::Write;
+
+#[cfg(not(feature = "usb_logging"))]
+use crate::serial::ansi::{Color, EscapeSequence, Style};
+use crate::serial::{USBSerialWriter, SERIAL};
+
+/// What the board does after a panic has been reported
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PanicBehavior {
+    /// Stop in an endless loop, leaving the report on screen
+    Halt,
+    /// Reset the board, restarting the program
+    Reset,
+}
+
+static RESET_ON_PANIC: AtomicBool = AtomicBool::new(false);
+
+/// Set what the panic handler does after reporting a panic. Defaults to [`PanicBehavior::Halt`]
+pub fn set_behavior(behavior: PanicBehavior) {
+    RESET_ON_PANIC.store(behavior == PanicBehavior::Reset, Ordering::Relaxed);
+}
+
+/// Get what the panic handler does after reporting a panic
+pub fn behavior() -> PanicBehavior {
+    if RESET_ON_PANIC.load(Ordering::Relaxed) {
+        PanicBehavior::Reset
+    } else {
+        PanicBehavior::Halt
+    }
+}
+
+/// Write the panic message and location out over the serial port in red, and
+/// push it out to the host immediately.
+///
+/// With the feature `usb_logging`, the panic is written out as an `Error` line
+/// of the logger instead, in the same format and colors as the logged lines.
+pub fn report(info: &PanicInfo) {
+    let mut writer = USBSerialWriter {};
+
+    // Errors are ignored, as there is nothing left to report them to
+    #[cfg(feature = "usb_logging")]
+    {
+        let location = info.location();
+
+        crate::serial::log::USBLogger::format_record(
+            &mut writer,
+            &::log::Record::builder()
+                .args(format_args!("{}", Message(info)))
+                .level(::log::Level::Error)
+                .target("panic")
+                .file(location.map(|location| location.file()))
+                .line(location.map(|location| location.line()))
+                .build(),
+        )
+        .ok();
+    }
+
+    #[cfg(not(feature = "usb_logging"))]
+    writeln!(
+        writer,
+        "{}{}{}\r",
+        EscapeSequence::new().set_fg(Color::LightRed),
+        Message(info),
+        EscapeSequence::new().set_styles(&[Style::Clear])
+    )
+    .ok();
+
+    SERIAL::send_now();
+}
+
+/// The message of a panic, with its location, on one line
+struct Message<'a, 'b>(&'a PanicInfo<'b>);
+
+impl Display for Message<'_, '_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // The panic's own format puts the message on a new line after the location
+        write!(OneLine(f), "{}", self.0)
+    }
+}
+
+/// A writer that writes line breaks out as spaces
+struct OneLine<'a, 'b>(&'a mut Formatter<'b>);
+
+impl Write for OneLine<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.0.write_char(' ')?;
+            }
+            self.0.write_str(line)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Stop the program in an endless loop
+pub fn halt() -> ! {
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Reset the board by requesting a system reset from the ARM core
+pub fn reset() -> ! {
+    /// The Application Interrupt and Reset Control Register
+    const SCB_AIRCR: *mut u32 = 0xE000_ED0C as *mut u32;
+    /// The register key and the SYSRESETREQ bit
+    const SYSRESETREQ: u32 = 0x05FA_0004;
+
+    unsafe { ptr::write_volatile(SCB_AIRCR, SYSRESETREQ) };
+
+    // Wait for the reset to happen
+    halt()
+}
+
+// The host tests use std's panic handler
+#[cfg(all(feature = "serial_panic", not(test)))]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    report(info);
+
+    // Give the panic some context with the last lines that were logged
+    #[cfg(feature = "usb_logging")]
+    {
+        crate::serial::log::USBLogger::dump_recent(&mut USBSerialWriter {}).ok();
+        SERIAL::send_now();
+    }
+
+    match behavior() {
+        PanicBehavior::Halt => halt(),
+        PanicBehavior::Reset => reset(),
+    }
+}
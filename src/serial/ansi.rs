@@ -1,8 +1,14 @@
 //! Tools for working with ansi escape sequences, mainly in serial terminals
-//! 
+//!
 //! **Requires the feature `ansi`**
 
-use core::fmt::{self, Display, Formatter};
+use core::{
+    fmt::{self, Display, Formatter, Write},
+    str,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use super::{USBSerial, USBSerialWriter};
 
 const ANSI_ESCAPE: &str = "\u{1B}[";
 const ANSI_ESCAPE_END: &str = "m";
@@ -29,6 +35,325 @@ pub enum Color {
     TrueColor { r: u8, g: u8, b: u8 },
 }
 
+impl Color {
+    /// Create a true color from its red, green and blue components
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color::TrueColor { r, g, b }
+    }
+
+    /// Get the red, green and blue components of the color.
+    ///
+    /// The named colors are shown differently by every terminal, so they are
+    /// resolved to the values of xterm's default palette:
+    ///
+    /// | Color   | Normal          | Light           |
+    /// |---------|-----------------|-----------------|
+    /// | Black   | `0, 0, 0`       | `127, 127, 127` |
+    /// | Red     | `205, 0, 0`     | `255, 0, 0`     |
+    /// | Green   | `0, 205, 0`     | `0, 255, 0`     |
+    /// | Yellow  | `205, 205, 0`   | `255, 255, 0`   |
+    /// | Blue    | `0, 0, 238`     | `92, 92, 255`   |
+    /// | Magenta | `205, 0, 205`   | `255, 0, 255`   |
+    /// | Cyan    | `0, 205, 205`   | `0, 255, 255`   |
+    /// | White   | `229, 229, 229` | `255, 255, 255` |
+    pub const fn as_rgb(self) -> (u8, u8, u8) {
+        match self {
+            Color::Black => (0, 0, 0),
+            Color::Red => (205, 0, 0),
+            Color::Green => (0, 205, 0),
+            Color::Yellow => (205, 205, 0),
+            Color::Blue => (0, 0, 238),
+            Color::Magenta => (205, 0, 205),
+            Color::Cyan => (0, 205, 205),
+            Color::White => (229, 229, 229),
+            Color::LightBlack => (127, 127, 127),
+            Color::LightRed => (255, 0, 0),
+            Color::LightGreen => (0, 255, 0),
+            Color::LightYellow => (255, 255, 0),
+            Color::LightBlue => (92, 92, 255),
+            Color::LightMagenta => (255, 0, 255),
+            Color::LightCyan => (0, 255, 255),
+            Color::LightWhite => (255, 255, 255),
+            Color::TrueColor { r, g, b } => (r, g, b),
+        }
+    }
+
+    /// Find the named color closest to this one, for terminals that only support the 16 named colors
+    pub fn nearest_named(self) -> Color {
+        let (r, g, b) = self.as_rgb();
+        let distance = |color: Color| {
+            let (cr, cg, cb) = color.as_rgb();
+            let (dr, dg, db) = (
+                i32::from(r) - i32::from(cr),
+                i32::from(g) - i32::from(cg),
+                i32::from(b) - i32::from(cb),
+            );
+
+            dr * dr + dg * dg + db * db
+        };
+
+        NAMED_COLORS
+            .iter()
+            .copied()
+            .min_by_key(|&color| distance(color))
+            .unwrap()
+    }
+
+    /// Find the index into the 6x6x6 color cube of the 256 color palette that is closest to
+    /// this color, for terminals that support 256 colors but not true color
+    pub const fn nearest_256(self) -> u8 {
+        const fn level(component: u8) -> u8 {
+            // The cube levels are 0, 95, 135, 175, 215 and 255
+            if component < 48 {
+                0
+            } else if component < 115 {
+                1
+            } else {
+                (component - 35) / 40
+            }
+        }
+
+        let (r, g, b) = self.as_rgb();
+
+        16 + 36 * level(r) + 6 * level(g) + level(b)
+    }
+
+    /// Get the true color that an index into the 256 color palette stands for, in
+    /// xterm's default palette. This is the reverse of [`nearest_256`](Color::nearest_256).
+    ///
+    /// - `0..=15` are the named colors, resolved with [`as_rgb`](Color::as_rgb)
+    /// - `16..=231` are the 6x6x6 color cube, with the levels 0, 95, 135, 175, 215 and 255
+    /// - `232..=255` are the grayscale ramp, from `8, 8, 8` to `238, 238, 238`
+    pub const fn palette_to_truecolor(index: u8) -> Color {
+        const fn level(step: u8) -> u8 {
+            if step == 0 {
+                0
+            } else {
+                55 + 40 * step
+            }
+        }
+
+        match index {
+            0..=15 => {
+                let (r, g, b) = NAMED_COLORS[index as usize].as_rgb();
+
+                Color::rgb(r, g, b)
+            }
+            16..=231 => {
+                let cube = index - 16;
+
+                Color::rgb(level(cube / 36), level(cube / 6 % 6), level(cube % 6))
+            }
+            _ => {
+                let gray = 8 + 10 * (index - 232);
+
+                Color::rgb(gray, gray, gray)
+            }
+        }
+    }
+
+    /// Blend between two colors, getting `a` at `t = 0.0` and `b` at `t = 1.0`, for
+    /// drawing gradients. Each component is interpolated separately, with named colors
+    /// resolved with [`as_rgb`](Color::as_rgb), and `t` is clamped to `0.0..=1.0`.
+    pub fn lerp(a: Color, b: Color, t: f32) -> Color {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+
+        let mix = |from: u8, to: u8| {
+            let (from, to) = (f32::from(from), f32::from(to));
+
+            // Rounded to the nearest, there is no f32::round without std
+            (from + (to - from) * t + 0.5) as u8
+        };
+
+        let ((r1, g1, b1), (r2, g2, b2)) = (a.as_rgb(), b.as_rgb());
+
+        Color::rgb(mix(r1, r2), mix(g1, g2), mix(b1, b2))
+    }
+
+    /// The [WCAG contrast ratio](https://www.w3.org/TR/WCAG21/#dfn-contrast-ratio) between
+    /// two colors, from 1 (no contrast) to 21 (black on white). Named colors are resolved
+    /// with [`as_rgb`](Color::as_rgb).
+    pub fn contrast_ratio(fg: Color, bg: Color) -> f32 {
+        let (fg, bg) = (fg.luminance(), bg.luminance());
+        let (lighter, darker) = if fg > bg { (fg, bg) } else { (bg, fg) };
+
+        (lighter + 0.05) / (darker + 0.05)
+    }
+
+    /// Get a foreground color that is readable on the background: the foreground itself if
+    /// its [`contrast_ratio`](Color::contrast_ratio) is at least [`MIN_CONTRAST_RATIO`], or
+    /// otherwise black or white, whichever contrasts more with the background.
+    pub fn ensure_readable(fg: Color, bg: Color) -> Color {
+        if Color::contrast_ratio(fg, bg) >= MIN_CONTRAST_RATIO {
+            return fg;
+        }
+
+        let (black, white) = (Color::rgb(0, 0, 0), Color::rgb(255, 255, 255));
+
+        if Color::contrast_ratio(black, bg) > Color::contrast_ratio(white, bg) {
+            black
+        } else {
+            white
+        }
+    }
+
+    /// The relative luminance of the color, from 0 (black) to 1 (white)
+    fn luminance(self) -> f32 {
+        /// Undo the sRGB gamma of a color component
+        fn linearize(component: u8) -> f32 {
+            let c = f32::from(component) / 255.0;
+
+            if c <= 0.040_45 {
+                c / 12.92
+            } else {
+                // x^2.4, as x^2 * (x^(1/5))^2, without a float math library
+                let x = (c + 0.055) / 1.055;
+                let root = fifth_root(x);
+
+                x * x * root * root
+            }
+        }
+
+        /// The fifth root of a number between 0 and 1, with Newton's method
+        fn fifth_root(x: f32) -> f32 {
+            let mut y = 1.0f32;
+
+            for _ in 0..8 {
+                y = (4.0 * y + x / (y * y * y * y)) / 5.0;
+            }
+
+            y
+        }
+
+        let (r, g, b) = self.as_rgb();
+
+        0.2126 * linearize(r) + 0.7152 * linearize(g) + 0.0722 * linearize(b)
+    }
+}
+
+/// The lowest contrast ratio that [`Color::ensure_readable`] accepts, which is the
+/// minimum for normal text of [WCAG level AA](https://www.w3.org/TR/WCAG21/#contrast-minimum)
+pub const MIN_CONTRAST_RATIO: f32 = 4.5;
+
+/// All of the named colors, in the order of their escape codes
+const NAMED_COLORS: [Color; 16] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::White,
+    Color::LightBlack,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightCyan,
+    Color::LightWhite,
+];
+
+/// The level of color support of the connected terminal, which decides how colors are
+/// written out. True colors are downgraded to the closest color that the terminal can show.
+///
+/// This is set at runtime with [`set_color_level`], unlike the `no_color` feature
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[repr(u8)]
+pub enum ColorLevel {
+    /// No escape sequences are written out at all
+    None = 0,
+    /// Only the 16 named colors
+    Basic = 1,
+    /// The 256 color palette
+    Ansi256 = 2,
+    /// Full 24 bit colors
+    TrueColor = 3,
+}
+
+static COLOR_LEVEL: AtomicU8 = AtomicU8::new(ColorLevel::TrueColor as u8);
+
+/// Set the level of color support used when writing out escape sequences.
+/// Defaults to [`ColorLevel::TrueColor`]
+pub fn set_color_level(level: ColorLevel) {
+    COLOR_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Get the level of color support used when writing out escape sequences
+pub fn color_level() -> ColorLevel {
+    match COLOR_LEVEL.load(Ordering::Relaxed) {
+        0 => ColorLevel::None,
+        1 => ColorLevel::Basic,
+        2 => ColorLevel::Ansi256,
+        _ => ColorLevel::TrueColor,
+    }
+}
+
+/// The purposes that a [`Palette`] assigns colors to
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Role {
+    /// Most highlighted text
+    Primary,
+    /// Text that needs to stand out from the primary text
+    Accent,
+    /// Errors and failures
+    Error,
+    /// Text of little importance, such as hints and timestamps
+    Muted,
+}
+
+/// A set of colors for each [`Role`], so that text is colored by what it is for
+/// instead of with hardcoded colors. Switching themes is then a matter of
+/// switching palettes.
+///
+/// The default palette is [`Palette::DARK`], which matches the colors of the logger.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Palette {
+    /// The color of [`Role::Primary`] text
+    pub primary: Color,
+    /// The color of [`Role::Accent`] text
+    pub accent: Color,
+    /// The color of [`Role::Error`] text
+    pub error: Color,
+    /// The color of [`Role::Muted`] text
+    pub muted: Color,
+}
+
+impl Palette {
+    /// A palette for terminals with a dark background
+    pub const DARK: Palette = Palette {
+        primary: Color::LightBlue,
+        accent: Color::LightYellow,
+        error: Color::LightRed,
+        muted: Color::LightBlack,
+    };
+
+    /// A palette for terminals with a light background
+    pub const LIGHT: Palette = Palette {
+        primary: Color::Blue,
+        accent: Color::Magenta,
+        error: Color::Red,
+        muted: Color::LightBlack,
+    };
+
+    /// Get the color of the role
+    pub const fn color(&self, role: Role) -> Color {
+        match role {
+            Role::Primary => self.primary,
+            Role::Accent => self.accent,
+            Role::Error => self.error,
+            Role::Muted => self.muted,
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::DARK
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 #[allow(missing_docs)]
 pub enum Style {
@@ -41,6 +366,21 @@ pub enum Style {
     Blink,
     Hidden,
     Strikethrough,
+    DoubleUnderline,
+    Overline,
+    /// Turns off both `Bold` and `Dimmed`
+    NotBold,
+    NotItalic,
+    /// Turns off both `Underline` and `DoubleUnderline`
+    NotUnderline,
+    NotBlink,
+    NotReversed,
+    NotHidden,
+    NotStrikethrough,
+    NotOverline,
+    /// Underlines in the color of the text again, after
+    /// [`EscapeSequence::set_underline_color`]
+    NotUnderlineColor,
 }
 
 /// A structure defining an ansi escape sequence. To convert
@@ -50,6 +390,7 @@ pub enum Style {
 pub struct EscapeSequence<'a> {
     fg: Option<Color>,
     bg: Option<Color>,
+    underline_color: Option<Color>,
     styles: &'a [Style],
 }
 
@@ -59,6 +400,7 @@ impl<'a> EscapeSequence<'a> {
         Self {
             bg: None,
             fg: None,
+            underline_color: None,
             styles: &[],
         }
     }
@@ -79,6 +421,20 @@ impl<'a> EscapeSequence<'a> {
         }
     }
 
+    /// Set the color of underlines, separate from the color of the text, such as for
+    /// highlighting errors. Go back to underlines in the text color with
+    /// [`Style::NotUnderlineColor`].
+    ///
+    /// Terminals that do not support underline colors ignore it. As there is no
+    /// underline color in the 16 named colors, named colors are written out as their
+    /// index into the 256 color palette.
+    pub const fn set_underline_color(self, color: Color) -> Self {
+        EscapeSequence {
+            underline_color: Some(color),
+            ..self
+        }
+    }
+
     /// Set the styles in the escape sequence
     pub const fn set_styles(self, styles: &'a [Style]) -> Self {
         EscapeSequence { styles, ..self }
@@ -88,14 +444,28 @@ impl<'a> EscapeSequence<'a> {
 #[cfg(not(feature = "no_color"))]
 impl<'a> Display for EscapeSequence<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let level = color_level();
+
+        if level == ColorLevel::None {
+            return Ok(());
+        }
+
         f.write_str(ANSI_ESCAPE)?;
 
+        // The parameters are separated by semicolons
+        let mut separator = "";
+
         // Foreground format
         if let Some(color) = self.fg {
-            if let Color::TrueColor { r, g, b } = color {
-                write!(f, "38;2;{};{};{}", r, g, b)?;
-            } else {
-                f.write_str(match color {
+            f.write_str(separator)?;
+            separator = ";";
+
+            match downgrade(color, level) {
+                Color::TrueColor { r, g, b } if level == ColorLevel::TrueColor => {
+                    write!(f, "38;2;{};{};{}", r, g, b)?
+                }
+                color @ Color::TrueColor { .. } => write!(f, "38;5;{}", color.nearest_256())?,
+                color => f.write_str(match color {
                     Color::Black => "30",
                     Color::Red => "31",
                     Color::Green => "32",
@@ -113,16 +483,21 @@ impl<'a> Display for EscapeSequence<'a> {
                     Color::LightCyan => "96",
                     Color::LightWhite => "97",
                     Color::TrueColor { .. } => unreachable!(),
-                })?;
+                })?,
             }
         }
 
         // Background format
         if let Some(color) = self.bg {
-            if let Color::TrueColor { r, g, b } = color {
-                write!(f, "48;2;{};{};{}", r, g, b)?;
-            } else {
-                f.write_str(match color {
+            f.write_str(separator)?;
+            separator = ";";
+
+            match downgrade(color, level) {
+                Color::TrueColor { r, g, b } if level == ColorLevel::TrueColor => {
+                    write!(f, "48;2;{};{};{}", r, g, b)?
+                }
+                color @ Color::TrueColor { .. } => write!(f, "48;5;{}", color.nearest_256())?,
+                color => f.write_str(match color {
                     Color::Black => "40",
                     Color::Red => "41",
                     Color::Green => "42",
@@ -140,11 +515,32 @@ impl<'a> Display for EscapeSequence<'a> {
                     Color::LightCyan => "106",
                     Color::LightWhite => "107",
                     Color::TrueColor { .. } => unreachable!(),
-                })?;
+                })?,
+            }
+        }
+
+        // Underline color format
+        if let Some(color) = self.underline_color {
+            f.write_str(separator)?;
+            separator = ";";
+
+            match color {
+                Color::TrueColor { r, g, b } if level == ColorLevel::TrueColor => {
+                    write!(f, "58;2;{};{};{}", r, g, b)?
+                }
+                Color::TrueColor { .. } => write!(f, "58;5;{}", color.nearest_256())?,
+                named => {
+                    let index = NAMED_COLORS.iter().position(|&c| c == named).unwrap();
+
+                    write!(f, "58;5;{}", index)?
+                }
             }
         }
 
         for style in self.styles {
+            f.write_str(separator)?;
+            separator = ";";
+
             f.write_str(match style {
                 Style::Clear => "0",
                 Style::Bold => "1",
@@ -155,6 +551,17 @@ impl<'a> Display for EscapeSequence<'a> {
                 Style::Reversed => "7",
                 Style::Hidden => "8",
                 Style::Strikethrough => "9",
+                Style::DoubleUnderline => "21",
+                Style::Overline => "53",
+                Style::NotBold => "22",
+                Style::NotItalic => "23",
+                Style::NotUnderline => "24",
+                Style::NotBlink => "25",
+                Style::NotReversed => "27",
+                Style::NotHidden => "28",
+                Style::NotStrikethrough => "29",
+                Style::NotOverline => "55",
+                Style::NotUnderlineColor => "59",
             })?;
         }
 
@@ -162,9 +569,738 @@ impl<'a> Display for EscapeSequence<'a> {
     }
 }
 
+/// Downgrade a true color to a named color if the terminal only supports those
+#[cfg(not(feature = "no_color"))]
+fn downgrade(color: Color, level: ColorLevel) -> Color {
+    match color {
+        Color::TrueColor { .. } if level == ColorLevel::Basic => color.nearest_named(),
+        color => color,
+    }
+}
+
 #[cfg(feature = "no_color")]
 impl Display for EscapeSequence<'_> {
     fn fmt(&self, _: &mut Formatter<'_>) -> fmt::Result {
         Ok(())
     }
 }
+
+/// The escape sequence for text that has to grab attention, such as critical
+/// errors: blinking, bold and light red. End it with [`alert_end`]
+pub const fn alert() -> EscapeSequence<'static> {
+    EscapeSequence::new()
+        .set_fg(Color::LightRed)
+        .set_styles(&[Style::Blink, Style::Bold])
+}
+
+/// The escape sequence that ends an [`alert`], resetting all styling
+pub const fn alert_end() -> EscapeSequence<'static> {
+    EscapeSequence::new().set_styles(&[Style::Clear])
+}
+
+/// An escape sequence that shows or hides the terminal's cursor. To
+/// convert the structure to its string representation, use the
+/// Display implementation
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CursorVisibility {
+    visible: bool,
+}
+
+/// Create the escape sequence that hides the terminal's cursor, such as
+/// while drawing an animation. See [`HiddenCursor`] to show it again
+/// automatically.
+///
+/// As with [`EscapeSequence`], nothing is written out with [`ColorLevel::None`].
+pub const fn hide_cursor() -> CursorVisibility {
+    CursorVisibility { visible: false }
+}
+
+/// Create the escape sequence that shows the terminal's cursor
+pub const fn show_cursor() -> CursorVisibility {
+    CursorVisibility { visible: true }
+}
+
+#[cfg(not(feature = "no_color"))]
+impl Display for CursorVisibility {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if color_level() == ColorLevel::None {
+            return Ok(());
+        }
+
+        f.write_str(if self.visible {
+            "\u{1B}[?25h"
+        } else {
+            "\u{1B}[?25l"
+        })
+    }
+}
+
+#[cfg(feature = "no_color")]
+impl Display for CursorVisibility {
+    fn fmt(&self, _: &mut Formatter<'_>) -> fmt::Result {
+        Ok(())
+    }
+}
+
+/// Keeps the cursor of the terminal on the usb serial port hidden until it is
+/// dropped, so that returning early can not leave the cursor hidden
+pub struct HiddenCursor {
+    _private: (),
+}
+
+impl HiddenCursor {
+    /// Hide the cursor until the returned guard is dropped
+    pub fn hide() -> HiddenCursor {
+        write!(USBSerialWriter {}, "{}", hide_cursor()).ok();
+
+        HiddenCursor { _private: () }
+    }
+}
+
+impl Drop for HiddenCursor {
+    fn drop(&mut self) {
+        write!(USBSerialWriter {}, "{}", show_cursor()).ok();
+    }
+}
+
+/// A stack of escape sequences, for styling nested regions of text. Pushing
+/// a sequence applies it on top of the current style, and popping it restores
+/// the style from before it was pushed.
+///
+/// At most `N` sequences can be pushed at once.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StyleStack<'a, const N: usize> {
+    stack: [EscapeSequence<'a>; N],
+    depth: usize,
+}
+
+impl<'a, const N: usize> StyleStack<'a, N> {
+    /// Create a new, empty style stack
+    pub const fn new() -> Self {
+        Self {
+            stack: [EscapeSequence::new(); N],
+            depth: 0,
+        }
+    }
+
+    /// The amount of sequences currently pushed
+    pub const fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Push a sequence onto the stack, writing it out to apply it. Returns
+    /// an error without writing anything if the stack is full.
+    pub fn push(&mut self, f: &mut impl Write, sequence: EscapeSequence<'a>) -> fmt::Result {
+        if self.depth == N {
+            return Err(fmt::Error);
+        }
+
+        self.stack[self.depth] = sequence;
+        self.depth += 1;
+
+        write!(f, "{}", sequence)
+    }
+
+    /// Pop the last pushed sequence off of the stack, writing out the escapes to
+    /// restore the style from before it was pushed. Does nothing if the stack is empty.
+    pub fn pop(&mut self, f: &mut impl Write) -> fmt::Result {
+        if self.depth == 0 {
+            return Ok(());
+        }
+
+        self.depth -= 1;
+
+        // Clear all styling, then re-apply the sequences that are left
+        write!(f, "{}", EscapeSequence::new().set_styles(&[Style::Clear]))?;
+        for sequence in &self.stack[..self.depth] {
+            write!(f, "{}", sequence)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<const N: usize> Default for StyleStack<'_, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A control sequence (CSI), as sent by a terminal in response to a query, in the
+/// form `ESC [ <parameters> <intermediates> <final byte>`. See [`parse_csi`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ControlSequence<'a> {
+    parameters: &'a [u8],
+    intermediates: &'a [u8],
+    final_byte: u8,
+}
+
+impl<'a> ControlSequence<'a> {
+    /// The private marker at the start of the parameters, such as the `?` in `ESC [ ? 1 c`
+    pub fn private_marker(&self) -> Option<u8> {
+        self.parameters
+            .first()
+            .copied()
+            .filter(|byte| (b'<'..=b'?').contains(byte))
+    }
+
+    /// The numeric parameters, separated by `;`. Empty or unparsable parameters are `None`
+    pub fn params(&self) -> impl Iterator<Item = Option<u16>> + 'a {
+        let parameters = match self.parameters.first() {
+            Some(b'<'..=b'?') => &self.parameters[1..],
+            _ => self.parameters,
+        };
+
+        parameters
+            .split(|&byte| byte == b';')
+            // An empty parameter list has no parameters, rather than one empty one
+            .filter(move |_| !parameters.is_empty())
+            .map(|param| str::from_utf8(param).ok()?.parse().ok())
+    }
+
+    /// The intermediate bytes, between the parameters and the final byte
+    pub fn intermediates(&self) -> &'a [u8] {
+        self.intermediates
+    }
+
+    /// The final byte, which identifies the sequence
+    pub fn final_byte(&self) -> u8 {
+        self.final_byte
+    }
+}
+
+/// Parse a control sequence off of the start of the input, returning it along with the
+/// amount of bytes it took up. Returns `None` if the input does not start with a
+/// complete control sequence.
+pub fn parse_csi(input: &[u8]) -> Option<(ControlSequence<'_>, usize)> {
+    let body = input.strip_prefix(b"\x1B[")?;
+
+    // Parameter bytes are in 0x30..=0x3F, then intermediate bytes in 0x20..=0x2F
+    let parameters_end = body.iter().position(|byte| !(0x30..=0x3F).contains(byte))?;
+    let intermediates_end = parameters_end
+        + body[parameters_end..]
+            .iter()
+            .position(|byte| !(0x20..=0x2F).contains(byte))?;
+
+    let final_byte = body[intermediates_end];
+    if !(0x40..=0x7E).contains(&final_byte) {
+        return None;
+    }
+
+    Some((
+        ControlSequence {
+            parameters: &body[..parameters_end],
+            intermediates: &body[parameters_end..intermediates_end],
+            final_byte,
+        },
+        2 + intermediates_end + 1,
+    ))
+}
+
+/// Copy the input into the output without any escape sequences, such as for writing
+/// colored text into a log that can not display them. Returns the amount of bytes
+/// written, which is cut off at the end of a character if the output is too small.
+///
+/// Control sequences (`ESC [`, including all of the styling written out by
+/// [`EscapeSequence`]) are removed whole, as are two character escapes such as `ESC 7`,
+/// and operating system commands such as window titles and hyperlinks (`ESC ]`, ended
+/// by a `BEL` or `ESC \`). An unfinished sequence at the end of the input is removed too.
+pub fn strip(input: &str, out: &mut [u8]) -> usize {
+    let (text, input) = (input, input.as_bytes());
+    let mut read = 0usize;
+    let mut written = 0usize;
+
+    while read < input.len() {
+        let rest = &input[read..];
+
+        if rest[0] == 0x1B {
+            read += match (parse_csi(rest), osc_length(rest)) {
+                (Some((_, length)), _) | (None, Some(length)) => length,
+                _ if rest.starts_with(b"\x1B[") || rest.starts_with(b"\x1B]") => input.len() - read,
+                // The escape and the whole char after it, the escape being one byte
+                _ => 1 + text[read + 1..].chars().next().map_or(0, char::len_utf8),
+            };
+
+            continue;
+        }
+
+        // Copy the text up to the next escape, as much as fits
+        let text = &rest[..rest
+            .iter()
+            .position(|&byte| byte == 0x1B)
+            .unwrap_or(rest.len())];
+        let mut length = text.len().min(out.len() - written);
+        // Do not cut a character in half, continuation bytes are 0b10xxxxxx
+        while length < text.len() && text[length] & 0xC0 == 0x80 {
+            length -= 1;
+        }
+
+        out[written..written + length].copy_from_slice(&text[..length]);
+        written += length;
+        read += text.len();
+
+        if length < text.len() {
+            break;
+        }
+    }
+
+    written
+}
+
+/// The length of the operating system command (`ESC ] ... BEL` or `ESC ] ... ESC \`)
+/// at the start of the input, if it is complete
+fn osc_length(input: &[u8]) -> Option<usize> {
+    let body = input.strip_prefix(b"\x1B]")?;
+
+    body.iter()
+        .enumerate()
+        .find_map(|(index, &byte)| match byte {
+            0x07 => Some(index + 1),
+            0x1B if body.get(index + 1) == Some(&b'\\') => Some(index + 2),
+            _ => None,
+        })
+        .map(|length| 2 + length)
+}
+
+/// Ask the terminal for its device attributes (`ESC [ c`), and guess its level of color
+/// support from the response, then [`set_color_level`] to it. Waits up to `timeout_ms`
+/// milliseconds for the response, and assumes [`ColorLevel::Basic`] if none arrives, as
+/// with the Arduino serial monitor. Returns the level that was set.
+///
+/// The input buffer is cleared before the query is sent.
+///
+/// # Reliability
+/// The device attributes do not actually describe color support, so this is only a
+/// heuristic. Terminals claiming to be a VT500 series terminal or newer (which are all
+/// modern emulators) are assumed to support true color, and those either claiming to be
+/// a VT220 or newer, or to support ansi color, are assumed to support 256 colors. Many
+/// terminals misreport themselves, so let the user override the result where it matters.
+pub fn negotiate_color_level(timeout_ms: u32) -> ColorLevel {
+    let mut response = [0u8; 64];
+
+    let level = USBSerial::transaction(b"\x1B[c", &mut response, b'c', timeout_ms)
+        .ok()
+        .and_then(|length| {
+            // Skip anything before the start of the response
+            let start = response[..length].iter().position(|&byte| byte == 0x1B)?;
+            let (sequence, _) = parse_csi(&response[start..length])?;
+
+            if sequence.final_byte() != b'c' || sequence.private_marker() != Some(b'?') {
+                return None;
+            }
+
+            let mut params = sequence.params().flatten();
+            // The first parameter is the conformance level, the rest are the supported features
+            let class = params.next()?;
+            // Feature 22 is ansi color
+            let ansi_color = params.any(|feature| feature == 22);
+
+            Some(if class >= 65 {
+                ColorLevel::TrueColor
+            } else if class >= 62 || ansi_color {
+                ColorLevel::Ansi256
+            } else {
+                ColorLevel::Basic
+            })
+        })
+        .unwrap_or(ColorLevel::Basic);
+
+    set_color_level(level);
+
+    level
+}
+
+impl USBSerial {
+    /// Ask the terminal for its size, returning its rows and columns. The cursor is moved
+    /// as far into the bottom right corner as it can go, its position is requested
+    /// (`ESC [ 6 n`), and then it is moved back to where it was.
+    ///
+    /// Waits up to `timeout_ms` milliseconds for the response, returning `None` if it does
+    /// not arrive, as with terminals that do not understand escape sequences. The input
+    /// buffer is cleared before the query is sent.
+    ///
+    /// **Requires the feature `ansi`**
+    pub fn query_terminal_size(timeout_ms: u32) -> Option<(u16, u16)> {
+        let mut response = [0u8; 32];
+
+        let length = Self::transaction(
+            b"\x1B7\x1B[999;999H\x1B[6n\x1B8",
+            &mut response,
+            b'R',
+            timeout_ms,
+        )
+        .ok()?;
+
+        // Skip anything before the start of the response
+        let start = response[..length].iter().position(|&byte| byte == 0x1B)?;
+        let (sequence, _) = parse_csi(&response[start..length])?;
+
+        if sequence.final_byte() != b'R' {
+            return None;
+        }
+
+        let mut params = sequence.params();
+        let rows = params.next()??;
+        let columns = params.next()??;
+
+        Some((rows, columns))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    #[cfg(not(feature = "no_color"))]
+    fn popping_restores_the_outer_styles() {
+        use core::mem;
+
+        let _lock = test_support::lock();
+        let mut stack = StyleStack::<2>::new();
+        let mut out = String::new();
+
+        stack
+            .push(&mut out, EscapeSequence::new().set_fg(Color::Red))
+            .unwrap();
+        assert_eq!(mem::take(&mut out), "\u{1B}[31m");
+        stack
+            .push(&mut out, EscapeSequence::new().set_styles(&[Style::Bold]))
+            .unwrap();
+        assert_eq!(mem::take(&mut out), "\u{1B}[1m");
+
+        stack.pop(&mut out).unwrap();
+        assert_eq!(mem::take(&mut out), "\u{1B}[0m\u{1B}[31m");
+        stack.pop(&mut out).unwrap();
+        assert_eq!(mem::take(&mut out), "\u{1B}[0m");
+
+        // Nothing is left to pop
+        stack.pop(&mut out).unwrap();
+        assert_eq!(out, "");
+    }
+
+    #[test]
+    fn pushing_onto_a_full_stack_fails() {
+        let _lock = test_support::lock();
+        let mut stack = StyleStack::<1>::new();
+        let mut out = String::new();
+
+        assert!(stack.push(&mut out, alert()).is_ok());
+        out.clear();
+
+        assert!(stack.push(&mut out, alert_end()).is_err());
+        assert_eq!(out, "");
+        assert_eq!(stack.depth(), 1);
+    }
+
+    #[test]
+    #[cfg(not(feature = "no_color"))]
+    fn parameters_are_separated_by_semicolons() {
+        let _lock = test_support::lock();
+
+        assert_eq!(
+            std::format!(
+                "{}",
+                EscapeSequence::new()
+                    .set_fg(Color::Red)
+                    .set_styles(&[Style::Bold])
+            ),
+            "\u{1B}[31;1m"
+        );
+        assert_eq!(
+            std::format!(
+                "{}",
+                EscapeSequence::new().set_styles(&[
+                    Style::Bold,
+                    Style::DoubleUnderline,
+                    Style::Overline
+                ])
+            ),
+            "\u{1B}[1;21;53m"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "no_color"))]
+    fn styles_can_be_turned_off_one_at_a_time() {
+        let _lock = test_support::lock();
+
+        assert_eq!(
+            std::format!(
+                "{}",
+                EscapeSequence::new().set_fg(Color::Green).set_styles(&[
+                    Style::NotBold,
+                    Style::NotItalic,
+                    Style::NotUnderline,
+                    Style::NotBlink,
+                    Style::NotReversed,
+                    Style::NotHidden,
+                    Style::NotStrikethrough,
+                    Style::NotOverline,
+                ])
+            ),
+            "\u{1B}[32;22;23;24;25;27;28;29;55m"
+        );
+    }
+
+    #[test]
+    fn rgb_round_trips() {
+        assert_eq!(Color::rgb(1, 2, 3), Color::TrueColor { r: 1, g: 2, b: 3 });
+        assert_eq!(Color::rgb(1, 2, 3).as_rgb(), (1, 2, 3));
+        assert_eq!(Color::Blue.as_rgb(), (0, 0, 238));
+        assert_eq!(Color::LightBlack.as_rgb(), (127, 127, 127));
+
+        // Every named color is the nearest to its own components
+        for &color in &NAMED_COLORS {
+            let (r, g, b) = color.as_rgb();
+
+            assert_eq!(Color::rgb(r, g, b).nearest_named(), color);
+        }
+    }
+
+    #[test]
+    fn nearest_colors_are_found() {
+        assert_eq!(Color::rgb(250, 10, 10).nearest_named(), Color::LightRed);
+        assert_eq!(Color::rgb(190, 20, 10).nearest_named(), Color::Red);
+        assert_eq!(Color::rgb(20, 20, 20).nearest_named(), Color::Black);
+
+        assert_eq!(Color::rgb(0, 0, 0).nearest_256(), 16);
+        assert_eq!(Color::rgb(255, 0, 0).nearest_256(), 196);
+        assert_eq!(Color::rgb(255, 255, 255).nearest_256(), 231);
+        // Each component is rounded to the closest cube level
+        assert_eq!(Color::rgb(100, 130, 180).nearest_256(), 67);
+    }
+
+    #[test]
+    #[cfg(not(feature = "no_color"))]
+    fn cursor_is_left_alone_without_escapes() {
+        let _serial = test_support::serial();
+
+        assert_eq!(std::format!("{}", hide_cursor()), "\u{1B}[?25l");
+        drop(HiddenCursor::hide());
+        assert_eq!(test_support::sent(), b"\x1B[?25l\x1B[?25h");
+
+        set_color_level(ColorLevel::None);
+        let hidden = std::format!("{}", hide_cursor());
+        drop(HiddenCursor::hide());
+        set_color_level(ColorLevel::TrueColor);
+
+        assert_eq!(hidden, "");
+        assert_eq!(test_support::sent(), b"");
+    }
+
+    #[test]
+    fn palettes_resolve_roles() {
+        let roles = [Role::Primary, Role::Accent, Role::Error, Role::Muted];
+        let colors = |palette: Palette| roles.map(|role| palette.color(role));
+
+        assert_eq!(Palette::default(), Palette::DARK);
+        assert_eq!(
+            colors(Palette::DARK),
+            [
+                Color::LightBlue,
+                Color::LightYellow,
+                Color::LightRed,
+                Color::LightBlack
+            ]
+        );
+        // Swapping the palette changes the colors, but not what they are for
+        assert_eq!(
+            colors(Palette::LIGHT),
+            [Color::Blue, Color::Magenta, Color::Red, Color::LightBlack]
+        );
+        assert_eq!(
+            colors(Palette {
+                error: Color::rgb(255, 80, 0),
+                ..Palette::DARK
+            })[2],
+            Color::rgb(255, 80, 0)
+        );
+    }
+
+    #[test]
+    fn cursor_reports_are_parsed() {
+        let (sequence, length) = parse_csi(b"\x1B[24;80Rrest").unwrap();
+
+        assert_eq!(length, 8);
+        assert_eq!(sequence.final_byte(), b'R');
+        assert_eq!(sequence.private_marker(), None);
+        assert_eq!(
+            sequence.params().collect::<std::vec::Vec<_>>(),
+            [Some(24), Some(80)]
+        );
+    }
+
+    #[test]
+    fn broken_sequences_are_not_parsed() {
+        // Truncated
+        assert_eq!(parse_csi(b"\x1B[24;80"), None);
+        assert_eq!(parse_csi(b"\x1B["), None);
+        // Not a control sequence
+        assert_eq!(parse_csi(b"24;80R"), None);
+        assert_eq!(parse_csi(b"\x1B724;80R"), None);
+        // A control character where the final byte should be
+        assert_eq!(parse_csi(b"\x1B[24;80\n"), None);
+
+        // Empty and too large parameters
+        let (sequence, _) = parse_csi(b"\x1B[;99999R").unwrap();
+        assert_eq!(
+            sequence.params().collect::<std::vec::Vec<_>>(),
+            [None, None]
+        );
+    }
+
+    #[test]
+    fn terminal_size_is_read_from_the_response() {
+        let _serial = test_support::serial();
+
+        test_support::receive(b"left over");
+        test_support::reply(b"noise\x1B[24;80R");
+        assert_eq!(USBSerial::query_terminal_size(0), Some((24, 80)));
+        assert_eq!(test_support::sent(), b"\x1B7\x1B[999;999H\x1B[6n\x1B8");
+
+        // No response
+        assert_eq!(USBSerial::query_terminal_size(0), None);
+    }
+
+    #[test]
+    #[cfg(not(feature = "no_color"))]
+    fn alerts_are_bold_blinking_and_light_red() {
+        let _lock = test_support::lock();
+
+        assert_eq!(std::format!("{}", alert()), "\u{1B}[91;5;1m");
+        assert_eq!(std::format!("{}", alert_end()), "\u{1B}[0m");
+    }
+
+    #[test]
+    fn contrast_ratios_match_wcag() {
+        let ratio = |fg, bg| Color::contrast_ratio(fg, bg);
+        let (black, white) = (Color::rgb(0, 0, 0), Color::rgb(255, 255, 255));
+
+        assert!((ratio(black, white) - 21.0).abs() < 0.01);
+        assert_eq!(ratio(black, white), ratio(white, black));
+        assert!((ratio(Color::Blue, Color::Blue) - 1.0).abs() < 0.001);
+        // The lightest gray that passes on white
+        assert!((ratio(Color::rgb(118, 118, 118), white) - 4.54).abs() < 0.02);
+    }
+
+    #[test]
+    fn unreadable_colors_are_fixed_up() {
+        let (black, white) = (Color::rgb(0, 0, 0), Color::rgb(255, 255, 255));
+
+        assert_eq!(Color::ensure_readable(Color::Blue, white), Color::Blue);
+        assert_eq!(Color::ensure_readable(Color::LightYellow, white), black);
+        assert_eq!(Color::ensure_readable(Color::Blue, Color::Black), white);
+        assert!(
+            Color::contrast_ratio(
+                Color::ensure_readable(Color::Cyan, Color::Green),
+                Color::Green
+            ) >= MIN_CONTRAST_RATIO
+        );
+    }
+
+    #[test]
+    fn palette_indices_resolve_to_true_colors() {
+        let rgb = |index| Color::palette_to_truecolor(index).as_rgb();
+
+        // Named colors
+        assert_eq!(rgb(1), (205, 0, 0));
+        assert_eq!(rgb(15), (255, 255, 255));
+        // The color cube
+        assert_eq!(rgb(16), (0, 0, 0));
+        assert_eq!(rgb(17), (0, 0, 95));
+        assert_eq!(rgb(196), (255, 0, 0));
+        assert_eq!(rgb(231), (255, 255, 255));
+        // The grayscale ramp
+        assert_eq!(rgb(232), (8, 8, 8));
+        assert_eq!(rgb(255), (238, 238, 238));
+    }
+
+    #[test]
+    fn color_cube_round_trips() {
+        for index in 16..=231 {
+            assert_eq!(Color::palette_to_truecolor(index).nearest_256(), index);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "no_color"))]
+    fn underline_colors_are_written_out() {
+        let _lock = test_support::lock();
+        let underline = |color| {
+            std::format!(
+                "{}",
+                EscapeSequence::new()
+                    .set_underline_color(color)
+                    .set_styles(&[Style::Underline])
+            )
+        };
+
+        assert_eq!(underline(Color::rgb(1, 2, 3)), "\u{1B}[58;2;1;2;3;4m");
+        // Named colors by their index into the palette
+        assert_eq!(underline(Color::Red), "\u{1B}[58;5;1;4m");
+        assert_eq!(underline(Color::LightWhite), "\u{1B}[58;5;15;4m");
+
+        set_color_level(ColorLevel::Ansi256);
+        let downgraded = underline(Color::rgb(255, 0, 0));
+        set_color_level(ColorLevel::TrueColor);
+        assert_eq!(downgraded, "\u{1B}[58;5;196;4m");
+
+        assert_eq!(
+            std::format!(
+                "{}",
+                EscapeSequence::new().set_styles(&[Style::NotUnderlineColor])
+            ),
+            "\u{1B}[59m"
+        );
+    }
+
+    /// Strip the escapes out of the input into a new string
+    fn stripped(input: &str) -> String {
+        let mut out = std::vec![0u8; input.len()];
+        let length = strip(input, &mut out);
+        out.truncate(length);
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn escapes_are_stripped_from_the_text() {
+        assert_eq!(stripped("plain text, é"), "plain text, é");
+        assert_eq!(stripped("\x1B[1;31mred\x1B[0m text"), "red text");
+        // Window titles, ended either way
+        assert_eq!(stripped("\x1B]0;title\x07a\x1B]2;title\x1B\\b"), "ab");
+        // Two character escapes
+        assert_eq!(stripped("\x1B7saved\x1B8"), "saved");
+    }
+
+    #[test]
+    fn nested_escapes_are_stripped() {
+        // A hyperlink around styled text
+        assert_eq!(
+            stripped("see \x1B]8;;https://a.b\x1B\\\x1B[4mthe docs\x1B[24m\x1B]8;;\x1B\\!"),
+            "see the docs!"
+        );
+    }
+
+    #[test]
+    fn unfinished_escapes_are_stripped() {
+        assert_eq!(stripped("text\x1B[1;3"), "text");
+        assert_eq!(stripped("text\x1B]0;never ended"), "text");
+        assert_eq!(stripped("text\x1B"), "text");
+    }
+
+    #[test]
+    fn stripped_text_is_cut_off_at_a_char() {
+        let mut out = [0u8; 4];
+
+        assert_eq!(strip("\x1B[1mabé", &mut out), 4);
+        assert_eq!(strip("\x1B[1mabcé", &mut out), 3);
+        assert_eq!(&out[..3], b"abc");
+    }
+}
@@ -0,0 +1,227 @@
+//! Sending AT commands to a modem, such as an ESP or SIM module, and parsing
+//! the response
+//!
+//! A command is answered with any amount of lines of data, followed by a line
+//! with the final result: `OK`, `ERROR`, or an extended `+CME ERROR: <n>` or
+//! `+CMS ERROR: <n>`. Blank lines are ignored, as is an echo of the command
+//! in the first line, if the modem has echo enabled.
+
+use core::str;
+
+use crate::millis;
+
+use super::{spin_timed_out, Uart};
+
+/// The most bytes of data lines that a response can hold
+pub const AT_RESPONSE_SIZE: usize = 256;
+
+/// The lines of data that came before the `OK` of a successful command
+#[derive(Clone, Copy)]
+pub struct AtResponse {
+    data: [u8; AT_RESPONSE_SIZE],
+    len: usize,
+}
+
+impl AtResponse {
+    const fn new() -> Self {
+        AtResponse {
+            data: [0; AT_RESPONSE_SIZE],
+            len: 0,
+        }
+    }
+
+    /// The lines of data, without their line endings
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        // Only whole lines of valid UTF-8 are ever stored, each followed by a `\n`
+        let data = str::from_utf8(&self.data[..self.len]).unwrap_or_default();
+
+        data.split_terminator('\n')
+    }
+
+    /// Check if the response held no lines of data
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn push(&mut self, line: &str) -> Result<(), AtError> {
+        let end = self.len + line.len() + 1;
+        let slot = self.data.get_mut(self.len..end).ok_or(AtError::Overflow)?;
+
+        slot[..line.len()].copy_from_slice(line.as_bytes());
+        slot[line.len()] = b'\n';
+        self.len = end;
+
+        Ok(())
+    }
+}
+
+/// An error encountered while sending an AT command
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AtError {
+    /// The command could not be written out before the timeout
+    WriteFailed,
+    /// No final result arrived before the timeout
+    Timeout,
+    /// The modem answered with `ERROR`
+    Error,
+    /// The modem answered with `+CME ERROR: <n>`, an equipment error
+    CmeError(u16),
+    /// The modem answered with `+CMS ERROR: <n>`, a messaging error
+    CmsError(u16),
+    /// The lines of data did not fit in [`AT_RESPONSE_SIZE`] bytes, or one line
+    /// did not fit in [`AT_RESPONSE_SIZE`] bytes or was not valid UTF-8
+    Overflow,
+}
+
+/// Send `AT+<cmd>\r\n` to the modem (or just `AT\r\n` if the command is empty), then read
+/// the lines of the response until the final result, with the whole exchange taking at most
+/// `timeout_ms` milliseconds. Returns the lines of data if the final result is `OK`.
+///
+/// ```ignore
+/// let response = at::command(&mut uart, "CWJAP?", 1000)?;
+/// for line in response.lines() {
+///     writeln!(USBSerialWriter {}, "{}", line)?;
+/// }
+/// ```
+pub fn command(port: &mut impl Uart, cmd: &str, timeout_ms: u32) -> Result<AtResponse, AtError> {
+    // The start time, for timeout
+    let start_millis = millis();
+
+    let prefix: &[u8] = if cmd.is_empty() { b"AT" } else { b"AT+" };
+    for part in [prefix, cmd.as_bytes(), b"\r\n"] {
+        let mut written = 0usize;
+
+        while written < part.len() {
+            written += port.write(&part[written..]);
+
+            if written < part.len() && spin_timed_out(start_millis, timeout_ms) {
+                return Err(AtError::WriteFailed);
+            }
+        }
+    }
+
+    let mut response = AtResponse::new();
+    let mut line = [0u8; AT_RESPONSE_SIZE];
+    // The current count of read in bytes of the line
+    let mut count = 0usize;
+    // Only the first line can be the echo, so data lines that start with `AT` are kept
+    let mut first_line = true;
+
+    loop {
+        let mut byte = [0u8];
+
+        if port.read(&mut byte) == 0 {
+            if spin_timed_out(start_millis, timeout_ms) {
+                return Err(AtError::Timeout);
+            }
+
+            continue;
+        }
+
+        match byte[0] {
+            b'\n' => {
+                let text = str::from_utf8(&line[..count]).map_err(|_| AtError::Overflow)?;
+                let text = text.trim_end_matches('\r');
+                count = 0;
+
+                if text.is_empty() {
+                    continue;
+                }
+
+                let echo = first_line && is_echo(text, prefix, cmd);
+                first_line = false;
+
+                match text {
+                    "OK" => return Ok(response),
+                    "ERROR" => return Err(AtError::Error),
+                    _ if echo => {}
+                    text => {
+                        if let Some(code) = text.strip_prefix("+CME ERROR:") {
+                            return Err(AtError::CmeError(code.trim().parse().unwrap_or(0)));
+                        }
+                        if let Some(code) = text.strip_prefix("+CMS ERROR:") {
+                            return Err(AtError::CmsError(code.trim().parse().unwrap_or(0)));
+                        }
+
+                        response.push(text)?;
+                    }
+                }
+            }
+            byte => {
+                let slot = line.get_mut(count).ok_or(AtError::Overflow)?;
+                *slot = byte;
+                count += 1;
+            }
+        }
+    }
+}
+
+/// Check if the line is the echo of the command, as sent
+fn is_echo(line: &str, prefix: &[u8], cmd: &str) -> bool {
+    line.as_bytes().strip_prefix(prefix) == Some(cmd.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::test_support::MockUart;
+
+    /// Send the command to a modem that answers with the response
+    fn answer(cmd: &str, response: &[u8]) -> (Result<AtResponse, AtError>, MockUart) {
+        let mut uart = MockUart::new(response, 64);
+
+        (command(&mut uart, cmd, 0), uart)
+    }
+
+    #[test]
+    fn data_lines_come_before_ok() {
+        // The echo keeps the `\r` the command was sent with
+        let modem = b"AT+CWJAP?\r\r\n+CWJAP:\"home\"\r\n\r\nOK\r\n";
+        let (response, uart) = answer("CWJAP?", modem);
+
+        assert_eq!(uart.outgoing, b"AT+CWJAP?\r\n");
+        let response = response.unwrap();
+        assert_eq!(response.lines().collect::<Vec<_>>(), ["+CWJAP:\"home\""]);
+    }
+
+    #[test]
+    fn only_the_echo_is_dropped() {
+        // Without echo, a data line that starts with `AT` is kept
+        let (response, _) = answer("", b"AT version 1.0\r\nOK\r\n");
+        assert_eq!(
+            response.unwrap().lines().collect::<Vec<_>>(),
+            ["AT version 1.0"]
+        );
+
+        let (response, uart) = answer("", b"AT\r\nOK\r\n");
+        assert_eq!(uart.outgoing, b"AT\r\n");
+        assert!(response.unwrap().is_empty());
+    }
+
+    #[test]
+    fn errors_are_returned() {
+        assert_eq!(answer("X", b"ERROR\r\n").0.err(), Some(AtError::Error));
+        assert_eq!(
+            answer("X", b"AT+X\r\n+CME ERROR: 10\r\n").0.err(),
+            Some(AtError::CmeError(10))
+        );
+        assert_eq!(
+            answer("X", b"+CMS ERROR: 304\r\n").0.err(),
+            Some(AtError::CmsError(304))
+        );
+    }
+
+    #[test]
+    fn missing_results_time_out() {
+        assert_eq!(answer("X", b"").0.err(), Some(AtError::Timeout));
+        // A line without its ending
+        assert_eq!(answer("X", b"OK").0.err(), Some(AtError::Timeout));
+
+        let mut uart = MockUart::new(b"OK\r\n", 2);
+        assert_eq!(command(&mut uart, "X", 0).err(), Some(AtError::WriteFailed));
+    }
+}
@@ -0,0 +1,83 @@
+//! Guessing the baud rate of a serial line from the timing of its signal
+
+use crate::{gpio::Pin, micros, millis};
+
+use super::timed_out;
+
+/// The baud rates that [`auto_baud`] can detect
+pub const BAUD_CANDIDATES: [u32; 10] = [
+    300, 600, 1200, 2400, 4800, 9600, 19200, 38400, 57600, 115_200,
+];
+
+/// The amount of pulses that are timed to make a guess
+const PULSES: usize = 32;
+
+/// Guess the baud rate of the serial line connected to the `rx` pin, by timing the
+/// pulses on it for up to `window_ms` milliseconds. The pin should already be set
+/// up as an input.
+///
+/// This assumes that the other device is repeatedly sending the sync byte `0x55`
+/// (`U`) with 8 data bits and 1 stop bit. Every bit of that byte differs from the
+/// last, so the shortest pulses on the line are exactly one bit long. Only the
+/// rates in [`BAUD_CANDIDATES`] are detected.
+///
+/// Returns `None` if no consistent bit timing was seen within the window, or if it
+/// did not come within 10% of a candidate baud rate.
+///
+/// The timing is done with [`micros`], so rates much above 115200 baud
+/// can not be measured reliably.
+pub fn auto_baud(rx: &Pin, window_ms: u32) -> Option<u32> {
+    let mut widths = [0u32; PULSES];
+    let mut count = 0usize;
+
+    let start_millis = millis();
+    let mut level = rx.digital_read();
+    let mut edge_micros = None;
+
+    while count < PULSES && !timed_out(start_millis, window_ms) {
+        let sample = rx.digital_read();
+
+        if sample != level {
+            let now = micros();
+
+            // The first edge only starts the first pulse
+            if let Some(edge) = edge_micros {
+                widths[count] = now.wrapping_sub(edge);
+                count += 1;
+            }
+
+            edge_micros = Some(now);
+            level = sample;
+        }
+    }
+
+    baud_from_widths(&widths[..count])
+}
+
+/// Guess the baud rate from a set of pulse widths in microseconds
+fn baud_from_widths(widths: &[u32]) -> Option<u32> {
+    // A single sync byte has 9 single bit pulses, so wait to see at least one
+    if widths.len() < 9 {
+        return None;
+    }
+
+    let bit_width = widths.iter().copied().filter(|&width| width > 0).min()?;
+
+    // Most of the pulses of a stream of sync bytes are one bit wide, so
+    // require that at least half are within 25% of the shortest one
+    let single_bits = widths
+        .iter()
+        .filter(|&&width| width <= bit_width + bit_width / 4)
+        .count();
+    if single_bits * 2 < widths.len() {
+        return None;
+    }
+
+    let measured = 1_000_000 / bit_width;
+
+    BAUD_CANDIDATES
+        .iter()
+        .copied()
+        .min_by_key(|&baud| (baud as i64 - measured as i64).abs())
+        .filter(|&baud| (baud as i64 - measured as i64).abs() * 10 <= baud as i64)
+}
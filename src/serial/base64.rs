@@ -0,0 +1,268 @@
+//! Base64, for sending binary data (such as firmware or certificates) through a
+//! serial monitor that only handles text
+//!
+//! The standard alphabet is used, with `+` and `/`, and `=` padding.
+
+use core::fmt::{self, Write};
+
+use super::SerialReader;
+
+/// The characters that each 6 bit value is encoded as
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// An error encountered while decoding base64
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Base64Error {
+    /// A character that is not part of base64 was read in
+    InvalidChar(u8),
+    /// The stream ended, or was padded, part way through a byte
+    Truncated,
+    /// The decoded bytes did not fit in the output
+    Overflow,
+}
+
+/// The 6 bit value of a base64 character
+fn value(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+/// Decode base64 text read in from the reader into the output, returning the
+/// amount of decoded bytes. Spaces and line breaks within the text are skipped,
+/// and the padding is optional.
+///
+/// The text ends at its padding, at an empty line, or when no character arrives
+/// before the timeout of the reader. The characters are not kept in the buffer of
+/// the reader, so the text can be longer than it.
+///
+/// ```ignore
+/// let mut firmware = [0u8; 1024];
+/// let length = SERIAL::with_reader(|reader| base64::decode_stream(reader, &mut firmware))?;
+/// ```
+pub fn decode_stream(reader: &mut SerialReader, out: &mut [u8]) -> Result<usize, Base64Error> {
+    let mut count = 0usize;
+    // The bits decoded but not yet written out, and how many of them there are
+    let mut bits = 0u32;
+    let mut bit_count = 0u32;
+    // The amount of base64 characters read in since the last line break, for finding
+    // empty lines
+    let mut line_length = 0usize;
+    let mut started = false;
+
+    while let Some(c) = reader.next_byte() {
+        match c {
+            b'\n' => {
+                if started && line_length == 0 {
+                    break;
+                }
+                line_length = 0;
+            }
+            b'\r' | b' ' | b'\t' => {}
+            b'=' => {
+                // Padding fills out the last 4 characters, after 2 or 3 of them, which
+                // leave 4 or 2 bits over
+                return match bit_count {
+                    // `xx==`, with a second padding character to skip
+                    4 if reader.next_byte() == Some(b'=') => Ok(count),
+                    2 => Ok(count),
+                    _ => Err(Base64Error::Truncated),
+                };
+            }
+            c => {
+                let value = value(c).ok_or(Base64Error::InvalidChar(c))?;
+
+                started = true;
+                line_length += 1;
+                bits = bits << 6 | u32::from(value);
+                bit_count += 6;
+
+                if bit_count >= 8 {
+                    bit_count -= 8;
+
+                    *out.get_mut(count).ok_or(Base64Error::Overflow)? = (bits >> bit_count) as u8;
+                    count += 1;
+                    bits &= (1 << bit_count) - 1;
+                }
+            }
+        }
+    }
+
+    // Without padding, 1 leftover character can not make a byte
+    if bit_count == 6 {
+        return Err(Base64Error::Truncated);
+    }
+
+    Ok(count)
+}
+
+/// Encode the input as base64 text, with padding, into the writer
+pub fn encode(input: &[u8], writer: &mut impl Write) -> fmt::Result {
+    let mut encoder = Encoder::new(writer);
+    encoder.write(input)?;
+    encoder.finish()?;
+
+    Ok(())
+}
+
+/// A streaming base64 encoder, for encoding data that comes in pieces, such as
+/// a file read out in chunks. Nothing is written out for leftover bytes until
+/// they make up 3 bytes, or the encoder is finished.
+pub struct Encoder<W: Write> {
+    writer: W,
+    pending: [u8; 3],
+    pending_len: usize,
+}
+
+impl<W: Write> Encoder<W> {
+    /// Create an encoder that writes the base64 text into the writer
+    pub fn new(writer: W) -> Self {
+        Encoder {
+            writer,
+            pending: [0; 3],
+            pending_len: 0,
+        }
+    }
+
+    /// Encode the next piece of the data
+    pub fn write(&mut self, bytes: &[u8]) -> fmt::Result {
+        for &byte in bytes {
+            self.pending[self.pending_len] = byte;
+            self.pending_len += 1;
+
+            if self.pending_len == 3 {
+                self.write_pending()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Encode the leftover bytes with padding, and give back the writer
+    pub fn finish(mut self) -> Result<W, fmt::Error> {
+        if self.pending_len > 0 {
+            self.write_pending()?;
+        }
+
+        Ok(self.writer)
+    }
+
+    /// Write out the leftover bytes, padded if there are less than 3 of them
+    fn write_pending(&mut self) -> fmt::Result {
+        let [a, b, c] = self.pending;
+        let length = self.pending_len;
+        let b = if length > 1 { b } else { 0 };
+        let c = if length > 2 { c } else { 0 };
+        let bits = u32::from(a) << 16 | u32::from(b) << 8 | u32::from(c);
+
+        for i in 0..4 {
+            let character = if i <= length {
+                ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize]
+            } else {
+                b'='
+            };
+
+            self.writer.write_char(character as char)?;
+        }
+
+        self.pending_len = 0;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+
+    use super::*;
+    use crate::{serial::USBSerial, test_support};
+
+    /// The test vectors of RFC 4648, as data and its encoding
+    const VECTORS: [(&str, &str); 7] = [
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("fooba", "Zm9vYmE="),
+        ("foobar", "Zm9vYmFy"),
+    ];
+
+    /// Decode the base64 text as if it was received
+    fn decode(text: &[u8], out: &mut [u8]) -> Result<usize, Base64Error> {
+        test_support::receive(text);
+
+        USBSerial::with_reader(|reader| decode_stream(reader, out))
+    }
+
+    #[test]
+    fn rfc_vectors_are_encoded() {
+        for (data, encoded) in VECTORS {
+            let mut out = String::new();
+            encode(data.as_bytes(), &mut out).unwrap();
+
+            assert_eq!(out, encoded);
+        }
+    }
+
+    #[test]
+    fn rfc_vectors_are_decoded() {
+        let _serial = test_support::serial();
+
+        for (data, encoded) in VECTORS {
+            let mut out = [0u8; 8];
+            // Ended by the padding, an empty line, or the timeout
+            for text in [encoded, &std::format!("{}\r\n\r\n", encoded)] {
+                assert_eq!(decode(text.as_bytes(), &mut out), Ok(data.len()));
+                assert_eq!(&out[..data.len()], data.as_bytes());
+                USBSerial::clear();
+            }
+        }
+
+        // Without the padding
+        let mut out = [0u8; 8];
+        assert_eq!(decode(b"Zm9vYg", &mut out), Ok(4));
+        assert_eq!(&out[..4], b"foob");
+
+        // Split across lines, in the middle of a byte
+        assert_eq!(decode(b"Zm9vY\r\nm Fy\n", &mut out), Ok(6));
+        assert_eq!(&out[..6], b"foobar");
+    }
+
+    #[test]
+    fn pieces_are_encoded_across_chunks() {
+        let mut encoder = Encoder::new(String::new());
+
+        // Split up so that no piece is a whole group of 3 bytes
+        for piece in ["f", "oob", "ar", "", "!"] {
+            encoder.write(piece.as_bytes()).unwrap();
+        }
+        assert_eq!(encoder.finish().unwrap(), "Zm9vYmFyIQ==");
+    }
+
+    #[test]
+    fn broken_text_is_not_decoded() {
+        let _serial = test_support::serial();
+        let mut out = [0u8; 8];
+
+        assert_eq!(
+            decode(b"Zm9v*", &mut out),
+            Err(Base64Error::InvalidChar(b'*'))
+        );
+        USBSerial::clear();
+        assert_eq!(decode(b"Zm9vY", &mut out), Err(Base64Error::Truncated));
+        assert_eq!(decode(b"Z=", &mut out), Err(Base64Error::Truncated));
+        assert_eq!(
+            decode(b"Zm9vYmFy", &mut out[..5]),
+            Err(Base64Error::Overflow)
+        );
+    }
+}
@@ -0,0 +1,293 @@
+//! Helpers for binary serial protocols
+
+use super::USBSerial;
+
+/// The order that the bytes of multi-byte numbers are sent in
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Endian {
+    /// Most significant byte first, also known as network order (default)
+    #[default]
+    Big,
+    /// Least significant byte first, as used natively by most processors
+    Little,
+}
+
+/// An error encountered while reading in a length prefixed frame
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FrameError {
+    /// The length prefix did not arrive before the timeout
+    Timeout,
+    /// The frame is longer than the buffer. Holds the length from the prefix.
+    /// The payload is left unread in the serial buffer.
+    Oversized(usize),
+    /// The payload did not arrive in full before the timeout. Holds the amount
+    /// of bytes of it that did arrive.
+    Truncated(usize),
+}
+
+/// A view of the usb serial port that deals only in raw bytes, for binary protocols.
+/// No text decoding ever happens through it, unlike with the string and char
+/// functions of [`USBSerial`]. Get one with [`USBSerial::binary`].
+#[derive(Clone, Copy, Debug)]
+pub struct BinarySerial {
+    _private: (),
+}
+
+impl BinarySerial {
+    /// Read in the bytes that have already arrived, without waiting, returning the
+    /// amount read in. See [`USBSerial::read_bytes`]
+    pub fn read(&self, buffer: &mut [u8]) -> usize {
+        USBSerial::read_bytes(buffer)
+    }
+
+    /// Fill the buffer, waiting for the duration of the serial timeout. Returns `Err` with the
+    /// amount of bytes read in if it did not fill. See [`USBSerial::read_bytes_exact_timeout`]
+    pub fn read_exact(&self, buffer: &mut [u8]) -> Result<(), usize> {
+        USBSerial::read_bytes_exact_timeout(buffer)
+    }
+
+    /// Write out the bytes, returning the amount of bytes successfully written
+    /// out. See [`USBSerial::write_bytes`]
+    pub fn write(&self, buffer: &[u8]) -> usize {
+        USBSerial::write_bytes(buffer)
+    }
+
+    /// The amount of bytes that are available for reading. See [`USBSerial::avaliable`]
+    pub fn available(&self) -> usize {
+        USBSerial::avaliable()
+    }
+}
+
+impl USBSerial {
+    /// Get a view of the serial port that only reads and writes raw bytes, to keep
+    /// binary protocols clearly apart from text
+    pub fn binary() -> BinarySerial {
+        BinarySerial { _private: () }
+    }
+
+    /// Read in a frame made of a 2 byte big endian length, followed by that many bytes
+    /// of payload, which is read into the buffer. Both parts are read with the serial
+    /// timeout. On success, returns the length of the payload.
+    ///
+    /// See [`read_length_prefixed_endian`](USBSerial::read_length_prefixed_endian)
+    /// for a little endian length.
+    pub fn read_length_prefixed(buffer: &mut [u8]) -> Result<usize, FrameError> {
+        Self::read_length_prefixed_endian(buffer, Endian::Big)
+    }
+
+    /// Read in a length prefixed frame, as with [`read_length_prefixed`], with the
+    /// length sent in the provided byte order
+    ///
+    /// [`read_length_prefixed`]: USBSerial::read_length_prefixed
+    pub fn read_length_prefixed_endian(
+        buffer: &mut [u8],
+        endian: Endian,
+    ) -> Result<usize, FrameError> {
+        let length = usize::from(Self::read_u16(endian).ok_or(FrameError::Timeout)?);
+        let payload = buffer
+            .get_mut(..length)
+            .ok_or(FrameError::Oversized(length))?;
+
+        Self::read_bytes_exact_timeout(payload).map_err(FrameError::Truncated)?;
+
+        Ok(length)
+    }
+
+    /// Read in a `u16` sent in the provided byte order, waiting for the duration of
+    /// the timeout. Returns `None` if it did not arrive in full.
+    pub fn read_u16(endian: Endian) -> Option<u16> {
+        let mut bytes = [0u8; 2];
+        Self::read_bytes_exact_timeout(&mut bytes).ok()?;
+
+        Some(match endian {
+            Endian::Big => u16::from_be_bytes(bytes),
+            Endian::Little => u16::from_le_bytes(bytes),
+        })
+    }
+
+    /// Read in a `u32` sent in the provided byte order, waiting for the duration of
+    /// the timeout. Returns `None` if it did not arrive in full.
+    pub fn read_u32(endian: Endian) -> Option<u32> {
+        let mut bytes = [0u8; 4];
+        Self::read_bytes_exact_timeout(&mut bytes).ok()?;
+
+        Some(match endian {
+            Endian::Big => u32::from_be_bytes(bytes),
+            Endian::Little => u32::from_le_bytes(bytes),
+        })
+    }
+
+    /// Write out a `u16` in the provided byte order, returning the amount of bytes
+    /// successfully written out
+    pub fn write_u16(value: u16, endian: Endian) -> usize {
+        Self::write_bytes(&match endian {
+            Endian::Big => value.to_be_bytes(),
+            Endian::Little => value.to_le_bytes(),
+        })
+    }
+
+    /// Write out a `u32` in the provided byte order, returning the amount of bytes
+    /// successfully written out
+    pub fn write_u32(value: u32, endian: Endian) -> usize {
+        Self::write_bytes(&match endian {
+            Endian::Big => value.to_be_bytes(),
+            Endian::Little => value.to_le_bytes(),
+        })
+    }
+
+    /// Read in a `u64` sent in the provided byte order, waiting for the duration of
+    /// the timeout. Returns `None` if it did not arrive in full.
+    pub fn read_u64(endian: Endian) -> Option<u64> {
+        let mut bytes = [0u8; 8];
+        Self::read_bytes_exact_timeout(&mut bytes).ok()?;
+
+        Some(match endian {
+            Endian::Big => u64::from_be_bytes(bytes),
+            Endian::Little => u64::from_le_bytes(bytes),
+        })
+    }
+
+    /// Write out a `u64` in the provided byte order, returning the amount of bytes
+    /// successfully written out
+    pub fn write_u64(value: u64, endian: Endian) -> usize {
+        Self::write_bytes(&match endian {
+            Endian::Big => value.to_be_bytes(),
+            Endian::Little => value.to_le_bytes(),
+        })
+    }
+
+    /// Read in the raw bits of an `f32` sent in the provided byte order, waiting for
+    /// the duration of the timeout. Returns `None` if it did not arrive in full.
+    pub fn read_f32(endian: Endian) -> Option<f32> {
+        Self::read_u32(endian).map(f32::from_bits)
+    }
+
+    /// Write out the raw bits of an `f32` in the provided byte order, returning the
+    /// amount of bytes successfully written out
+    pub fn write_f32(value: f32, endian: Endian) -> usize {
+        Self::write_u32(value.to_bits(), endian)
+    }
+
+    /// Read in the raw bits of an `f64` sent in the provided byte order, waiting for
+    /// the duration of the timeout. Returns `None` if it did not arrive in full.
+    pub fn read_f64(endian: Endian) -> Option<f64> {
+        Self::read_u64(endian).map(f64::from_bits)
+    }
+
+    /// Write out the raw bits of an `f64` in the provided byte order, returning the
+    /// amount of bytes successfully written out
+    pub fn write_f64(value: f64, endian: Endian) -> usize {
+        Self::write_u64(value.to_bits(), endian)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn frames_round_trip() {
+        let _serial = test_support::serial();
+        let binary = USBSerial::binary();
+
+        USBSerial::write_u16(5, Endian::Big);
+        binary.write(b"hello");
+        assert_eq!(test_support::sent(), b"\0\x05hello");
+
+        test_support::receive(b"\0\x05hello");
+        let mut buffer = [0u8; 8];
+        assert_eq!(USBSerial::read_length_prefixed(&mut buffer), Ok(5));
+        assert_eq!(&buffer[..5], b"hello");
+    }
+
+    #[test]
+    fn oversized_frames_are_left_unread() {
+        let _serial = test_support::serial();
+        test_support::receive(b"\0\x09too long!");
+
+        let mut buffer = [0u8; 8];
+        assert_eq!(
+            USBSerial::read_length_prefixed(&mut buffer),
+            Err(FrameError::Oversized(9))
+        );
+        assert_eq!(USBSerial::avaliable(), 9);
+    }
+
+    #[test]
+    fn short_frames_are_truncated() {
+        let _serial = test_support::serial();
+        let mut buffer = [0u8; 8];
+
+        test_support::receive(b"\0");
+        assert_eq!(
+            USBSerial::read_length_prefixed(&mut buffer),
+            Err(FrameError::Timeout)
+        );
+
+        test_support::receive(b"\0\x05hel");
+        assert_eq!(
+            USBSerial::read_length_prefixed(&mut buffer),
+            Err(FrameError::Truncated(3))
+        );
+    }
+
+    #[test]
+    fn numbers_are_sent_in_either_order() {
+        let _serial = test_support::serial();
+
+        assert_eq!(USBSerial::write_u32(0x0102_0304, Endian::Big), 4);
+        assert_eq!(USBSerial::write_u32(0x0102_0304, Endian::Little), 4);
+        assert_eq!(test_support::sent(), b"\x01\x02\x03\x04\x04\x03\x02\x01");
+
+        test_support::receive(b"\x01\x02\x03\x04\x01\x02\x03\x04");
+        assert_eq!(USBSerial::read_u32(Endian::Big), Some(0x0102_0304));
+        assert_eq!(USBSerial::read_u32(Endian::Little), Some(0x0403_0201));
+        assert_eq!(USBSerial::read_u32(Endian::Little), None);
+
+        // The length prefix can be little endian too
+        test_support::receive(b"\x02\0hi");
+        let mut buffer = [0u8; 2];
+        assert_eq!(
+            USBSerial::read_length_prefixed_endian(&mut buffer, Endian::Little),
+            Ok(2)
+        );
+        assert_eq!(Endian::default(), Endian::Big);
+    }
+
+    #[test]
+    fn floats_round_trip_bit_for_bit() {
+        let _serial = test_support::serial();
+        let values = [1.5, -0.0, f64::NAN, f64::INFINITY, f64::NEG_INFINITY];
+
+        for endian in [Endian::Big, Endian::Little] {
+            for &value in &values {
+                assert_eq!(USBSerial::write_f32(value as f32, endian), 4);
+                assert_eq!(USBSerial::write_f64(value, endian), 8);
+            }
+
+            test_support::receive(&test_support::sent());
+            for &value in &values {
+                let single = USBSerial::read_f32(endian).unwrap();
+                let double = USBSerial::read_f64(endian).unwrap();
+
+                // Comparing the bits, as NaN is not equal to itself and -0.0 is equal to 0.0
+                assert_eq!(single.to_bits(), (value as f32).to_bits());
+                assert_eq!(double.to_bits(), value.to_bits());
+            }
+        }
+    }
+
+    #[test]
+    fn floats_are_sent_as_their_bits() {
+        let _serial = test_support::serial();
+
+        USBSerial::write_f32(-0.0, Endian::Big);
+        USBSerial::write_f32(1.0, Endian::Little);
+        assert_eq!(test_support::sent(), b"\x80\0\0\0\0\0\x80\x3F");
+
+        // Only part of one arrived
+        test_support::receive(b"\0\0\x80");
+        assert_eq!(USBSerial::read_f32(Endian::Little), None);
+    }
+}
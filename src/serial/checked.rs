@@ -0,0 +1,153 @@
+//! Lines of text carrying a checksum, in the form `payload*1A2B`
+//!
+//! The checksum is the [CRC-16](super::crc16) of the payload, written as
+//! four uppercase hex digits.
+
+use core::str;
+
+use super::{crc16, LineError, USBSerial};
+
+/// An error encountered while reading in a checked line
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChecksumError {
+    /// The line could not be read in
+    Line(LineError),
+    /// The line did not end with a `*` and four hex digits
+    MissingChecksum,
+    /// The checksum did not match the payload, which was corrupted
+    Mismatch {
+        /// The checksum that came with the line
+        received: u16,
+        /// The checksum of the payload as received
+        computed: u16,
+    },
+}
+
+impl From<LineError> for ChecksumError {
+    fn from(err: LineError) -> Self {
+        ChecksumError::Line(err)
+    }
+}
+
+/// The hex digits
+const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+/// Split a checked line into its payload, verifying the checksum
+pub fn parse_checked_line(line: &str) -> Result<&str, ChecksumError> {
+    let split = line
+        .len()
+        .checked_sub(5)
+        .filter(|&split| line.as_bytes()[split] == b'*' && line.is_char_boundary(split))
+        .ok_or(ChecksumError::MissingChecksum)?;
+
+    let (payload, checksum) = (&line[..split], &line[split + 1..]);
+    // from_str_radix would also take a sign in place of a digit
+    if !checksum.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(ChecksumError::MissingChecksum);
+    }
+    let received = u16::from_str_radix(checksum, 16).map_err(|_| ChecksumError::MissingChecksum)?;
+    let computed = crc16::checksum(payload.as_bytes());
+
+    if received == computed {
+        Ok(payload)
+    } else {
+        Err(ChecksumError::Mismatch { received, computed })
+    }
+}
+
+impl USBSerial {
+    /// Write out a line with a checksum, followed by a `\r\n`, returning the amount
+    /// of bytes successfully written out. The other end can verify the line with
+    /// [`parse_checked_line`], or its own equivalent.
+    ///
+    /// The payload is written out as is, without [translating its line
+    /// endings](USBSerial::set_crlf_translation), so that the checksum matches it.
+    pub fn write_checked_line(payload: &str) -> usize {
+        let crc = crc16::checksum(payload.as_bytes());
+        let trailer = [
+            b'*',
+            HEX[usize::from(crc >> 12)],
+            HEX[usize::from(crc >> 8 & 0xF)],
+            HEX[usize::from(crc >> 4 & 0xF)],
+            HEX[usize::from(crc & 0xF)],
+            b'\r',
+            b'\n',
+        ];
+
+        Self::write_bytes(payload.as_bytes()) + Self::write_bytes(&trailer)
+    }
+
+    /// Read in a line with a checksum, as with [`read_line`](USBSerial::read_line),
+    /// returning its payload if the checksum matches
+    pub fn read_checked_line(buffer: &mut [u8]) -> Result<Option<&str>, ChecksumError> {
+        match Self::read_line(buffer)? {
+            Some(line) => parse_checked_line(line).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn checksum_is_appended() {
+        let _serial = test_support::serial();
+
+        assert_eq!(USBSerial::write_checked_line("123456789"), 16);
+        assert_eq!(test_support::sent(), b"123456789*29B1\r\n");
+    }
+
+    #[test]
+    fn written_lines_read_back_in() {
+        let _serial = test_support::serial();
+        // The payload is checksummed as is, so its line endings are left alone
+        USBSerial::set_crlf_translation(true);
+
+        USBSerial::write_checked_line("speed=12\ntemp=40");
+        let sent = test_support::sent();
+        assert!(sent.starts_with(b"speed=12\ntemp=40*"));
+
+        let line = core::str::from_utf8(&sent[..sent.len() - 2]).unwrap();
+        assert_eq!(parse_checked_line(line), Ok("speed=12\ntemp=40"));
+
+        USBSerial::write_checked_line("speed=12");
+        test_support::receive(&test_support::sent());
+        let mut buffer = [0u8; 32];
+        assert_eq!(
+            USBSerial::read_checked_line(&mut buffer),
+            Ok(Some("speed=12"))
+        );
+    }
+
+    #[test]
+    fn corrupted_lines_are_rejected() {
+        assert_eq!(parse_checked_line("123456789*29B1"), Ok("123456789"));
+        assert_eq!(
+            parse_checked_line("123456780*29B1"),
+            Err(ChecksumError::Mismatch {
+                received: 0x29B1,
+                computed: crc16::checksum(b"123456780"),
+            })
+        );
+
+        assert_eq!(
+            parse_checked_line("123456789"),
+            Err(ChecksumError::MissingChecksum)
+        );
+        assert_eq!(
+            parse_checked_line("123456789*29B"),
+            Err(ChecksumError::MissingChecksum)
+        );
+        assert_eq!(
+            parse_checked_line("123456789*+9B1"),
+            Err(ChecksumError::MissingChecksum)
+        );
+        assert_eq!(
+            parse_checked_line("123456789*29G1"),
+            Err(ChecksumError::MissingChecksum)
+        );
+    }
+}
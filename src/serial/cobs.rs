@@ -0,0 +1,440 @@
+//! Consistent Overhead Byte Stuffing (COBS), for framing binary packets on a
+//! serial link
+//!
+//! COBS encodes a packet so that it contains no zero bytes, so a zero byte can
+//! mark the end of every frame. A receiver that loses track in the middle of a
+//! frame picks up again at the next zero byte.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::millis;
+
+use super::{crc16, read_timeout, spin_timed_out, USBSerial};
+
+/// The byte that ends every frame
+pub const DELIMITER: u8 = 0;
+
+/// The most bytes that encoding `length` bytes can take, without the delimiter
+pub const fn max_encoded_len(length: usize) -> usize {
+    length + length / 254 + 1
+}
+
+/// An error encountered while decoding a frame
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    /// The frame held a zero byte
+    UnexpectedZero,
+    /// The frame ended part way through a block, so it was cut short
+    Truncated,
+    /// The decoded frame did not fit in the output
+    Overflow,
+}
+
+/// An error encountered while reading in a frame with a checksum
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CheckedFrameError {
+    /// No whole frame arrived before the timeout
+    Timeout,
+    /// The frame was too short to hold a checksum
+    MissingChecksum,
+    /// The checksum did not match the payload, which was corrupted
+    Mismatch {
+        /// The checksum that came with the frame
+        received: u16,
+        /// The checksum of the payload as received
+        computed: u16,
+    },
+}
+
+/// Encode the input into the output, without a delimiter, returning the
+/// encoded length. Returns `None` if the output is too small, which a
+/// [`max_encoded_len`] long output never is.
+pub fn encode(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    let mut length = 0usize;
+
+    for_each_block(input, |code, run| {
+        let end = length + 1 + run.len();
+        let block = output.get_mut(length..end)?;
+
+        block[0] = code;
+        block[1..].copy_from_slice(run);
+        length = end;
+
+        Some(())
+    })?;
+
+    Some(length)
+}
+
+/// Decode a frame, without its delimiter, into the output, returning the decoded length
+pub fn decode(input: &[u8], output: &mut [u8]) -> Result<usize, DecodeError> {
+    let mut read = 0usize;
+    let mut written = 0usize;
+
+    while read < input.len() {
+        let code = input[read];
+        if code == 0 {
+            return Err(DecodeError::UnexpectedZero);
+        }
+        read += 1;
+
+        let run = input
+            .get(read..read + usize::from(code) - 1)
+            .ok_or(DecodeError::Truncated)?;
+        if run.contains(&0) {
+            return Err(DecodeError::UnexpectedZero);
+        }
+        read += run.len();
+
+        // Every block but the last and the full ones stands in for a zero
+        let zero = code < 0xFF && read < input.len();
+        let block = output
+            .get_mut(written..written + run.len() + usize::from(zero))
+            .ok_or(DecodeError::Overflow)?;
+
+        block[..run.len()].copy_from_slice(run);
+        if zero {
+            block[run.len()] = 0;
+        }
+        written += block.len();
+    }
+
+    Ok(written)
+}
+
+/// Decode a frame, without its delimiter, in place, returning the decoded length.
+/// The decoded frame is never longer than the encoded one.
+pub fn decode_in_place(buffer: &mut [u8]) -> Result<usize, DecodeError> {
+    let mut read = 0usize;
+    let mut written = 0usize;
+
+    while read < buffer.len() {
+        let code = usize::from(buffer[read]);
+        if code == 0 {
+            return Err(DecodeError::UnexpectedZero);
+        }
+        read += 1;
+
+        let end = read + code - 1;
+        if end > buffer.len() {
+            return Err(DecodeError::Truncated);
+        }
+        if buffer[read..end].contains(&0) {
+            return Err(DecodeError::UnexpectedZero);
+        }
+
+        // The decoded data is always behind the encoded data, so it can be moved down
+        buffer.copy_within(read..end, written);
+        written += end - read;
+        read = end;
+
+        if code < 0xFF && read < buffer.len() {
+            buffer[written] = 0;
+            written += 1;
+        }
+    }
+
+    Ok(written)
+}
+
+/// Split the input into the blocks of its encoding, calling `f` with the code byte and
+/// the bytes following it in each block. Stops early if `f` returns `None`.
+fn for_each_block(input: &[u8], mut f: impl FnMut(u8, &[u8]) -> Option<()>) -> Option<()> {
+    let mut rest = input;
+
+    loop {
+        // A block holds up to 254 bytes, ending before the next zero
+        let run = rest
+            .iter()
+            .take(254)
+            .position(|&byte| byte == 0)
+            .unwrap_or_else(|| rest.len().min(254));
+
+        f(run as u8 + 1, &rest[..run])?;
+
+        if run == 254 {
+            // A full block does not stand in for a zero
+            rest = &rest[run..];
+            if rest.is_empty() {
+                return Some(());
+            }
+        } else if run < rest.len() {
+            // Skip the zero that the block stands in for
+            rest = &rest[run + 1..];
+        } else {
+            return Some(());
+        }
+    }
+}
+
+static BYTES_SKIPPED: AtomicUsize = AtomicUsize::new(0);
+
+impl USBSerial {
+    /// Encode the payload and write it out as a frame, followed by the delimiter, returning
+    /// the amount of bytes successfully written out. The payload is encoded as it is
+    /// written out, so no extra buffer is needed.
+    pub fn write_cobs_frame(payload: &[u8]) -> usize {
+        let mut count = 0usize;
+
+        for_each_block(payload, |code, run| {
+            count += Self::write_bytes(&[code]) + Self::write_bytes(run);
+
+            Some(())
+        });
+
+        count + Self::write_bytes(&[DELIMITER])
+    }
+
+    /// Write out a frame as with [`write_cobs_frame`](USBSerial::write_cobs_frame), with the
+    /// [CRC-16](super::crc16) of the payload (in big endian) added to the end of it, inside
+    /// the frame. Returns the amount of bytes successfully written out.
+    pub fn write_cobs_frame_checked(payload: &[u8]) -> usize {
+        let crc = crc16::checksum(payload).to_be_bytes();
+
+        Self::write_encoded(payload.iter().copied().chain(crc))
+    }
+
+    /// Read in a frame written out with
+    /// [`write_cobs_frame_checked`](USBSerial::write_cobs_frame_checked), as with
+    /// [`read_cobs_frame`](USBSerial::read_cobs_frame), returning the length of the payload,
+    /// which is at the start of the buffer. The buffer has to fit the two checksum bytes too.
+    ///
+    /// A frame whose checksum does not match is dropped with an error, and the next read
+    /// starts at the frame after it.
+    pub fn read_cobs_frame_checked(buffer: &mut [u8]) -> Result<usize, CheckedFrameError> {
+        let length = Self::read_cobs_frame(buffer).ok_or(CheckedFrameError::Timeout)?;
+        let split = length
+            .checked_sub(2)
+            .ok_or(CheckedFrameError::MissingChecksum)?;
+
+        let received = u16::from_be_bytes([buffer[split], buffer[split + 1]]);
+        let computed = crc16::checksum(&buffer[..split]);
+
+        if received == computed {
+            Ok(split)
+        } else {
+            Err(CheckedFrameError::Mismatch { received, computed })
+        }
+    }
+
+    /// Encode the bytes as they come and write them out as a frame, followed by the
+    /// delimiter, returning the amount of bytes successfully written out. Each block is
+    /// collected on the stack, as its code byte goes before it.
+    fn write_encoded(bytes: impl Iterator<Item = u8>) -> usize {
+        let mut block = [0u8; 254];
+        let mut run = 0usize;
+        // A full block does not stand in for a zero, so no empty block has to follow it
+        let mut after_full = false;
+        let mut count = 0usize;
+
+        for byte in bytes {
+            after_full = false;
+
+            if byte == 0 {
+                count += Self::write_bytes(&[run as u8 + 1]) + Self::write_bytes(&block[..run]);
+                run = 0;
+            } else {
+                block[run] = byte;
+                run += 1;
+
+                if run == block.len() {
+                    count += Self::write_bytes(&[0xFF]) + Self::write_bytes(&block);
+                    run = 0;
+                    after_full = true;
+                }
+            }
+        }
+
+        if !after_full {
+            count += Self::write_bytes(&[run as u8 + 1]) + Self::write_bytes(&block[..run]);
+        }
+
+        count + Self::write_bytes(&[DELIMITER])
+    }
+
+    /// Read in a frame and decode it into the buffer, waiting for the duration of the serial
+    /// timeout, returning the decoded length. Returns `None` if no whole frame arrived in time.
+    ///
+    /// Frames that fail to decode, or do not fit in the buffer, are skipped, and reading
+    /// carries on with the next frame, so one corrupted byte can not wedge the stream. The
+    /// bytes skipped are counted, see [`cobs_bytes_skipped`](USBSerial::cobs_bytes_skipped).
+    pub fn read_cobs_frame(buffer: &mut [u8]) -> Option<usize> {
+        // The start time and duration, for timeout
+        let start_millis = millis();
+        let timeout = read_timeout();
+        // The current count of read in encoded bytes
+        let mut count = 0usize;
+
+        loop {
+            match Self::read_byte() {
+                // Empty frames, as from a stray delimiter, are ignored
+                Some(DELIMITER) if count == 0 => {}
+                Some(DELIMITER) => match decode_in_place(&mut buffer[..count]) {
+                    Ok(length) => return Some(length),
+                    Err(_) => {
+                        BYTES_SKIPPED.fetch_add(count + 1, Ordering::Relaxed);
+                        count = 0;
+                    }
+                },
+                Some(byte) => {
+                    if count < buffer.len() {
+                        buffer[count] = byte;
+                        count += 1;
+                    } else {
+                        // The frame can not fit, so drop the rest of it
+                        let skipped = Self::resync_cobs()?;
+                        BYTES_SKIPPED.fetch_add(count + 1 + skipped, Ordering::Relaxed);
+                        count = 0;
+                    }
+                }
+                None => {
+                    if spin_timed_out(start_millis, timeout) {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Skip the rest of the current frame, up to and including the next delimiter, so that
+    /// the next read starts at the beginning of a frame. Waits for the duration of the
+    /// serial timeout for the delimiter, returning the amount of bytes skipped, or `None`
+    /// if the delimiter did not arrive in time.
+    pub fn resync_cobs() -> Option<usize> {
+        // The start time and duration, for timeout
+        let start_millis = millis();
+        let timeout = read_timeout();
+        let mut skipped = 0usize;
+
+        loop {
+            match Self::read_byte() {
+                Some(DELIMITER) => return Some(skipped + 1),
+                Some(_) => skipped += 1,
+                None => {
+                    if spin_timed_out(start_millis, timeout) {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Get the amount of bytes that [`read_cobs_frame`](USBSerial::read_cobs_frame) has
+    /// skipped over in bad frames since startup, or since the last
+    /// [`reset_cobs_bytes_skipped`](USBSerial::reset_cobs_bytes_skipped)
+    pub fn cobs_bytes_skipped() -> usize {
+        BYTES_SKIPPED.load(Ordering::Relaxed)
+    }
+
+    /// Reset the count of bytes skipped over in bad frames to zero
+    pub fn reset_cobs_bytes_skipped() {
+        BYTES_SKIPPED.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    /// Encode the input, checking its length against [`max_encoded_len`]
+    fn encoded(input: &[u8], output: &mut [u8]) -> usize {
+        let length = encode(input, output).unwrap();
+        assert!(length <= max_encoded_len(input.len()));
+
+        length
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let mut long = [0u8; 600];
+        for (index, byte) in long.iter_mut().enumerate() {
+            *byte = (index % 256) as u8;
+        }
+
+        let inputs: [&[u8]; 6] = [b"", b"\0", b"\0\0", b"a\0bc\0", &[0xAB; 254], &long];
+        for input in inputs {
+            let mut frame = [0u8; max_encoded_len(600)];
+            let length = encoded(input, &mut frame);
+            assert!(!frame[..length].contains(&0));
+
+            let mut decoded = [0u8; 600];
+            assert_eq!(decode(&frame[..length], &mut decoded), Ok(input.len()));
+            assert_eq!(&decoded[..input.len()], input);
+
+            assert_eq!(decode_in_place(&mut frame[..length]), Ok(input.len()));
+            assert_eq!(&frame[..input.len()], input);
+        }
+    }
+
+    #[test]
+    fn known_encodings_are_used() {
+        let mut frame = [0u8; 8];
+
+        assert_eq!(encode(b"\x11\x22\0\x33", &mut frame), Some(5));
+        assert_eq!(&frame[..5], b"\x03\x11\x22\x02\x33");
+        // Too small an output
+        assert_eq!(encode(b"\x11\x22\0\x33", &mut frame[..4]), None);
+    }
+
+    #[test]
+    fn broken_frames_do_not_decode() {
+        let mut output = [0u8; 8];
+
+        assert_eq!(
+            decode(b"\x03\x11\0", &mut output),
+            Err(DecodeError::UnexpectedZero)
+        );
+        assert_eq!(
+            decode(b"\x05\x11\x22", &mut output),
+            Err(DecodeError::Truncated)
+        );
+        assert_eq!(
+            decode(b"\x03\x11\x22\x02\x33", &mut output[..3]),
+            Err(DecodeError::Overflow)
+        );
+
+        assert_eq!(
+            decode_in_place(&mut [0x02, 0x00]),
+            Err(DecodeError::UnexpectedZero)
+        );
+        assert_eq!(
+            decode_in_place(&mut [0x05, 0x11, 0x22]),
+            Err(DecodeError::Truncated)
+        );
+    }
+
+    #[test]
+    fn reading_carries_on_after_a_corrupted_frame() {
+        let _serial = test_support::serial();
+        USBSerial::reset_cobs_bytes_skipped();
+
+        USBSerial::write_cobs_frame(b"first");
+        let first = test_support::sent();
+        USBSerial::write_cobs_frame(b"second");
+        let second = test_support::sent();
+
+        test_support::receive(&first);
+        // Cut short, as if a byte was lost
+        test_support::receive(b"\x05ab\0");
+        test_support::receive(&second);
+
+        let mut buffer = [0u8; 16];
+        assert_eq!(USBSerial::read_cobs_frame(&mut buffer), Some(5));
+        assert_eq!(&buffer[..5], b"first");
+        assert_eq!(USBSerial::read_cobs_frame(&mut buffer), Some(6));
+        assert_eq!(&buffer[..6], b"second");
+        assert_eq!(USBSerial::cobs_bytes_skipped(), 4);
+    }
+
+    #[test]
+    fn resyncing_skips_to_the_next_frame() {
+        let _serial = test_support::serial();
+        test_support::receive(b"rest of a frame\0\x02a\0");
+
+        assert_eq!(USBSerial::resync_cobs(), Some(16));
+        let mut buffer = [0u8; 4];
+        assert_eq!(USBSerial::read_cobs_frame(&mut buffer), Some(1));
+        assert_eq!(USBSerial::resync_cobs(), None);
+    }
+}
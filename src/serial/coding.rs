@@ -0,0 +1,122 @@
+//! Reacting to the host changing the line coding of the serial port
+
+use core::ptr;
+
+use super::{LineCoding, USBSerial};
+
+/// The callback to run on changes, and the line coding that it last saw
+struct Watcher {
+    callback: fn(LineCoding),
+    last: LineCoding,
+}
+
+static mut WATCHER: Option<Watcher> = None;
+
+/// Access the line coding watcher
+///
+/// # Safety
+/// The serial port may only be used from one context at a time, so there
+/// must never be two live references to the watcher
+unsafe fn watcher() -> &'static mut Option<Watcher> {
+    &mut *ptr::addr_of_mut!(WATCHER)
+}
+
+impl USBSerial {
+    /// Set a callback to run with the new line coding whenever the host changes any of its
+    /// settings, such as for a usb to serial bridge that has to apply the parity and stop
+    /// bits as well as the baud rate. Replaces any earlier callback.
+    ///
+    /// The usb serial C API does not notify of changes, so they are only noticed by calling
+    /// [`poll_line_coding`](USBSerial::poll_line_coding). Only changes after this call are
+    /// reported.
+    pub fn on_line_coding_change(f: fn(LineCoding)) {
+        unsafe {
+            *watcher() = Some(Watcher {
+                callback: f,
+                last: Self::line_coding(),
+            })
+        };
+    }
+
+    /// Stop running the callback set with
+    /// [`on_line_coding_change`](USBSerial::on_line_coding_change)
+    pub fn clear_line_coding_change() {
+        unsafe { *watcher() = None };
+    }
+
+    /// Check if the line coding has changed since the last call, and if so, run the callback
+    /// set with [`on_line_coding_change`](USBSerial::on_line_coding_change) with the new line
+    /// coding. Returns whether the callback was run. Call this regularly, such as once every
+    /// loop.
+    pub fn poll_line_coding() -> bool {
+        let coding = Self::line_coding();
+
+        let callback = match unsafe { watcher() } {
+            Some(watcher) if watcher.last != coding => {
+                watcher.last = coding;
+                watcher.callback
+            }
+            _ => return false,
+        };
+
+        // Run outside of the borrow, so the callback can replace itself
+        callback(coding);
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::{serial::Parity, test_support};
+
+    #[test]
+    fn line_coding_is_decoded() {
+        let _serial = test_support::serial();
+        test_support::set_line_coding(115_200, 2, 1, 7);
+
+        let coding = USBSerial::line_coding();
+        assert_eq!(
+            coding,
+            LineCoding {
+                baud: 115_200,
+                stop_bits: 2,
+                parity: Parity::Odd,
+                num_bits: 7,
+            }
+        );
+        assert_eq!(
+            std::format!("{:?}", coding),
+            "LineCoding { baud: 115200, stop_bits: 2, parity: Odd, num_bits: 7 }"
+        );
+
+        // 0 stop bits stand for 1
+        test_support::set_line_coding(9600, 0, 0, 8);
+        assert_eq!(USBSerial::stop_bits(), 1);
+        assert_eq!(USBSerial::parity_type(), Parity::None);
+    }
+
+    #[test]
+    fn changes_run_the_callback() {
+        static BAUD: AtomicU32 = AtomicU32::new(0);
+
+        let _serial = test_support::serial();
+        test_support::set_line_coding(9600, 0, 0, 8);
+        USBSerial::on_line_coding_change(|coding| BAUD.store(coding.baud, Ordering::Relaxed));
+
+        assert!(!USBSerial::poll_line_coding());
+
+        test_support::set_line_coding(57_600, 0, 0, 8);
+        assert!(USBSerial::poll_line_coding());
+        assert_eq!(BAUD.load(Ordering::Relaxed), 57_600);
+        // Only reported once
+        assert!(!USBSerial::poll_line_coding());
+
+        USBSerial::clear_line_coding_change();
+    }
+}
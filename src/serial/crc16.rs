@@ -0,0 +1,39 @@
+//! The CRC-16/CCITT-FALSE checksum, for detecting corrupted data on a serial link
+//!
+//! The checksum is computed bit by bit rather than with a lookup table,
+//! trading some speed for flash space.
+
+/// The checksum of no data
+pub const INITIAL: u16 = 0xFFFF;
+
+/// The generator polynomial
+const POLYNOMIAL: u16 = 0x1021;
+
+/// Compute the checksum of the data
+pub const fn checksum(data: &[u8]) -> u16 {
+    update(INITIAL, data)
+}
+
+/// Continue computing a checksum with more data, for data that does not arrive
+/// all at once. Start from [`INITIAL`].
+pub const fn update(mut crc: u16, data: &[u8]) -> u16 {
+    let mut i = 0;
+
+    while i < data.len() {
+        crc ^= (data[i] as u16) << 8;
+
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ POLYNOMIAL
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+
+        i += 1;
+    }
+
+    crc
+}
@@ -0,0 +1,144 @@
+//! Expanding `\n` line endings to `\r\n` in written out text
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::USBSerial;
+
+static CRLF_TRANSLATION: AtomicBool = AtomicBool::new(false);
+/// If the last byte of text written out was a `\r`, so that a `\n`
+/// at the start of the next write is already part of a `\r\n`
+static LAST_WAS_CR: AtomicBool = AtomicBool::new(false);
+
+/// Check if `\n`s in text are expanded to `\r\n`
+pub(super) fn enabled() -> bool {
+    CRLF_TRANSLATION.load(Ordering::Relaxed)
+}
+
+/// Write out the text through `write`, expanding every `\n` that is not already part of a
+/// `\r\n` into a `\r\n`. Returns the amount of bytes of the text successfully written out,
+/// stopping once `write` writes out less than it is given.
+///
+/// If only the `\r` of an expanded `\n` is written out, the `\n` is not counted, and is
+/// written out on its own by the next write, without another `\r`.
+pub(super) fn write_translated(text: &[u8], mut write: impl FnMut(&[u8]) -> usize) -> usize {
+    let mut last_was_cr = LAST_WAS_CR.load(Ordering::Relaxed);
+    let count = translate(text, last_was_cr, |bytes| {
+        let written = write(bytes);
+        if let Some(&last) = bytes[..written].last() {
+            last_was_cr = last == b'\r';
+        }
+
+        written
+    });
+
+    LAST_WAS_CR.store(last_was_cr, Ordering::Relaxed);
+
+    count
+}
+
+/// Write out the text as with [`write_translated`], where `after_cr` is if the last byte
+/// written out before it was a `\r`
+fn translate(text: &[u8], after_cr: bool, mut write: impl FnMut(&[u8]) -> usize) -> usize {
+    let mut count = 0usize;
+
+    for (index, &byte) in text.iter().enumerate() {
+        if byte != b'\n' {
+            continue;
+        }
+
+        let after_cr = match index.checked_sub(1) {
+            Some(previous) => text[previous] == b'\r',
+            None => after_cr,
+        };
+        if after_cr {
+            continue;
+        }
+
+        // Write out everything up to the `\n`, then the `\r\n` in place of it
+        let segment = &text[count..index];
+        let written = write(segment);
+        if written < segment.len() {
+            return count + written;
+        }
+        // If only the `\r` went out, the `\n` is now after one
+        if write(b"\r\n") < 2 {
+            return index;
+        }
+
+        count = index + 1;
+    }
+
+    count + write(&text[count..])
+}
+
+impl USBSerial {
+    /// Set if `\n`s in text written out with [`write`](USBSerial::write) and
+    /// [`USBSerialWriter`](super::USBSerialWriter) are expanded to `\r\n`, as most
+    /// serial terminals expect. A `\n` that is already part of a `\r\n` is left as is.
+    /// Disabled by default.
+    ///
+    /// Bytes written out with [`write_bytes`](USBSerial::write_bytes) and the other
+    /// binary writers are never changed.
+    pub fn set_crlf_translation(enabled: bool) {
+        CRLF_TRANSLATION.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Check if `\n`s in text written out are expanded to `\r\n`
+    pub fn crlf_translation() -> bool {
+        enabled()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn line_endings_are_expanded() {
+        let _serial = test_support::serial();
+        USBSerial::set_crlf_translation(true);
+
+        assert_eq!(USBSerial::write("a\nb\r\nc\r"), 7);
+        // Already part of a `\r\n` from the last write
+        assert_eq!(USBSerial::write("\nd\n"), 3);
+        assert_eq!(test_support::sent(), b"a\r\nb\r\nc\r\nd\r\n");
+    }
+
+    #[test]
+    fn half_written_line_endings_are_not_repeated() {
+        let _serial = test_support::serial();
+        USBSerial::set_crlf_translation(true);
+        USBSerial::write("start");
+
+        // Only the `\r` fits
+        test_support::set_write_limit(3);
+        assert_eq!(USBSerial::write("ab\ncd"), 2);
+
+        test_support::set_write_limit(usize::MAX);
+        assert_eq!(USBSerial::write("\ncd"), 3);
+        assert_eq!(test_support::sent(), b"startab\r\ncd");
+    }
+
+    #[test]
+    fn short_writes_keep_track_of_the_last_byte() {
+        let _serial = test_support::serial();
+        USBSerial::set_crlf_translation(true);
+        USBSerial::write("start");
+
+        // Stopping right after a `\r` in the text
+        test_support::set_write_limit(2);
+        assert_eq!(USBSerial::write("a\rbc\n"), 2);
+
+        test_support::set_write_limit(usize::MAX);
+        assert_eq!(USBSerial::write("\n"), 1);
+        assert_eq!(test_support::sent(), b"starta\r\n");
+
+        // Stopping before anything is written out
+        test_support::set_write_limit(0);
+        assert_eq!(USBSerial::write("\n"), 0);
+        test_support::set_write_limit(usize::MAX);
+        assert_eq!(USBSerial::write("\n"), 1);
+        assert_eq!(test_support::sent(), b"\r\n");
+    }
+}
@@ -0,0 +1,148 @@
+//! Records of comma separated values, one per line
+//!
+//! Fields that are read in are not unquoted, so they can not contain commas or line
+//! endings. Fields that are written out are quoted as in
+//! [RFC 4180](https://www.rfc-editor.org/rfc/rfc4180) where needed.
+
+use core::fmt::{self, Write};
+
+use super::{LineError, USBSerial, USBSerialWriter};
+
+/// An error encountered while reading in a record
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CsvError {
+    /// The line could not be read in
+    Line(LineError),
+    /// The record had more fields than the output held. Holds the amount of fields
+    /// in the record. The output is filled with the first fields.
+    TooManyFields(usize),
+}
+
+impl From<LineError> for CsvError {
+    fn from(err: LineError) -> Self {
+        CsvError::Line(err)
+    }
+}
+
+impl USBSerial {
+    /// Read in a line, as with [`read_line`](USBSerial::read_line), and split it on commas
+    /// into `fields`, returning the amount of fields. Empty fields are kept as empty strings,
+    /// so an empty line has one field.
+    ///
+    /// Returns `Ok(0)` if no line was received before the timeout.
+    pub fn read_csv_record<'a>(
+        buffer: &'a mut [u8],
+        fields: &mut [&'a str],
+    ) -> Result<usize, CsvError> {
+        let line = match Self::read_line(buffer)? {
+            Some(line) => line,
+            None => return Ok(0),
+        };
+
+        let mut count = 0usize;
+        for field in line.split(',') {
+            if let Some(slot) = fields.get_mut(count) {
+                *slot = field;
+            }
+            count += 1;
+        }
+
+        if count > fields.len() {
+            Err(CsvError::TooManyFields(count))
+        } else {
+            Ok(count)
+        }
+    }
+
+    /// Write out the fields joined by commas, followed by a `\r\n` line ending, returning
+    /// if the whole record was written out. Fields that contain commas, quotes or line
+    /// endings are put in quotes, with the quotes in them doubled.
+    pub fn write_csv_record(fields: &[&str]) -> bool {
+        write_record(&mut USBSerialWriter {}, fields).is_ok()
+    }
+}
+
+/// Write out a record, quoting the fields that need it
+fn write_record(writer: &mut impl Write, fields: &[&str]) -> fmt::Result {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            writer.write_char(',')?;
+        }
+
+        if field.contains([',', '"', '\r', '\n']) {
+            writer.write_char('"')?;
+            for (j, part) in field.split('"').enumerate() {
+                if j > 0 {
+                    writer.write_str("\"\"")?;
+                }
+                writer.write_str(part)?;
+            }
+            writer.write_char('"')?;
+        } else {
+            writer.write_str(field)?;
+        }
+    }
+
+    writer.write_str("\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn records_are_split_on_commas() {
+        let _serial = test_support::serial();
+        test_support::receive(b"1,two,,4\r\n");
+
+        let mut buffer = [0u8; 32];
+        let mut fields = [""; 4];
+        assert_eq!(USBSerial::read_csv_record(&mut buffer, &mut fields), Ok(4));
+        // The empty field is kept
+        assert_eq!(fields, ["1", "two", "", "4"]);
+    }
+
+    #[test]
+    fn nothing_is_read_without_a_line() {
+        let _serial = test_support::serial();
+
+        let mut buffer = [0u8; 32];
+        let mut fields = [""; 2];
+        assert_eq!(USBSerial::read_csv_record(&mut buffer, &mut fields), Ok(0));
+    }
+
+    #[test]
+    fn extra_fields_are_counted() {
+        let _serial = test_support::serial();
+        test_support::receive(b"a,b,c\r\n");
+
+        let mut buffer = [0u8; 32];
+        let mut fields = [""; 2];
+        assert_eq!(
+            USBSerial::read_csv_record(&mut buffer, &mut fields),
+            Err(CsvError::TooManyFields(3))
+        );
+        assert_eq!(fields, ["a", "b"]);
+    }
+
+    #[test]
+    fn fields_are_quoted_where_needed() {
+        let mut out = std::string::String::new();
+        write_record(&mut out, &["plain", "a,b", "say \"hi\"", "two\nlines", ""]).unwrap();
+
+        assert_eq!(out, "plain,\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\",\r\n");
+    }
+
+    #[test]
+    fn records_are_written_out() {
+        let _serial = test_support::serial();
+
+        assert!(USBSerial::write_csv_record(&["1", "x\ry"]));
+        assert_eq!(test_support::sent(), b"1,\"x\ry\"\r\n");
+        assert!(USBSerial::write_csv_record(&[]));
+        assert_eq!(test_support::sent(), b"\r\n");
+    }
+}
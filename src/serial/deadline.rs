@@ -0,0 +1,66 @@
+//! A point in time that a timed operation has to finish by
+
+use crate::millis;
+
+use super::{timed_out, yield_now, USBSerial};
+
+/// A deadline some milliseconds from when it was created, for timed loops. The
+/// millisecond counter overflowing (after about 49 days) is accounted for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Deadline {
+    start_millis: u32,
+    timeout: u32,
+}
+
+impl Deadline {
+    /// Create a deadline `timeout` milliseconds from now
+    pub fn new(timeout: u32) -> Deadline {
+        Deadline {
+            start_millis: millis(),
+            timeout,
+        }
+    }
+
+    /// Check if the deadline has passed
+    pub fn expired(&self) -> bool {
+        timed_out(self.start_millis, self.timeout)
+    }
+
+    /// Yield to the [yield hook](USBSerial::set_yield_hook), then check if the deadline has
+    /// passed, for busy loops
+    pub(super) fn spin_expired(&self) -> bool {
+        yield_now();
+        self.expired()
+    }
+
+    /// The milliseconds left until the deadline, or 0 if it has passed
+    pub fn remaining(&self) -> u32 {
+        self.timeout
+            .saturating_sub(millis().wrapping_sub(self.start_millis))
+    }
+}
+
+impl USBSerial {
+    /// Run a custom timed operation, calling `f` over and over until it returns `Some`, or
+    /// `ms` milliseconds pass. Returns the result of `f`, or `None` if it timed out. The
+    /// [`Deadline`] is passed to `f`, so it can bound its own waiting with it, or pass it on.
+    ///
+    /// `f` is always called at least once.
+    ///
+    /// ```ignore
+    /// let c = SERIAL::with_deadline(100, |_| SERIAL::read());
+    /// ```
+    pub fn with_deadline<R>(ms: u32, mut f: impl FnMut(&Deadline) -> Option<R>) -> Option<R> {
+        let deadline = Deadline::new(ms);
+
+        loop {
+            if let Some(result) = f(&deadline) {
+                return Some(result);
+            }
+
+            if deadline.spin_expired() {
+                return None;
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Implementations of the [embedded-io](https://docs.rs/embedded-io) traits
+//! for the usb serial port
+//!
+//! **Requires the feature `embedded-io`**, and the feature `embedded-io-async`
+//! for the async traits
+
+use ::embedded_io::{ErrorKind, ErrorType, Read, ReadReady, Write, WriteReady};
+
+use super::{spin_timed_out, USBSerial};
+use crate::millis;
+
+/// A handle to the usb serial port, for use with drivers and parsers built
+/// on the embedded-io traits.
+///
+/// Blocking reads and writes wait for the serial read and write timeouts
+/// (see [`USBSerial::set_timeout`] and [`USBSerial::set_write_timeout`])
+/// before giving up with [`Error::TimedOut`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct USBSerialPort;
+
+/// An error from the usb serial port
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// No bytes could be read or written before the serial timeout
+    TimedOut,
+}
+
+impl ::embedded_io::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::TimedOut => ErrorKind::TimedOut,
+        }
+    }
+}
+
+impl ErrorType for USBSerialPort {
+    type Error = Error;
+}
+
+/// Repeat an operation until it moves at least one byte, or the timeout is reached
+fn until_timeout(timeout: u32, mut operation: impl FnMut() -> usize) -> Result<usize, Error> {
+    // The start time, for timeout
+    let start_millis = millis();
+
+    loop {
+        let count = operation();
+
+        if count > 0 {
+            return Ok(count);
+        }
+
+        if spin_timed_out(start_millis, timeout) {
+            return Err(Error::TimedOut);
+        }
+    }
+}
+
+impl Read for USBSerialPort {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        until_timeout(USBSerial::get_timeout(), || USBSerial::read_bytes(buf))
+    }
+}
+
+impl ReadReady for USBSerialPort {
+    fn read_ready(&mut self) -> Result<bool, Error> {
+        Ok(USBSerial::avaliable() > 0)
+    }
+}
+
+impl Write for USBSerialPort {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        until_timeout(USBSerial::get_write_timeout(), || {
+            USBSerial::write_bytes(buf)
+        })
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        USBSerial::send_now();
+
+        Ok(())
+    }
+}
+
+impl WriteReady for USBSerialPort {
+    fn write_ready(&mut self) -> Result<bool, Error> {
+        Ok(USBSerial::available_for_write() > 0)
+    }
+}
+
+/// The usb serial stack does not signal when data arrives or buffer space frees
+/// up, so these futures poll by waking themselves back up until they can make
+/// progress. They never time out; use your executor's timeouts instead.
+#[cfg(feature = "embedded-io-async")]
+mod asynchronous {
+    use core::{future::poll_fn, task::Poll};
+
+    use super::{Error, USBSerialPort};
+    use crate::serial::USBSerial;
+
+    impl ::embedded_io_async::Read for USBSerialPort {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            poll_fn(|cx| {
+                let count = USBSerial::read_bytes(buf);
+
+                if count > 0 || buf.is_empty() {
+                    Poll::Ready(Ok(count))
+                } else {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            })
+            .await
+        }
+    }
+
+    impl ::embedded_io_async::Write for USBSerialPort {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            poll_fn(|cx| {
+                let count = USBSerial::write_bytes(buf);
+
+                if count > 0 || buf.is_empty() {
+                    Poll::Ready(Ok(count))
+                } else {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            })
+            .await
+        }
+
+        async fn flush(&mut self) -> Result<(), Error> {
+            USBSerial::send_now();
+
+            Ok(())
+        }
+    }
+}
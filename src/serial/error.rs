@@ -0,0 +1,105 @@
+//! One error type for the serial functions, for callers that want to use `?`
+
+use core::{
+    fmt::{self, Arguments, Write},
+    str::Utf8Error,
+};
+
+use super::{LineError, TxError, USBSerial, USBSerialWriter};
+
+/// An error from one of the `try_` serial functions, such as [`USBSerial::try_read_line`].
+/// The errors of the other serial functions convert into it, so `?` can be used on all of them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SerialError {
+    /// Nothing, or not enough, arrived before the timeout
+    Timeout,
+    /// The output buffer stayed full for the whole write timeout, so the rest of the
+    /// write could not be written out
+    BufferFull,
+    /// Text read in was not valid UTF-8
+    Utf8(Utf8Error),
+    /// A request or formatted output could not be written out
+    WriteFailed,
+    /// The input was longer than the buffer, or the maximum line length
+    Overflow,
+}
+
+impl From<Utf8Error> for SerialError {
+    fn from(err: Utf8Error) -> Self {
+        SerialError::Utf8(err)
+    }
+}
+
+impl From<LineError> for SerialError {
+    fn from(err: LineError) -> Self {
+        match err {
+            LineError::Overflow => SerialError::Overflow,
+            LineError::Utf8(err) => SerialError::Utf8(err),
+        }
+    }
+}
+
+impl From<TxError> for SerialError {
+    fn from(err: TxError) -> Self {
+        match err {
+            TxError::WriteFailed => SerialError::WriteFailed,
+            TxError::Timeout(_) => SerialError::Timeout,
+            TxError::Overflow => SerialError::Overflow,
+        }
+    }
+}
+
+impl From<fmt::Error> for SerialError {
+    fn from(_: fmt::Error) -> Self {
+        SerialError::WriteFailed
+    }
+}
+
+impl USBSerial {
+    /// Write a whole string out, retrying for the duration of the write timeout, as with
+    /// [`write_bytes_timeout`](USBSerial::write_bytes_timeout)
+    pub fn try_write(string: &str) -> Result<(), SerialError> {
+        Self::try_write_bytes(string.as_bytes())
+    }
+
+    /// Write a whole buffer of bytes out, retrying for the duration of the write timeout, as
+    /// with [`write_bytes_timeout`](USBSerial::write_bytes_timeout)
+    pub fn try_write_bytes(buffer: &[u8]) -> Result<(), SerialError> {
+        if Self::write_bytes_timeout(buffer) == buffer.len() {
+            Ok(())
+        } else {
+            Err(SerialError::BufferFull)
+        }
+    }
+
+    /// Write out formatted text, as from `format_args!`, with the line endings translated
+    /// as with [`USBSerialWriter`]
+    pub fn try_write_fmt(args: Arguments) -> Result<(), SerialError> {
+        Ok(USBSerialWriter {}.write_fmt(args)?)
+    }
+
+    /// Fill the buffer with bytes, waiting for the duration of the timeout, as with
+    /// [`read_bytes_exact_timeout`](USBSerial::read_bytes_exact_timeout)
+    pub fn try_read_exact(buffer: &mut [u8]) -> Result<(), SerialError> {
+        Self::read_bytes_exact_timeout(buffer).map_err(|_| SerialError::Timeout)
+    }
+
+    /// Read in a line, as with [`read_line`](USBSerial::read_line), failing with
+    /// [`SerialError::Timeout`] if nothing arrived
+    pub fn try_read_line(buffer: &mut [u8]) -> Result<&str, SerialError> {
+        Self::read_line(buffer)?.ok_or(SerialError::Timeout)
+    }
+
+    /// Send a request and wait for the response, as with
+    /// [`transaction`](USBSerial::transaction)
+    pub fn try_transaction(
+        request: &[u8],
+        response: &mut [u8],
+        terminator: u8,
+        timeout_ms: u32,
+    ) -> Result<usize, SerialError> {
+        Ok(Self::transaction(
+            request, response, terminator, timeout_ms,
+        )?)
+    }
+}
@@ -0,0 +1,147 @@
+//! Parsing of decimal numbers into fixed point integers, for code that avoids floats
+
+use core::convert::TryFrom;
+
+use crate::millis;
+
+use super::{read_timeout, spin_timed_out, USBSerial};
+
+/// The most fractional digits that are kept, as more would overflow the fraction
+const MAX_FRACTION_DIGITS: u32 = 9;
+
+impl USBSerial {
+    /// Read in a decimal number, such as `-3.142`, and return it multiplied by `scale` as an
+    /// integer, so `3.142` with a scale of 1000 is `3142`. Missing fractional digits count as
+    /// zeros, and digits beyond what the scale can hold are cut off, so `3.14159` with a scale
+    /// of 100 is `314`.
+    ///
+    /// Anything before the number is skipped, and the number ends at the first byte that is
+    /// not part of it, which is left unread. Returns `None` if no digits arrived within the
+    /// serial timeout, or if the scaled number does not fit in an `i32`.
+    pub fn parse_fixed(scale: u32) -> Option<i32> {
+        // The start time and duration, for timeout
+        let start_millis = millis();
+        let timeout = read_timeout();
+        // Peek at the next byte, waiting for it to arrive
+        let next = || loop {
+            if let Some(c) = Self::peek() {
+                return Some(c as u8);
+            }
+
+            if spin_timed_out(start_millis, timeout) {
+                return None;
+            }
+        };
+
+        // Skip anything before the number
+        loop {
+            match next()? {
+                b'0'..=b'9' | b'-' | b'.' => break,
+                _ => {
+                    Self::read_byte();
+                }
+            }
+        }
+
+        let negative = next() == Some(b'-');
+        if negative {
+            Self::read_byte();
+        }
+
+        let mut whole = 0u64;
+        let mut fraction = 0u64;
+        let mut divisor = 1u64;
+        let mut digits = 0u32;
+        let mut fraction_digits = None;
+
+        while let Some(c) = next() {
+            match (c, fraction_digits) {
+                (b'0'..=b'9', None) => {
+                    whole = whole.saturating_mul(10).saturating_add(u64::from(c - b'0'));
+                    digits += 1;
+                }
+                (b'0'..=b'9', Some(count)) => {
+                    if count < MAX_FRACTION_DIGITS {
+                        fraction = fraction * 10 + u64::from(c - b'0');
+                        divisor *= 10;
+                        fraction_digits = Some(count + 1);
+                    }
+                    digits += 1;
+                }
+                (b'.', None) => fraction_digits = Some(0),
+                _ => break,
+            }
+
+            Self::read_byte();
+        }
+
+        if digits == 0 {
+            return None;
+        }
+
+        let scale = u64::from(scale);
+        let value = whole
+            .saturating_mul(scale)
+            .saturating_add(fraction * scale / divisor);
+        let value = i64::try_from(value).ok()?;
+
+        i32::try_from(if negative { -value } else { value }).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    /// Parse the received text with the scale
+    fn parse(text: &[u8], scale: u32) -> Option<i32> {
+        test_support::receive(text);
+        let value = USBSerial::parse_fixed(scale);
+        USBSerial::clear();
+
+        value
+    }
+
+    #[test]
+    fn numbers_are_scaled() {
+        let _serial = test_support::serial();
+
+        assert_eq!(parse(b"3.142", 1000), Some(3142));
+        assert_eq!(parse(b"-3.142", 1000), Some(-3142));
+        // Missing digits are zeros, and extra digits are cut off
+        assert_eq!(parse(b"2.5", 1000), Some(2500));
+        assert_eq!(parse(b"3.14159", 100), Some(314));
+        assert_eq!(parse(b"-.5", 10), Some(-5));
+        assert_eq!(parse(b"7", 1), Some(7));
+    }
+
+    #[test]
+    fn text_around_the_number_is_skipped() {
+        let _serial = test_support::serial();
+        test_support::receive(b"temp: 21.5C");
+
+        assert_eq!(USBSerial::parse_fixed(10), Some(215));
+        assert_eq!(USBSerial::read_byte(), Some(b'C'));
+    }
+
+    #[test]
+    fn overflowing_numbers_are_none() {
+        let _serial = test_support::serial();
+
+        assert_eq!(parse(b"2147483647", 1), Some(i32::MAX));
+        assert_eq!(parse(b"-2147483648", 1), Some(i32::MIN));
+        assert_eq!(parse(b"2147483648", 1), None);
+        assert_eq!(parse(b"21474837", 100), None);
+        assert_eq!(parse(b"99999999999999999999999", 1), None);
+    }
+
+    #[test]
+    fn numbers_need_digits() {
+        let _serial = test_support::serial();
+
+        assert_eq!(parse(b"", 1), None);
+        assert_eq!(parse(b"-.", 1), None);
+        assert_eq!(parse(b"abc", 1), None);
+    }
+}
@@ -0,0 +1,121 @@
+//! Software (XON/XOFF) flow control on the usb serial port, for bridging to
+//! legacy devices that pause and resume the flow of data in-band
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::USBSerial;
+
+/// The byte asking the sender to resume sending
+pub const XON: u8 = 0x11;
+/// The byte asking the sender to pause sending
+pub const XOFF: u8 = 0x13;
+
+/// The ways that the flow of data written out can be controlled
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FlowControl {
+    /// Data is always written out (default)
+    None,
+    /// Writing is paused when an [`XOFF`] is received, and resumed when an [`XON`] is received
+    XonXoff,
+}
+
+static XON_XOFF: AtomicBool = AtomicBool::new(false);
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Check if XON/XOFF flow control is enabled
+pub(super) fn xon_xoff() -> bool {
+    XON_XOFF.load(Ordering::Relaxed)
+}
+
+/// Check if the other end has paused writing with an XOFF
+pub(super) fn paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+/// Remove the flow control bytes from the slice, applying them, and moving the
+/// rest of the bytes to the front. Returns the amount of bytes left.
+pub(super) fn filter(bytes: &mut [u8]) -> usize {
+    let mut kept = 0usize;
+
+    for index in 0..bytes.len() {
+        match bytes[index] {
+            XON => PAUSED.store(false, Ordering::Relaxed),
+            XOFF => PAUSED.store(true, Ordering::Relaxed),
+            byte => {
+                bytes[kept] = byte;
+                kept += 1;
+            }
+        }
+    }
+
+    kept
+}
+
+impl USBSerial {
+    /// Set how the flow of data written out is controlled. Defaults to [`FlowControl::None`].
+    ///
+    /// With [`FlowControl::XonXoff`], the [`XON`] and [`XOFF`] bytes are consumed as they are
+    /// received, and are never delivered to the readers. While paused, writes write nothing
+    /// out, and the writes with a timeout wait for an XON until the write timeout. Received
+    /// bytes are then moved through the small internal peek buffer, so an XON can only be
+    /// noticed while that buffer has space; keep reading in while waiting to write.
+    pub fn set_flow_control(flow_control: FlowControl) {
+        XON_XOFF.store(flow_control == FlowControl::XonXoff, Ordering::Relaxed);
+        PAUSED.store(false, Ordering::Relaxed);
+    }
+
+    /// Get how the flow of data written out is controlled
+    pub fn flow_control() -> FlowControl {
+        if xon_xoff() {
+            FlowControl::XonXoff
+        } else {
+            FlowControl::None
+        }
+    }
+
+    /// Check if writing is paused by an [`XOFF`] from the other end
+    pub fn is_flow_paused() -> bool {
+        // Look for an XON that has arrived since
+        if xon_xoff() {
+            unsafe { super::peeked().fill(super::PEEK_BUFFER_SIZE) };
+        }
+
+        paused()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn flow_control_bytes_are_stripped_and_applied() {
+        let _serial = test_support::serial();
+
+        let mut bytes = *b"a\x13b\x11c\x13";
+        assert_eq!(filter(&mut bytes), 3);
+        assert_eq!(&bytes[..3], b"abc");
+        // The last one wins
+        assert!(paused());
+
+        let mut bytes = [XON];
+        assert_eq!(filter(&mut bytes), 0);
+        assert!(!paused());
+    }
+
+    #[test]
+    fn writes_wait_for_an_xon() {
+        let _serial = test_support::serial();
+        USBSerial::set_flow_control(FlowControl::XonXoff);
+
+        test_support::receive(&[XOFF]);
+        assert!(USBSerial::is_flow_paused());
+        assert_eq!(USBSerial::write("held"), 0);
+
+        test_support::receive(&[XON]);
+        assert!(!USBSerial::is_flow_paused());
+        assert_eq!(USBSerial::write("sent"), 4);
+        assert_eq!(test_support::sent(), b"sent");
+    }
+}
@@ -0,0 +1,124 @@
+//! Formatting into a fixed size buffer on the stack
+
+use core::{
+    fmt::{self, Write},
+    str,
+};
+
+/// A buffer of `N` bytes on the stack that text can be formatted into with
+/// `write!`, to be measured or inspected before being written out. Writing the
+/// whole buffer out with [`USBSerial::write`](super::USBSerial::write) takes a
+/// single call into the usb stack, instead of one for every piece of the format.
+///
+/// A write that does not fit fails with an `fmt::Error`, leaving the buffer
+/// as it was before the write.
+#[derive(Clone, Copy)]
+pub struct FmtBuf<const N: usize> {
+    buffer: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> FmtBuf<N> {
+    /// Create a new, empty buffer
+    pub const fn new() -> Self {
+        FmtBuf {
+            buffer: [0; N],
+            len: 0,
+        }
+    }
+
+    /// The text formatted into the buffer
+    pub fn as_str(&self) -> &str {
+        // Only whole strings are ever copied in
+        unsafe { str::from_utf8_unchecked(&self.buffer[..self.len]) }
+    }
+
+    /// The bytes of the text formatted into the buffer
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    /// The length (in bytes) of the text in the buffer
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if the buffer holds no text
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The most bytes that the buffer can hold
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Empty the buffer, to be reused
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl<const N: usize> Write for FmtBuf<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+
+        if end > N {
+            return Err(fmt::Error);
+        }
+
+        self.buffer[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+
+        Ok(())
+    }
+
+    fn write_fmt(&mut self, args: fmt::Arguments) -> fmt::Result {
+        let len = self.len;
+        let result = fmt::write(self, args);
+
+        // Drop the pieces of the format that did fit
+        if result.is_err() {
+            self.len = len;
+        }
+
+        result
+    }
+}
+
+impl<const N: usize> Default for FmtBuf<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_that_exactly_fits_is_kept() {
+        let mut buffer = FmtBuf::<8>::new();
+
+        let (number, text) = (123, "abcd");
+        write!(buffer, "{}-{}", number, text).unwrap();
+        assert_eq!(buffer.as_str(), "123-abcd");
+        assert_eq!(buffer.len(), buffer.capacity());
+    }
+
+    #[test]
+    fn overflowing_writes_are_undone() {
+        let mut buffer = FmtBuf::<8>::new();
+        buffer.write_str("ab").unwrap();
+
+        // The first pieces of the format fit, but the last does not
+        let (fits, overflows) = ("cd", "efghi");
+        assert_eq!(write!(buffer, "{}{}", fits, overflows), Err(fmt::Error));
+        assert_eq!(buffer.as_str(), "ab");
+        assert_eq!(buffer.write_str("cdefghi"), Err(fmt::Error));
+        assert_eq!(buffer.as_bytes(), b"ab");
+
+        buffer.clear();
+        assert!(buffer.is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+//! Reading in binary data written out as hex text
+
+use crate::millis;
+
+use super::{read_timeout, spin_timed_out, USBSerial};
+
+/// An error encountered while reading in hex text
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HexError {
+    /// A character other than a hex digit or whitespace was received. Holds the character.
+    /// The rest of the line is left in the serial buffer.
+    InvalidChar(u8),
+    /// An odd amount of hex digits was received, so the last byte is missing a digit
+    OddDigits,
+    /// The decoded bytes did not fit in the output. The rest of the line is left in
+    /// the serial buffer.
+    Overflow,
+}
+
+/// The value of a hex digit
+fn digit(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+impl USBSerial {
+    /// Read in hex text, such as `DE AD be ef`, decoding each pair of hex digits into a byte
+    /// of the output, and returning the amount of bytes decoded. Whitespace is ignored, even
+    /// between the two digits of a byte.
+    ///
+    /// Reading stops at a `\n`, which is consumed, or once the serial timeout passes.
+    pub fn read_hex(output: &mut [u8]) -> Result<usize, HexError> {
+        // The start time and duration, for timeout
+        let start_millis = millis();
+        let timeout = read_timeout();
+        // The amount of decoded bytes, and the first digit of the next one
+        let mut count = 0usize;
+        let mut high = None;
+
+        loop {
+            match Self::read_byte() {
+                Some(b'\n') => break,
+                Some(b' ' | b'\t' | b'\r') => {}
+                Some(byte) => {
+                    let value = digit(byte).ok_or(HexError::InvalidChar(byte))?;
+
+                    match high.take() {
+                        None => high = Some(value),
+                        Some(high) => {
+                            *output.get_mut(count).ok_or(HexError::Overflow)? = high << 4 | value;
+                            count += 1;
+                        }
+                    }
+                }
+                None => {
+                    if spin_timed_out(start_millis, timeout) {
+                        break;
+                    }
+                }
+            }
+        }
+
+        match high {
+            Some(_) => Err(HexError::OddDigits),
+            None => Ok(count),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn digit_pairs_are_decoded() {
+        let _serial = test_support::serial();
+        // Even between the two digits of a byte
+        test_support::receive(b"DE AD\tbe e f\r\nf0");
+
+        let mut output = [0u8; 8];
+        assert_eq!(USBSerial::read_hex(&mut output), Ok(4));
+        assert_eq!(output[..4], [0xDE, 0xAD, 0xBE, 0xEF]);
+
+        // Up to the timeout without a line ending
+        assert_eq!(USBSerial::read_hex(&mut output), Ok(1));
+        assert_eq!(output[0], 0xF0);
+    }
+
+    #[test]
+    fn odd_digits_are_caught() {
+        let _serial = test_support::serial();
+        test_support::receive(b"abc\n");
+
+        let mut output = [0u8; 8];
+        assert_eq!(USBSerial::read_hex(&mut output), Err(HexError::OddDigits));
+    }
+
+    #[test]
+    fn invalid_chars_stop_reading() {
+        let _serial = test_support::serial();
+        test_support::receive(b"12 xy\n");
+
+        let mut output = [0u8; 8];
+        assert_eq!(
+            USBSerial::read_hex(&mut output),
+            Err(HexError::InvalidChar(b'x'))
+        );
+        // The rest of the line is left unread
+        assert_eq!(USBSerial::read_line(&mut output), Ok(Some("y")));
+
+        test_support::receive(b"123456\n");
+        assert_eq!(
+            USBSerial::read_hex(&mut output[..2]),
+            Err(HexError::Overflow)
+        );
+    }
+}
@@ -0,0 +1,423 @@
+//! Writing out JSON, such as telemetry for a host, without allocating
+//!
+//! ```ignore
+//! let mut json = JsonWriter::new();
+//! let mut reading = json.object();
+//! reading.field("temp", tempmon::get_temp());
+//! reading.array("samples").value(1).value(2);
+//! reading.finish()?;
+//! // {"temp":45.2,"samples":[1,2]} and a \n
+//! ```
+//!
+//! Objects and arrays borrow the writer they are nested in, so the structure can
+//! only be written in order, and they are closed when dropped, so it is always
+//! complete. The first error is kept and returned by `finish`.
+//!
+//! Each value written straight into the [`JsonWriter`] is ended with a `\n`, so a
+//! stream of them is in the [JSON lines](https://jsonlines.org) format.
+
+use core::fmt::{self, Write};
+
+use super::USBSerialWriter;
+
+/// A writer that escapes everything written through it for use inside of a JSON string
+pub(crate) struct JsonEscaper<'w, W: Write>(pub(crate) &'w mut W);
+
+impl<W: Write> Write for JsonEscaper<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // The start of the run of characters that need no escaping
+        let mut start = 0;
+
+        for (i, c) in s.char_indices() {
+            let escape = match c {
+                '"' => "\\\"",
+                '\\' => "\\\\",
+                '\n' => "\\n",
+                '\r' => "\\r",
+                '\t' => "\\t",
+                // Other control characters have no short escape
+                c if (c as u32) < 0x20 => "",
+                _ => continue,
+            };
+
+            self.0.write_str(&s[start..i])?;
+            start = i + c.len_utf8();
+
+            if escape.is_empty() {
+                write!(self.0, "\\u{:04x}", c as u32)?;
+            } else {
+                self.0.write_str(escape)?;
+            }
+        }
+
+        self.0.write_str(&s[start..])
+    }
+}
+
+/// A value that can be written out as JSON
+pub trait JsonValue {
+    /// Write the value out as JSON
+    fn write_json<W: Write>(&self, f: &mut W) -> fmt::Result;
+}
+
+impl JsonValue for str {
+    fn write_json<W: Write>(&self, f: &mut W) -> fmt::Result {
+        f.write_char('"')?;
+        JsonEscaper(f).write_str(self)?;
+        f.write_char('"')
+    }
+}
+
+impl JsonValue for bool {
+    fn write_json<W: Write>(&self, f: &mut W) -> fmt::Result {
+        f.write_str(if *self { "true" } else { "false" })
+    }
+}
+
+/// `None` is written out as `null`
+impl<T: JsonValue> JsonValue for Option<T> {
+    fn write_json<W: Write>(&self, f: &mut W) -> fmt::Result {
+        match self {
+            Some(value) => value.write_json(f),
+            None => f.write_str("null"),
+        }
+    }
+}
+
+impl<T: JsonValue + ?Sized> JsonValue for &T {
+    fn write_json<W: Write>(&self, f: &mut W) -> fmt::Result {
+        (**self).write_json(f)
+    }
+}
+
+macro_rules! integer_json_value {
+    ($($t:ty),*) => {
+        $(
+            impl JsonValue for $t {
+                fn write_json<W: Write>(&self, f: &mut W) -> fmt::Result {
+                    write!(f, "{}", self)
+                }
+            }
+        )*
+    };
+}
+
+integer_json_value!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+/// JSON has no infinities or NaN, so they are written out as `null`
+impl JsonValue for f32 {
+    fn write_json<W: Write>(&self, f: &mut W) -> fmt::Result {
+        if self.is_finite() {
+            write!(f, "{}", self)
+        } else {
+            f.write_str("null")
+        }
+    }
+}
+
+/// JSON has no infinities or NaN, so they are written out as `null`
+impl JsonValue for f64 {
+    fn write_json<W: Write>(&self, f: &mut W) -> fmt::Result {
+        if self.is_finite() {
+            write!(f, "{}", self)
+        } else {
+            f.write_str("null")
+        }
+    }
+}
+
+/// The writer shared by an object or array and everything nested in it
+struct State<W: Write> {
+    writer: W,
+    result: fmt::Result,
+    /// If the next object or array opened is a separate value
+    top_level: bool,
+}
+
+impl<W: Write> State<W> {
+    /// Take if the object or array being opened is a separate value
+    fn take_top_level(&mut self) -> bool {
+        core::mem::replace(&mut self.top_level, false)
+    }
+
+    /// Write something out, unless an earlier write failed
+    fn write(&mut self, f: impl FnOnce(&mut W) -> fmt::Result) {
+        if self.result.is_ok() {
+            self.result = f(&mut self.writer);
+        }
+    }
+}
+
+/// Writes out JSON values into a writer, the usb serial port by default
+pub struct JsonWriter<W: Write = USBSerialWriter> {
+    state: State<W>,
+}
+
+impl JsonWriter {
+    /// Create a writer writing out onto the usb serial port
+    pub const fn new() -> Self {
+        Self::with_writer(USBSerialWriter {})
+    }
+}
+
+impl Default for JsonWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W: Write> JsonWriter<W> {
+    /// Create a writer writing into another writer
+    pub const fn with_writer(writer: W) -> Self {
+        JsonWriter {
+            state: State {
+                writer,
+                result: Ok(()),
+                top_level: false,
+            },
+        }
+    }
+
+    /// Start writing out an object. Each object and array started here is a
+    /// separate value, ended with a `\n` once it is closed, as in JSON lines.
+    pub fn object(&mut self) -> ObjectWriter<'_, W> {
+        ObjectWriter::open(self.top_level())
+    }
+
+    /// Start writing out an array, as a separate value like with [`object`](JsonWriter::object)
+    pub fn array(&mut self) -> ArrayWriter<'_, W> {
+        ArrayWriter::open(self.top_level())
+    }
+
+    /// Write out a single value, ended with a `\n`
+    pub fn value(&mut self, value: impl JsonValue) -> fmt::Result {
+        let state = self.top_level();
+
+        state.write(|f| value.write_json(f));
+        state.write(|f| f.write_char('\n'));
+
+        state.result
+    }
+
+    /// The state for a new separate value, which does not fail because of an error
+    /// in an earlier one
+    fn top_level(&mut self) -> &mut State<W> {
+        self.state.result = Ok(());
+        self.state.top_level = true;
+
+        &mut self.state
+    }
+
+    /// Get the writer back
+    pub fn into_inner(self) -> W {
+        self.state.writer
+    }
+}
+
+/// Writes out the fields of an object, given out by [`JsonWriter::object`].
+/// The object is closed when this is dropped.
+pub struct ObjectWriter<'a, W: Write> {
+    state: &'a mut State<W>,
+    first: bool,
+    closed: bool,
+    /// If this is a separate value, ended with a `\n`
+    top_level: bool,
+}
+
+impl<'a, W: Write> ObjectWriter<'a, W> {
+    fn open(state: &'a mut State<W>) -> Self {
+        let top_level = state.take_top_level();
+        state.write(|f| f.write_char('{'));
+
+        ObjectWriter {
+            state,
+            first: true,
+            closed: false,
+            top_level,
+        }
+    }
+
+    /// Write the separator and key of the next field
+    fn key(&mut self, key: &str) {
+        let first = core::mem::replace(&mut self.first, false);
+
+        self.state.write(|f| {
+            if !first {
+                f.write_char(',')?;
+            }
+            key.write_json(f)?;
+            f.write_char(':')
+        });
+    }
+
+    /// Write out a field
+    pub fn field(&mut self, key: &str, value: impl JsonValue) -> &mut Self {
+        self.key(key);
+        self.state.write(|f| value.write_json(f));
+
+        self
+    }
+
+    /// Start writing out an object as a field
+    pub fn object(&mut self, key: &str) -> ObjectWriter<'_, W> {
+        self.key(key);
+
+        ObjectWriter::open(self.state)
+    }
+
+    /// Start writing out an array as a field
+    pub fn array(&mut self, key: &str) -> ArrayWriter<'_, W> {
+        self.key(key);
+
+        ArrayWriter::open(self.state)
+    }
+
+    /// Close the object, returning the first error encountered while writing it out
+    pub fn finish(mut self) -> fmt::Result {
+        self.close();
+
+        self.state.result
+    }
+
+    fn close(&mut self) {
+        if !self.closed {
+            self.closed = true;
+            self.state.write(|f| f.write_char('}'));
+
+            if self.top_level {
+                self.state.write(|f| f.write_char('\n'));
+            }
+        }
+    }
+}
+
+impl<W: Write> Drop for ObjectWriter<'_, W> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Writes out the values of an array, given out by [`JsonWriter::array`].
+/// The array is closed when this is dropped.
+pub struct ArrayWriter<'a, W: Write> {
+    state: &'a mut State<W>,
+    first: bool,
+    closed: bool,
+    /// If this is a separate value, ended with a `\n`
+    top_level: bool,
+}
+
+impl<'a, W: Write> ArrayWriter<'a, W> {
+    fn open(state: &'a mut State<W>) -> Self {
+        let top_level = state.take_top_level();
+        state.write(|f| f.write_char('['));
+
+        ArrayWriter {
+            state,
+            first: true,
+            closed: false,
+            top_level,
+        }
+    }
+
+    /// Write the separator before the next value
+    fn separator(&mut self) {
+        if !core::mem::replace(&mut self.first, false) {
+            self.state.write(|f| f.write_char(','));
+        }
+    }
+
+    /// Write out a value
+    pub fn value(&mut self, value: impl JsonValue) -> &mut Self {
+        self.separator();
+        self.state.write(|f| value.write_json(f));
+
+        self
+    }
+
+    /// Start writing out an object as a value
+    pub fn object(&mut self) -> ObjectWriter<'_, W> {
+        self.separator();
+
+        ObjectWriter::open(self.state)
+    }
+
+    /// Start writing out an array as a value
+    pub fn array(&mut self) -> ArrayWriter<'_, W> {
+        self.separator();
+
+        ArrayWriter::open(self.state)
+    }
+
+    /// Close the array, returning the first error encountered while writing it out
+    pub fn finish(mut self) -> fmt::Result {
+        self.close();
+
+        self.state.result
+    }
+
+    fn close(&mut self) {
+        if !self.closed {
+            self.closed = true;
+            self.state.write(|f| f.write_char(']'));
+
+            if self.top_level {
+                self.state.write(|f| f.write_char('\n'));
+            }
+        }
+    }
+}
+
+impl<W: Write> Drop for ArrayWriter<'_, W> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+
+    use super::*;
+
+    #[test]
+    fn strings_are_escaped() {
+        let mut json = JsonWriter::with_writer(String::new());
+
+        json.value("say \"hi\"\\\n\r\t\u{1}\u{1F} é").unwrap();
+        assert_eq!(
+            json.into_inner(),
+            "\"say \\\"hi\\\"\\\\\\n\\r\\t\\u0001\\u001f é\"\n"
+        );
+    }
+
+    #[test]
+    fn keys_are_escaped_too() {
+        let mut json = JsonWriter::with_writer(String::new());
+
+        let mut object = json.object();
+        object.field("a\"b", "c");
+        object.finish().unwrap();
+        assert_eq!(json.into_inner(), "{\"a\\\"b\":\"c\"}\n");
+    }
+
+    #[test]
+    fn values_are_nested_in_order() {
+        let mut json = JsonWriter::with_writer(String::new());
+
+        {
+            let mut reading = json.object();
+            reading.field("temp", 45.5f32).field("ok", true);
+            reading.array("samples").value(1).value(-2);
+            reading.object("none").field("value", None::<u8>);
+        }
+        json.array().value(f32::NAN);
+
+        assert_eq!(
+            json.into_inner(),
+            "{\"temp\":45.5,\"ok\":true,\"samples\":[1,-2],\"none\":{\"value\":null}}\n[null]\n"
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! Helpers for laying out text in columns
+
+use core::fmt::{self, Write};
+
+use super::{USBSerial, WRITE_CHUNK_SIZE};
+
+/// The alignment of text within a column
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Align {
+    /// Pad on the right
+    Left,
+    /// Pad on the left
+    Right,
+    /// Pad evenly on both sides, with the extra space on the right
+    Center,
+}
+
+/// The marker that replaces the end of text too long for its column
+pub(super) const ELLIPSIS: &str = "…";
+
+/// The parts of a string padded to a column: the spaces before it, the visible
+/// part of the string, if it was truncated, and the spaces after it
+fn layout(s: &str, width: usize, align: Align) -> (usize, &str, bool, usize) {
+    let length = s.chars().count();
+
+    if length > width {
+        // Keep one column for the ellipsis
+        if width == 0 {
+            return (0, "", false, 0);
+        }
+
+        let end = s
+            .char_indices()
+            .nth(width - 1)
+            .map_or(s.len(), |(index, _)| index);
+
+        return (0, &s[..end], true, 0);
+    }
+
+    let padding = width - length;
+
+    match align {
+        Align::Left => (0, s, false, padding),
+        Align::Right => (padding, s, false, 0),
+        Align::Center => (padding / 2, s, false, padding - padding / 2),
+    }
+}
+
+/// Write the string into the writer, padded with spaces to `width` characters,
+/// as with [`USBSerial::write_padded`]
+pub(super) fn write_padded_to(
+    f: &mut impl Write,
+    s: &str,
+    width: usize,
+    align: Align,
+) -> fmt::Result {
+    let (before, body, truncated, after) = layout(s, width, align);
+
+    write_repeated_to(f, ' ', before)?;
+    f.write_str(body)?;
+    if truncated {
+        f.write_str(ELLIPSIS)?;
+    }
+    write_repeated_to(f, ' ', after)
+}
+
+/// Write the char into the writer `count` times
+pub(super) fn write_repeated_to(f: &mut impl Write, c: char, count: usize) -> fmt::Result {
+    for _ in 0..count {
+        f.write_char(c)?;
+    }
+
+    Ok(())
+}
+
+impl USBSerial {
+    /// Write a string out onto the serial port, padded with spaces to `width` characters,
+    /// for lining up columns of text whose contents are not known ahead of time. A string
+    /// longer than the width is cut short, with its last visible character replaced by `…`.
+    ///
+    /// Returns the amount of bytes successfully written out. Widths are counted in `char`s,
+    /// so characters that terminals show wider or narrower than one column will misalign.
+    pub fn write_padded(s: &str, width: usize, align: Align) -> usize {
+        let (before, body, truncated, after) = layout(s, width, align);
+        let mut count = Self::write_repeated(' ', before);
+
+        count += Self::write(body);
+        if truncated {
+            count += Self::write(ELLIPSIS);
+        }
+        count += Self::write_repeated(' ', after);
+
+        count
+    }
+
+    /// Write a char out onto the serial port `count` times, such as for drawing separator
+    /// lines, returning the amount of bytes successfully written out.
+    ///
+    /// The char is repeated into a [`WRITE_CHUNK_SIZE`] byte buffer on the stack, which
+    /// is written out as many times as needed. The write is stopped if a chunk is only
+    /// partially written out.
+    pub fn write_repeated(c: char, count: usize) -> usize {
+        let mut encoded = [0u8; 4];
+        let encoded = c.encode_utf8(&mut encoded).as_bytes();
+
+        // Fill the chunk with as many whole chars as fit
+        let per_chunk = WRITE_CHUNK_SIZE / encoded.len();
+        let mut chunk = [0u8; WRITE_CHUNK_SIZE];
+        for slot in chunk.chunks_exact_mut(encoded.len()).take(per_chunk) {
+            slot.copy_from_slice(encoded);
+        }
+
+        let mut remaining = count;
+        // The total count of written out bytes
+        let mut total = 0usize;
+
+        while remaining > 0 {
+            let chars = remaining.min(per_chunk);
+            let length = chars * encoded.len();
+
+            let written = Self::write_bytes(&chunk[..length]);
+            total += written;
+
+            // Stop if the chunk could not be written out in full
+            if written < length {
+                return total;
+            }
+
+            remaining -= chars;
+        }
+
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+
+    use super::*;
+    use crate::test_support;
+
+    /// Pad the string into a new string
+    fn padded(s: &str, width: usize, align: Align) -> String {
+        let mut out = String::new();
+        write_padded_to(&mut out, s, width, align).unwrap();
+
+        out
+    }
+
+    #[test]
+    fn text_is_aligned_in_its_column() {
+        assert_eq!(padded("ab", 6, Align::Left), "ab    ");
+        assert_eq!(padded("ab", 6, Align::Right), "    ab");
+        assert_eq!(padded("ab", 6, Align::Center), "  ab  ");
+        // The extra space goes on the right
+        assert_eq!(padded("ab", 5, Align::Center), " ab  ");
+        assert_eq!(padded("abcd", 4, Align::Right), "abcd");
+    }
+
+    #[test]
+    fn long_text_is_cut_off() {
+        assert_eq!(padded("abcdef", 4, Align::Left), "abc…");
+        assert_eq!(padded("abcdef", 1, Align::Center), "…");
+        assert_eq!(padded("abcdef", 0, Align::Right), "");
+    }
+
+    #[test]
+    fn widths_count_chars() {
+        assert_eq!(padded("né", 4, Align::Right), "  né");
+        assert_eq!(padded("ééé", 3, Align::Left), "ééé");
+        assert_eq!(padded("éééé", 3, Align::Left), "éé…");
+    }
+
+    #[test]
+    fn padded_text_is_written_out() {
+        let _serial = test_support::serial();
+
+        assert_eq!(USBSerial::write_padded("né", 4, Align::Center), 5);
+        assert_eq!(USBSerial::write_padded("abcdef", 4, Align::Left), 6);
+        assert_eq!(test_support::sent(), " né abc…".as_bytes());
+    }
+
+    #[test]
+    fn chars_are_repeated_in_chunks() {
+        let _serial = test_support::serial();
+
+        assert_eq!(USBSerial::write_repeated('-', 40), 40);
+        assert_eq!(test_support::sent(), "-".repeat(40).as_bytes());
+
+        // 3 bytes each, so only whole chars are put in a chunk
+        assert_eq!(USBSerial::write_repeated('═', 20), 60);
+        assert_eq!(test_support::sent(), "═".repeat(20).as_bytes());
+    }
+
+    #[test]
+    fn repeating_stops_at_a_short_write() {
+        let _serial = test_support::serial();
+        test_support::set_write_limit(WRITE_CHUNK_SIZE + 1);
+
+        assert_eq!(
+            USBSerial::write_repeated('x', 3 * WRITE_CHUNK_SIZE),
+            WRITE_CHUNK_SIZE + 1
+        );
+    }
+}
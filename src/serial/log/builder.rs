@@ -0,0 +1,236 @@
+//! Building a logging configuration one option at a time
+
+use log::LevelFilter;
+
+use super::{LogFormat, LogSink, LogTheme, LoggingConfig, HISTORY_CAPACITY};
+
+/// A builder for a [`LoggingConfig`], created with [`LoggingConfig::builder`].
+/// Every option starts out at its default, and each setter takes the same type
+/// as its field, so the options that are an `Option` can be unset with `None`.
+///
+/// ```ignore
+/// USBLogger::init(
+///     LoggingConfig::builder()
+///         .max_level(LevelFilter::Debug)
+///         .format(LogFormat::JsonLine)
+///         .history_lines(8)
+///         .build(),
+/// )?;
+/// ```
+#[derive(Default)]
+pub struct LoggingConfigBuilder {
+    config: LoggingConfig,
+}
+
+impl LoggingConfig {
+    /// Start building a configuration from the defaults
+    pub fn builder() -> LoggingConfigBuilder {
+        LoggingConfigBuilder::default()
+    }
+}
+
+impl LoggingConfigBuilder {
+    /// Set the max log level, see [`max_level`](LoggingConfig::max_level)
+    pub fn max_level(mut self, max_level: LevelFilter) -> Self {
+        self.config.max_level = max_level;
+        self
+    }
+
+    /// Set the filtered targets, see [`filters`](LoggingConfig::filters)
+    pub fn filters(mut self, filters: &'static [(&'static str, Option<LevelFilter>)]) -> Self {
+        self.config.filters = filters;
+        self
+    }
+
+    /// Set if the source location is included, see [`show_location`](LoggingConfig::show_location)
+    pub fn show_location(mut self, show_location: bool) -> Self {
+        self.config.show_location = show_location;
+        self
+    }
+
+    /// Set the colors of each level, see [`theme`](LoggingConfig::theme)
+    pub fn theme(mut self, theme: LogTheme) -> Self {
+        self.config.theme = theme;
+        self
+    }
+
+    /// Set the rate limit, see [`min_interval_ms`](LoggingConfig::min_interval_ms)
+    pub fn min_interval_ms(mut self, min_interval_ms: u32) -> Self {
+        self.config.min_interval_ms = min_interval_ms;
+        self
+    }
+
+    /// Set the format of the lines, see [`format`](LoggingConfig::format)
+    pub fn format(mut self, format: LogFormat) -> Self {
+        self.config.format = format;
+        self
+    }
+
+    /// Set if each line ends with a style reset, see [`reset_after_message`](LoggingConfig::reset_after_message)
+    pub fn reset_after_message(mut self, reset_after_message: bool) -> Self {
+        self.config.reset_after_message = reset_after_message;
+        self
+    }
+
+    /// Set the amount of lines kept in memory, see [`history_lines`](LoggingConfig::history_lines)
+    pub fn history_lines(mut self, history_lines: usize) -> Self {
+        self.config.history_lines = history_lines;
+        self
+    }
+
+    /// Set the text between fields, see [`field_separator`](LoggingConfig::field_separator)
+    pub fn field_separator(mut self, field_separator: &'static str) -> Self {
+        self.config.field_separator = field_separator;
+        self
+    }
+
+    /// Set if `Error` lines blink, see [`blink_errors`](LoggingConfig::blink_errors)
+    pub fn blink_errors(mut self, blink_errors: bool) -> Self {
+        self.config.blink_errors = blink_errors;
+        self
+    }
+
+    /// Set if runs of identical lines are collapsed, see [`dedup`](LoggingConfig::dedup)
+    pub fn dedup(mut self, dedup: bool) -> Self {
+        self.config.dedup = dedup;
+        self
+    }
+
+    /// Set where the lines are written to, see [`sink`](LoggingConfig::sink)
+    pub fn sink(mut self, sink: LogSink) -> Self {
+        self.config.sink = sink;
+        self
+    }
+
+    /// Set if the timestamp is included, see [`show_timestamp`](LoggingConfig::show_timestamp)
+    pub fn show_timestamp(mut self, show_timestamp: bool) -> Self {
+        self.config.show_timestamp = show_timestamp;
+        self
+    }
+
+    /// Set the level that pushes lines out at once, see [`flush_on`](LoggingConfig::flush_on)
+    pub fn flush_on(mut self, flush_on: Option<LevelFilter>) -> Self {
+        self.config.flush_on = flush_on;
+        self
+    }
+
+    /// Set where `Error` and `Warn` lines are written to, see [`error_sink`](LoggingConfig::error_sink)
+    pub fn error_sink(mut self, error_sink: Option<LogSink>) -> Self {
+        self.config.error_sink = error_sink;
+        self
+    }
+
+    /// Set the length messages are cut off after, see [`max_message_len`](LoggingConfig::max_message_len)
+    pub fn max_message_len(mut self, max_message_len: Option<usize>) -> Self {
+        self.config.max_message_len = max_message_len;
+        self
+    }
+
+    /// Set the text that ends each line, see [`line_ending`](LoggingConfig::line_ending)
+    pub fn line_ending(mut self, line_ending: &'static str) -> Self {
+        self.config.line_ending = line_ending;
+        self
+    }
+
+    /// Finish the configuration, with the amount of
+    /// [`history_lines`](LoggingConfig::history_lines) limited to the
+    /// [`HISTORY_CAPACITY`] that can actually be kept
+    pub fn build(mut self) -> LoggingConfig {
+        self.config.history_lines = self.config.history_lines.min(HISTORY_CAPACITY);
+        self.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serial::ansi::Color;
+
+    /// Check that every option of the two configurations is the same
+    fn assert_same(built: &LoggingConfig, expected: &LoggingConfig) {
+        assert_eq!(built.max_level, expected.max_level);
+        assert_eq!(built.filters, expected.filters);
+        assert_eq!(built.show_location, expected.show_location);
+        assert_eq!(built.theme, expected.theme);
+        assert_eq!(built.min_interval_ms, expected.min_interval_ms);
+        assert_eq!(built.format, expected.format);
+        assert_eq!(built.reset_after_message, expected.reset_after_message);
+        assert_eq!(built.history_lines, expected.history_lines);
+        assert_eq!(built.field_separator, expected.field_separator);
+        assert_eq!(built.blink_errors, expected.blink_errors);
+        assert_eq!(built.dedup, expected.dedup);
+        // Writer sinks can not be compared, so only the serial sinks are used
+        assert!(matches!(built.sink, LogSink::Serial));
+        assert!(matches!(expected.sink, LogSink::Serial));
+        assert_eq!(built.show_timestamp, expected.show_timestamp);
+        assert_eq!(built.flush_on, expected.flush_on);
+        assert_eq!(built.error_sink.is_some(), expected.error_sink.is_some());
+        assert_eq!(built.max_message_len, expected.max_message_len);
+        assert_eq!(built.line_ending, expected.line_ending);
+    }
+
+    #[test]
+    fn builder_matches_the_struct_form() {
+        const FILTERS: &[(&str, Option<LevelFilter>)] = &[("app", Some(LevelFilter::Warn))];
+        let theme = LogTheme {
+            error: Color::Cyan,
+            ..LogTheme::default()
+        };
+
+        let built = LoggingConfig::builder()
+            .max_level(LevelFilter::Debug)
+            .filters(FILTERS)
+            .show_location(true)
+            .theme(theme)
+            .min_interval_ms(100)
+            .format(LogFormat::JsonLine)
+            .reset_after_message(false)
+            .history_lines(8)
+            .field_separator("\t")
+            .blink_errors(true)
+            .dedup(true)
+            .show_timestamp(false)
+            .flush_on(None)
+            .error_sink(Some(LogSink::Serial))
+            .max_message_len(Some(64))
+            .line_ending("\n")
+            .build();
+
+        assert_same(
+            &built,
+            &LoggingConfig {
+                max_level: LevelFilter::Debug,
+                filters: FILTERS,
+                show_location: true,
+                theme,
+                min_interval_ms: 100,
+                format: LogFormat::JsonLine,
+                reset_after_message: false,
+                history_lines: 8,
+                field_separator: "\t",
+                blink_errors: true,
+                dedup: true,
+                show_timestamp: false,
+                flush_on: None,
+                error_sink: Some(LogSink::Serial),
+                max_message_len: Some(64),
+                line_ending: "\n",
+                ..LoggingConfig::default()
+            },
+        );
+    }
+
+    #[test]
+    fn builder_starts_out_at_the_defaults() {
+        assert_same(&LoggingConfig::builder().build(), &LoggingConfig::default());
+    }
+
+    #[test]
+    fn history_lines_are_limited_to_the_capacity() {
+        let built = LoggingConfig::builder()
+            .history_lines(HISTORY_CAPACITY + 1)
+            .build();
+
+        assert_eq!(built.history_lines, HISTORY_CAPACITY);
+    }
+}
@@ -0,0 +1,332 @@
+//! Collapsing runs of identical log lines into one
+
+use core::{
+    fmt::{self, Write},
+    ptr,
+};
+
+use log::{Level, Record};
+
+use super::history::HistoryLine;
+
+/// Hashes the text written into it (FNV-1a)
+struct Hasher(u32);
+
+impl Write for Hasher {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 = s.bytes().fold(self.0, |hash, byte| {
+            (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+        });
+
+        Ok(())
+    }
+}
+
+/// Hash the parts of a record that make two lines identical, ignoring the time
+fn key(record: &Record) -> u32 {
+    let mut hasher = Hasher(0x811C_9DC5);
+    write!(
+        hasher,
+        "{}\0{}\0{}",
+        record.level(),
+        record.target(),
+        record.args()
+    )
+    .ok();
+
+    hasher.0
+}
+
+/// Text kept of a line, along with its full length, which it may be too long to keep whole
+struct Text {
+    kept: HistoryLine,
+    len: usize,
+}
+
+impl Text {
+    const fn new() -> Self {
+        Text {
+            kept: HistoryLine::new(),
+            len: 0,
+        }
+    }
+
+    /// Check if the formatted arguments are the same as the text, as far as it was kept
+    fn matches(&self, args: fmt::Arguments) -> bool {
+        let mut matcher = Matcher {
+            expected: self.kept.as_str().as_bytes(),
+            len: 0,
+            matches: true,
+        };
+        fmt::write(&mut matcher, args).ok();
+
+        matcher.matches && matcher.len == self.len
+    }
+}
+
+impl Write for Text {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.len += s.len();
+
+        self.kept.write_str(s)
+    }
+}
+
+/// Compares the text written into it against the expected start of it
+struct Matcher<'a> {
+    expected: &'a [u8],
+    len: usize,
+    matches: bool,
+}
+
+impl Write for Matcher<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if let Some(expected) = self.expected.get(self.len..) {
+            let length = s.len().min(expected.len());
+            self.matches &= expected[..length] == s.as_bytes()[..length];
+        }
+        self.len += s.len();
+
+        Ok(())
+    }
+}
+
+/// The last line logged, and how many times it has been logged in a row
+pub(super) struct Dedup {
+    /// The key of the last line
+    last: Option<u32>,
+    /// How many times the last line has been logged in a row
+    count: u32,
+    /// The parts of the last line, to tell apart lines whose keys collide
+    level: Level,
+    target: Text,
+    location: Option<(&'static str, u32)>,
+    message: Text,
+}
+
+impl Dedup {
+    const fn new() -> Self {
+        Dedup {
+            last: None,
+            count: 0,
+            level: Level::Info,
+            target: Text::new(),
+            location: None,
+            message: Text::new(),
+        }
+    }
+
+    /// Check if the record repeats the last line, in which case it is held back.
+    /// Otherwise the collapsed repeats of the last line are passed to `emit`, and
+    /// the record becomes the new last line.
+    pub(super) fn repeat(&mut self, record: &Record, emit: impl FnOnce(&Record)) -> bool {
+        let key = key(record);
+
+        if self.last == Some(key) && self.is_last(record) {
+            self.count += 1;
+
+            true
+        } else {
+            // Written out before the last line is replaced, so it keeps its own level
+            self.flush(emit);
+            self.keep(record);
+            self.last = Some(key);
+            self.count = 1;
+
+            false
+        }
+    }
+
+    /// Check if the record is identical to the last line, and not only hashes the same
+    fn is_last(&self, record: &Record) -> bool {
+        self.level == record.level()
+            && self.target.matches(format_args!("{}", record.target()))
+            && self.message.matches(*record.args())
+    }
+
+    /// Keep the parts of the record, as the new last line
+    fn keep(&mut self, record: &Record) {
+        self.level = record.level();
+        self.target = Text::new();
+        self.target.write_str(record.target()).ok();
+        self.location = record.file_static().zip(record.line());
+        self.message = Text::new();
+        write!(self.message, "{}", record.args()).ok();
+    }
+
+    /// Pass the held back repeats to `emit` as one record, with the count of times the
+    /// line was logged, if there were any. The next line is then logged as usual, even
+    /// if it is identical.
+    pub(super) fn flush(&mut self, emit: impl FnOnce(&Record)) {
+        if self.count > 1 {
+            let (file, line) = match self.location {
+                Some((file, line)) => (Some(file), Some(line)),
+                None => (None, None),
+            };
+
+            emit(
+                &Record::builder()
+                    .args(format_args!(
+                        "{} (x{})",
+                        self.message.kept.as_str(),
+                        self.count
+                    ))
+                    .level(self.level)
+                    .target(self.target.kept.as_str())
+                    .file_static(file)
+                    .line(line)
+                    .build(),
+            );
+        }
+
+        self.last = None;
+        self.count = 0;
+    }
+}
+
+static mut DEDUP: Dedup = Dedup::new();
+
+/// Access the deduplication state
+///
+/// # Safety
+/// The logger may only be used from one context at a time, so there
+/// must never be two live references to the deduplication state
+pub(super) unsafe fn dedup() -> &'static mut Dedup {
+    &mut *ptr::addr_of_mut!(DEDUP)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::RefCell;
+    use std::{format, string::String, vec::Vec};
+
+    use super::{super::HISTORY_LINE_LENGTH, *};
+
+    /// Run `f` with a record of the message, as the arguments it borrows only live
+    /// until the end of the statement that builds it
+    fn with_record(level: Level, message: &str, f: impl FnOnce(&Record)) {
+        f(&Record::builder()
+            .args(format_args!("{}", message))
+            .level(level)
+            .target("app")
+            .build())
+    }
+
+    /// Log the messages through the deduplication as the logger does, returning the lines
+    /// that come out, and then the lines that come out when the logger is flushed
+    fn log(messages: &[(Level, &str)]) -> (Vec<String>, Vec<String>) {
+        let mut dedup = Dedup::new();
+        let lines = RefCell::new(Vec::new());
+        let emit = |record: &Record| {
+            lines.borrow_mut().push(format!(
+                "{} {}: {}",
+                record.level(),
+                record.target(),
+                record.args()
+            ))
+        };
+
+        for &(level, message) in messages {
+            with_record(level, message, |record| {
+                if !dedup.repeat(record, emit) {
+                    emit(record);
+                }
+            });
+        }
+
+        let logged = lines.take();
+        dedup.flush(emit);
+
+        (logged, lines.take())
+    }
+
+    #[test]
+    fn repeats_are_collapsed_into_one_line() {
+        let (logged, flushed) = log(&[
+            (Level::Warn, "low battery"),
+            (Level::Warn, "low battery"),
+            (Level::Warn, "low battery"),
+            (Level::Info, "charging"),
+        ]);
+
+        assert_eq!(
+            logged,
+            [
+                "WARN app: low battery",
+                "WARN app: low battery (x3)",
+                "INFO app: charging",
+            ]
+        );
+        assert!(flushed.is_empty());
+    }
+
+    #[test]
+    fn held_back_repeats_keep_their_level() {
+        let (logged, _) = log(&[
+            (Level::Error, "overheating"),
+            (Level::Error, "overheating"),
+            (Level::Trace, "tick"),
+        ]);
+
+        assert_eq!(logged[1], "ERROR app: overheating (x2)");
+    }
+
+    #[test]
+    fn flushing_writes_out_the_held_back_repeats() {
+        let (logged, flushed) = log(&[(Level::Info, "ping"), (Level::Info, "ping")]);
+
+        assert_eq!(logged, ["INFO app: ping"]);
+        assert_eq!(flushed, ["INFO app: ping (x2)"]);
+    }
+
+    #[test]
+    fn the_same_message_at_another_level_is_not_a_repeat() {
+        let (logged, _) = log(&[(Level::Info, "ping"), (Level::Warn, "ping")]);
+
+        assert_eq!(logged, ["INFO app: ping", "WARN app: ping"]);
+    }
+
+    #[test]
+    fn colliding_keys_are_not_repeats() {
+        let mut dedup = Dedup::new();
+        let mut emitted = 0;
+
+        with_record(Level::Info, "ping", |record| {
+            assert!(!dedup.repeat(record, |_| emitted += 1))
+        });
+        with_record(Level::Info, "pong", |record| {
+            // As if the hashes of the two collided
+            dedup.last = Some(key(record));
+            assert!(!dedup.repeat(record, |_| emitted += 1))
+        });
+
+        // Only differing past the part of the message that is kept, telling apart by length
+        let long = "x".repeat(HISTORY_LINE_LENGTH);
+        for message in [long.clone(), format!("{}y", long)] {
+            with_record(Level::Info, &message, |record| {
+                dedup.last = Some(key(record));
+                assert!(!dedup.repeat(record, |_| emitted += 1))
+            });
+        }
+        with_record(Level::Info, &format!("{}y", long), |record| {
+            assert!(dedup.repeat(record, |_| emitted += 1))
+        });
+
+        assert_eq!(emitted, 0);
+    }
+
+    #[test]
+    fn identical_records_hash_the_same() {
+        let hash = |message| {
+            let mut hash = 0;
+            with_record(Level::Info, message, |record| hash = key(record));
+
+            hash
+        };
+
+        assert_eq!(hash("a"), hash("a"));
+        assert_ne!(hash("a"), hash("b"));
+    }
+}
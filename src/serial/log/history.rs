@@ -0,0 +1,201 @@
+//! Keeping the most recent log lines in memory, to be dumped out later
+
+use core::{
+    fmt::{self, Display, Write},
+    ptr, str,
+};
+
+/// The most lines that can be kept at once
+pub const HISTORY_CAPACITY: usize = 16;
+
+/// The most bytes of each line that are kept. The rest of a longer line is dropped.
+pub const HISTORY_LINE_LENGTH: usize = 128;
+
+/// One kept line, without its line ending
+#[derive(Clone, Copy)]
+pub(super) struct HistoryLine {
+    text: [u8; HISTORY_LINE_LENGTH],
+    len: usize,
+    truncated: bool,
+}
+
+impl HistoryLine {
+    pub(super) const fn new() -> Self {
+        HistoryLine {
+            text: [0; HISTORY_LINE_LENGTH],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    pub(super) fn as_str(&self) -> &str {
+        // Only whole chars are ever copied in
+        str::from_utf8(&self.text[..self.len]).unwrap_or_default()
+    }
+
+    /// If the line was cut short
+    #[cfg(feature = "persist_logs")]
+    pub(super) fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl Write for HistoryLine {
+    /// Copy in as much of the string as fits, never failing
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let space = HISTORY_LINE_LENGTH - self.len;
+        let mut end = s.len().min(space);
+
+        if end < s.len() {
+            self.truncated = true;
+
+            // Do not split a char in half
+            while !s.is_char_boundary(end) {
+                end -= 1;
+            }
+        }
+
+        self.text[self.len..self.len + end].copy_from_slice(&s.as_bytes()[..end]);
+        self.len += end;
+
+        Ok(())
+    }
+}
+
+/// A ring of the most recently logged lines
+pub(super) struct History {
+    lines: [HistoryLine; HISTORY_CAPACITY],
+    /// The slot that the next line is written into
+    next: usize,
+    /// The amount of slots holding a line
+    len: usize,
+}
+
+impl History {
+    const fn new() -> Self {
+        History {
+            lines: [HistoryLine::new(); HISTORY_CAPACITY],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    /// Keep a line, formatted by `format`, forgetting the oldest line if `limit` lines
+    /// are already kept. Does nothing if the limit is 0.
+    pub(super) fn record(&mut self, limit: usize, format: impl FnOnce(&mut HistoryLine)) {
+        let limit = limit.min(HISTORY_CAPACITY);
+
+        if limit == 0 {
+            return;
+        }
+
+        let line = &mut self.lines[self.next];
+        *line = HistoryLine::new();
+        format(line);
+
+        // The line ending is written out when dumping
+        let text = line.as_str();
+        line.len = text.trim_end_matches(['\r', '\n']).len();
+
+        self.next = (self.next + 1) % limit;
+        self.len = (self.len + 1).min(limit);
+    }
+
+    /// Write out the kept lines, from the oldest to the newest. Lines that were cut
+    /// short are ended with `reset`, so that a style left open can not leak.
+    pub(super) fn dump(
+        &self,
+        writer: &mut impl Write,
+        limit: usize,
+        reset: impl Display,
+        line_ending: &str,
+    ) -> fmt::Result {
+        let limit = limit.min(HISTORY_CAPACITY);
+        // The oldest line is the one that will be overwritten next
+        let oldest = if self.len < limit { 0 } else { self.next };
+
+        for index in 0..self.len {
+            let line = &self.lines[(oldest + index) % limit];
+
+            writer.write_str(line.as_str())?;
+            if line.truncated {
+                write!(writer, "{}", reset)?;
+            }
+            writer.write_str(line_ending)?;
+        }
+
+        Ok(())
+    }
+}
+
+static mut HISTORY: History = History::new();
+
+/// Access the history
+///
+/// # Safety
+/// The logger may only be used from one context at a time, so there
+/// must never be two live references to the history
+pub(super) unsafe fn history() -> &'static mut History {
+    &mut *ptr::addr_of_mut!(HISTORY)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{format, string::String};
+
+    use super::*;
+
+    /// Write out the kept lines, with `<reset>` after the ones cut short
+    fn dump(history: &History, limit: usize) -> String {
+        let mut out = String::new();
+        history.dump(&mut out, limit, "<reset>", "\n").unwrap();
+
+        out
+    }
+
+    #[test]
+    fn only_the_last_lines_are_kept() {
+        let mut history = History::new();
+
+        for i in 0..10 {
+            history.record(4, |line| write!(line, "line {}\r\n", i).unwrap());
+        }
+
+        assert_eq!(dump(&history, 4), "line 6\nline 7\nline 8\nline 9\n");
+    }
+
+    #[test]
+    fn fewer_lines_than_the_limit_are_all_kept() {
+        let mut history = History::new();
+
+        history.record(4, |line| line.write_str("first").unwrap());
+        history.record(4, |line| line.write_str("second").unwrap());
+
+        assert_eq!(dump(&history, 4), "first\nsecond\n");
+    }
+
+    #[test]
+    fn nothing_is_kept_without_a_limit() {
+        let mut history = History::new();
+
+        history.record(0, |line| line.write_str("lost").unwrap());
+
+        assert_eq!(dump(&history, 0), "");
+    }
+
+    #[test]
+    fn long_lines_are_cut_short_on_a_char_boundary() {
+        let mut history = History::new();
+        // One byte short of the length, so the last char does not fit
+        let text = format!("{}é", "a".repeat(HISTORY_LINE_LENGTH - 1));
+
+        history.record(1, |line| line.write_str(&text).unwrap());
+
+        assert_eq!(
+            dump(&history, 1),
+            format!("{}<reset>\n", "a".repeat(HISTORY_LINE_LENGTH - 1))
+        );
+    }
+}
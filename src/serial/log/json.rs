@@ -0,0 +1,108 @@
+//! Formatting of log records as JSON lines
+
+use core::fmt::{self, Write};
+
+use log::Record;
+
+use crate::serial::json::JsonEscaper;
+
+use super::truncate;
+
+/// Write a record out as a single line JSON object, leaving out the `ts` field
+/// if there is no timestamp
+pub(super) fn write_record(
+    writer: &mut impl Write,
+    record: &Record,
+    millis: Option<u32>,
+    show_location: bool,
+    max_message_len: Option<usize>,
+    line_ending: &str,
+) -> fmt::Result {
+    writer.write_char('{')?;
+    if let Some(millis) = millis {
+        write!(writer, "\"ts\":{},", millis)?;
+    }
+    write!(writer, "\"level\":\"{}\",\"target\":\"", record.level())?;
+    JsonEscaper(writer).write_str(record.target())?;
+    writer.write_char('"')?;
+
+    if show_location {
+        if let (Some(file), Some(line)) = (record.file(), record.line()) {
+            writer.write_str(",\"file\":\"")?;
+            JsonEscaper(writer).write_str(file)?;
+            write!(writer, "\",\"line\":{}", line)?;
+        }
+    }
+
+    writer.write_str(",\"msg\":\"")?;
+    // Cut off before escaping, so the escapes are never split
+    truncate::write_message(&mut JsonEscaper(writer), record.args(), max_message_len)?;
+
+    writer.write_str("\"}")?;
+    writer.write_str(line_ending)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+
+    use log::Level;
+
+    use super::*;
+
+    /// Format a record as a JSON line
+    fn format(record: &Record, millis: Option<u32>, show_location: bool) -> String {
+        let mut line = String::new();
+        write_record(&mut line, record, millis, show_location, None, "\n").unwrap();
+
+        line
+    }
+
+    #[test]
+    fn quotes_and_newlines_are_escaped() {
+        let record = Record::builder()
+            .args(format_args!("said \"hi\"\nthen left"))
+            .level(Level::Info)
+            .target("app")
+            .build();
+
+        assert_eq!(
+            format(&record, Some(123), false),
+            "{\"ts\":123,\"level\":\"INFO\",\"target\":\"app\",\
+             \"msg\":\"said \\\"hi\\\"\\nthen left\"}\n"
+        );
+    }
+
+    #[test]
+    fn backslashes_and_control_chars_are_escaped() {
+        let record = Record::builder()
+            .args(format_args!("C:\\dir\ttab\u{7}"))
+            .level(Level::Warn)
+            .target("app")
+            .build();
+
+        assert_eq!(
+            format(&record, None, false),
+            "{\"level\":\"WARN\",\"target\":\"app\",\"msg\":\"C:\\\\dir\\ttab\\u0007\"}\n"
+        );
+    }
+
+    #[test]
+    fn location_is_included() {
+        let record = Record::builder()
+            .args(format_args!("hello"))
+            .level(Level::Error)
+            .target("app")
+            .file(Some("src/main.rs"))
+            .line(Some(7))
+            .build();
+
+        assert_eq!(
+            format(&record, None, true),
+            "{\"level\":\"ERROR\",\"target\":\"app\",\"file\":\"src/main.rs\",\"line\":7,\
+             \"msg\":\"hello\"}\n"
+        );
+    }
+}
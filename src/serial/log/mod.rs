@@ -0,0 +1,939 @@
+//! An implementation for using the log library to log over serial
+//!
+//! **Requires the feature `usb_logging`**
+
+use crate::millis;
+
+use crate::serial::{ansi, ansi::Color};
+use ansi::{EscapeSequence, Style};
+use core::fmt::{self, Write};
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+mod builder;
+mod dedup;
+mod history;
+mod json;
+#[cfg(feature = "persist_logs")]
+mod persist;
+mod ratelimit;
+mod scoped;
+mod sink;
+mod truncate;
+
+pub use builder::LoggingConfigBuilder;
+pub use history::{HISTORY_CAPACITY, HISTORY_LINE_LENGTH};
+pub use scoped::ScopedLogger;
+pub use sink::LogSink;
+use sink::SinkWriter;
+
+/// Logging configuration
+///
+/// Allows a user to specify certain configurations of the logging
+/// system. By default, the max log level is the log level set at
+/// compile time. See the [compile time filters](https://docs.rs/log/0.4.8/log/#compile-time-filters)
+/// section for more information. We also enable logging for all targets.
+/// Set the `filters` collection to specify log targets of interest.
+///
+/// If the default configuration is good for you, use `Default::default()`
+/// as the argument to [`init`](fn.init.html).
+/// Otherwise, set the fields that you need to change, or build the
+/// configuration one option at a time with [`LoggingConfig::builder`].
+pub struct LoggingConfig {
+    /// The max log level
+    ///
+    /// By default, we select the static max level. Users may
+    /// override this if they'd like to bypass the statically-assigned
+    /// max level
+    pub max_level: LevelFilter,
+    /// A list of filtered targets to log.
+    ///
+    /// If set to an empty slice (default), the logger performs no
+    /// filtering. Otherwise, we filter the specified targets by
+    /// the accompanying log level. If there is no level, we default
+    pub filters: &'static [(&'static str, Option<LevelFilter>)],
+    /// Include the source location (`file:line`) of the log statement
+    /// in each line.
+    ///
+    /// Disabled by default. Records without location information are
+    /// logged as if this was disabled.
+    pub show_location: bool,
+    /// The colors used to highlight the level of each line
+    pub theme: LogTheme,
+    /// The minimum time (in milliseconds) between two records of the same
+    /// target and level. Records that arrive sooner are dropped, and the
+    /// amount dropped is logged along with the next record that is let through.
+    /// If no record of that target and level is let through, because the flood
+    /// stopped, the amount is logged along with the next record of any other
+    /// target or level, or when the logger is flushed.
+    ///
+    /// This keeps a hot loop that logs on every iteration from saturating
+    /// the usb link. Set to 0 (default) to let every record through.
+    pub min_interval_ms: u32,
+    /// The format that each line is written out in
+    pub format: LogFormat,
+    /// Write a full style reset at the very end of each line, so that
+    /// styling set by a message can never leak into the lines after it.
+    ///
+    /// Enabled by default.
+    pub reset_after_message: bool,
+    /// The amount of the most recent lines to keep in memory, to be written
+    /// out later with [`USBLogger::dump_recent`], such as after a panic.
+    /// At most [`HISTORY_CAPACITY`] lines are kept, each cut off after
+    /// [`HISTORY_LINE_LENGTH`] bytes.
+    ///
+    /// Set to 0 (default) to keep no lines.
+    pub history_lines: usize,
+    /// The text written out between the level, target, timestamp and
+    /// location of each line in the human readable format, such as `"\t"`
+    /// to import the logs into a spreadsheet.
+    ///
+    /// Defaults to a single space.
+    pub field_separator: &'static str,
+    /// Make `Error` lines blink, for faults that must not be missed in the
+    /// serial monitor. The line is always ended with a style reset, so the
+    /// blinking can not leak into the lines after it.
+    ///
+    /// Disabled by default.
+    pub blink_errors: bool,
+    /// Collapse runs of identical lines (ignoring the time) into one. The
+    /// first line is logged as usual, and the repeats are held back until a
+    /// different line is logged (or the logger is flushed). Then the line is
+    /// logged once more, followed by how many times it was logged in a row, so
+    /// three identical lines come out as `[WARN app 1234]: low battery` and
+    /// then `[WARN app 1240]: low battery (x3)`. The collapsed line is kept in
+    /// the history like any other, has the time it is written out, and its
+    /// message is cut off after [`HISTORY_LINE_LENGTH`] bytes.
+    ///
+    /// Disabled by default. Lines in the [`LogFormat::JsonLine`] format are
+    /// never collapsed, as the count would break the JSON.
+    pub dedup: bool,
+    /// Where the lines are written to. Defaults to the usb serial port.
+    pub sink: LogSink,
+    /// Write the time since boot (in milliseconds) into each line, as the
+    /// `ts` field in the [`LogFormat::JsonLine`] format. Turn this off when
+    /// the lines are read by a tool that already timestamps them.
+    ///
+    /// Enabled by default.
+    pub show_timestamp: bool,
+    /// Push the line out with [`USBSerial::send_now`](crate::serial::USBSerial::send_now)
+    /// right after logging a record at this level or a more severe one, so that
+    /// critical messages reach the host at once while the rest stay buffered.
+    /// Only lines logged to the usb serial port are pushed out.
+    /// Set to `None` to never push lines out early.
+    ///
+    /// Defaults to `Some(LevelFilter::Error)`.
+    pub flush_on: Option<LevelFilter>,
+    /// Where `Error` and `Warn` lines are written to instead of the
+    /// [`sink`](LoggingConfig::sink), such as a hardware serial port that is
+    /// watched separately, so critical lines are not lost when the usb host
+    /// disconnects.
+    ///
+    /// Defaults to `None`, which writes every line to the `sink`.
+    pub error_sink: Option<LogSink>,
+    /// The most bytes of each message that are written out, after which it is
+    /// cut off with an ellipsis (`…`). The level, target, timestamp and location
+    /// are always written out whole. This stops one huge message, such as a
+    /// `Debug` dump, from holding up the usb link.
+    ///
+    /// Defaults to `None`, which writes out messages of any length.
+    pub max_message_len: Option<usize>,
+    /// The text that ends each line, in every format, and in the lines written
+    /// back out from memory, such as by [`USBLogger::dump_recent`]. Use `"\n"`
+    /// for hosts that do not expect a carriage return.
+    ///
+    /// Defaults to `"\r\n"`.
+    pub line_ending: &'static str,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> LoggingConfig {
+        LoggingConfig {
+            max_level: ::log::STATIC_MAX_LEVEL,
+            filters: &[],
+            show_location: false,
+            theme: LogTheme::default(),
+            min_interval_ms: 0,
+            format: LogFormat::default(),
+            reset_after_message: true,
+            history_lines: 0,
+            field_separator: " ",
+            blink_errors: false,
+            dedup: false,
+            sink: LogSink::Serial,
+            show_timestamp: true,
+            flush_on: Some(LevelFilter::Error),
+            error_sink: None,
+            max_message_len: None,
+            line_ending: "\r\n",
+        }
+    }
+}
+
+/// The formats that the logger can write lines out in
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum LogFormat {
+    /// Colored lines, made for reading in a terminal (default)
+    ///
+    /// `[INFO app 1234]: message`
+    #[default]
+    Human,
+    /// One JSON object per line, made for collection by log ingestion tools.
+    /// Colors are never used.
+    ///
+    /// `{"ts":1234,"level":"INFO","target":"app","msg":"message"}`
+    ///
+    /// With [`show_location`](LoggingConfig::show_location), the `file` and
+    /// `line` fields are added before `msg`.
+    JsonLine,
+    /// The human readable format without colors, prefixed with the numeric
+    /// [RFC 5424](https://datatracker.ietf.org/doc/html/rfc5424#section-6.2.1)
+    /// severity of the level, for relaying to a syslog collector
+    ///
+    /// `<6>[INFO app 1234]: message`
+    ///
+    /// | Level   | Severity            |
+    /// |---------|---------------------|
+    /// | `Error` | `3` (error)         |
+    /// | `Warn`  | `4` (warning)       |
+    /// | `Info`  | `6` (informational) |
+    /// | `Debug` | `7` (debug)         |
+    /// | `Trace` | `7` (debug)         |
+    Syslog,
+}
+
+/// The syslog severity of a log level, see [`LogFormat::Syslog`]
+pub const fn syslog_severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// The colors that the logger highlights each log level with
+///
+/// The default theme is the set of colors that the logger has
+/// always used.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LogTheme {
+    /// The color of `Error` lines
+    pub error: Color,
+    /// The color of `Warn` lines
+    pub warn: Color,
+    /// The color of `Info` lines
+    pub info: Color,
+    /// The color of `Debug` lines
+    pub debug: Color,
+    /// The color of `Trace` lines
+    pub trace: Color,
+}
+
+const DEFAULT_THEME: LogTheme = LogTheme {
+    error: Color::LightRed,
+    warn: Color::LightYellow,
+    info: Color::LightBlue,
+    debug: Color::Magenta,
+    trace: Color::LightBlack,
+};
+
+impl LogTheme {
+    /// Get the color that the provided level is highlighted with
+    pub const fn color(&self, level: Level) -> Color {
+        match level {
+            Level::Error => self.error,
+            Level::Warn => self.warn,
+            Level::Info => self.info,
+            Level::Debug => self.debug,
+            Level::Trace => self.trace,
+        }
+    }
+}
+
+impl Default for LogTheme {
+    fn default() -> LogTheme {
+        DEFAULT_THEME
+    }
+}
+
+/// A logger for use with the log crate that outputs its data out over serial
+pub struct USBLogger {
+    enabled: bool,
+    filters: &'static [(&'static str, Option<LevelFilter>)],
+    show_location: bool,
+    theme: LogTheme,
+    min_interval_ms: u32,
+    format: LogFormat,
+    reset_after_message: bool,
+    history_lines: usize,
+    field_separator: &'static str,
+    blink_errors: bool,
+    dedup: bool,
+    show_timestamp: bool,
+    flush_on: Option<LevelFilter>,
+    max_message_len: Option<usize>,
+    line_ending: &'static str,
+}
+
+static mut LOGGER: USBLogger = USBLogger::new();
+
+impl USBLogger {
+    pub(crate) const fn new() -> Self {
+        USBLogger {
+            enabled: false,
+            filters: &[],
+            show_location: false,
+            theme: DEFAULT_THEME,
+            min_interval_ms: 0,
+            format: LogFormat::Human,
+            reset_after_message: true,
+            history_lines: 0,
+            field_separator: " ",
+            blink_errors: false,
+            dedup: false,
+            show_timestamp: true,
+            flush_on: Some(LevelFilter::Error),
+            max_message_len: None,
+            line_ending: "\r\n",
+        }
+    }
+
+    /// Initialize the USBLogger for use with the log crate
+    pub fn init(config: LoggingConfig) -> Result<(), SetLoggerError> {
+        unsafe {
+            LOGGER.enabled = true;
+            LOGGER.filters = config.filters;
+            LOGGER.show_location = config.show_location;
+            LOGGER.theme = config.theme;
+            LOGGER.min_interval_ms = config.min_interval_ms;
+            LOGGER.format = config.format;
+            LOGGER.reset_after_message = config.reset_after_message;
+            LOGGER.history_lines = config.history_lines;
+            LOGGER.field_separator = config.field_separator;
+            LOGGER.blink_errors = config.blink_errors;
+            LOGGER.dedup = config.dedup;
+            LOGGER.show_timestamp = config.show_timestamp;
+            LOGGER.flush_on = config.flush_on;
+            LOGGER.max_message_len = config.max_message_len;
+            LOGGER.line_ending = config.line_ending;
+            sink::set(config.sink, config.error_sink);
+
+            // Copied out, as the closure would capture the partially moved config
+            let max_level = config.max_level;
+
+            log::set_logger(&LOGGER).map(|()| log::set_max_level(max_level))
+        }
+    }
+
+    /// Write out the most recently logged lines that were kept in memory, from the oldest to
+    /// the newest. This gives context to a crash, even if nobody was watching the logs live.
+    ///
+    /// Only lines logged after [`init`](USBLogger::init) are kept, and only if
+    /// [`history_lines`](LoggingConfig::history_lines) is set
+    pub fn dump_recent(writer: &mut impl Write) -> fmt::Result {
+        unsafe {
+            history::history().dump(
+                writer,
+                LOGGER.history_lines,
+                EscapeSequence::new().set_styles(&[Style::Clear]),
+                LOGGER.line_ending,
+            )
+        }
+    }
+
+    /// Write out the most recently logged lines from before the last reset, such as one from
+    /// the watchdog or after a panic, from the oldest to the newest. The last
+    /// [`HISTORY_CAPACITY`] lines are kept, each cut off after [`HISTORY_LINE_LENGTH`] bytes.
+    /// Nothing is written out after power up, as RAM loses its contents without power.
+    ///
+    /// Lines logged since the reset replace the oldest ones, so call this before logging
+    /// anything, or [`clear_persisted`](USBLogger::clear_persisted) once they are dumped.
+    ///
+    /// **Requires the feature `persist_logs`**
+    ///
+    /// # Linker Script
+    /// The lines are kept in the `.noinit` linker section, which the startup code has to
+    /// leave alone. The linker script of the Teensyduino core has no such section, so it
+    /// has to be added to it, as a `NOLOAD` section in RAM that is not cleared:
+    ///
+    /// ```text
+    /// .noinit (NOLOAD) : {
+    ///     *(.noinit*)
+    /// } > RAM
+    /// ```
+    #[cfg(feature = "persist_logs")]
+    pub fn dump_persisted(writer: &mut impl Write) -> fmt::Result {
+        unsafe {
+            persist::persisted().dump(
+                writer,
+                EscapeSequence::new().set_styles(&[Style::Clear]),
+                LOGGER.line_ending,
+            )
+        }
+    }
+
+    /// Forget the lines kept for [`dump_persisted`](USBLogger::dump_persisted)
+    ///
+    /// **Requires the feature `persist_logs`**
+    #[cfg(feature = "persist_logs")]
+    pub fn clear_persisted() {
+        unsafe { persist::persisted() }.clear();
+    }
+
+    /// Format a record the same way that the logger does, for output that does not go
+    /// through the log crate, such as panic reports. The record is not filtered, rate
+    /// limited or kept in the history.
+    #[cfg(feature = "panic_report")]
+    pub(crate) fn format_record(writer: &mut impl Write, record: &Record) -> fmt::Result {
+        unsafe { &*core::ptr::addr_of!(LOGGER) }.write_record(writer, record)
+    }
+
+    /// Returns true if the target is in the filter, else false if the target is
+    /// not in the list of kept targets. If the filter collection is empty, return
+    /// true.
+    fn filtered(&self, metadata: &::log::Metadata) -> bool {
+        if self.filters.is_empty() {
+            true
+        } else if let Some(idx) = self
+            .filters
+            .iter()
+            .position(|&(target, _)| target == metadata.target())
+        {
+            let (_, lvl) = self.filters[idx];
+            lvl.is_none() || lvl.filter(|lvl| metadata.level() <= *lvl).is_some()
+        } else {
+            false
+        }
+    }
+
+    /// Write a record out over serial, keeping it in the history
+    fn emit(&self, writer: &mut impl Write, record: &Record) -> fmt::Result {
+        if self.history_lines > 0 {
+            unsafe { history::history() }.record(self.history_lines, |line| {
+                self.write_record(line, record).ok();
+            });
+        }
+
+        #[cfg(feature = "persist_logs")]
+        unsafe { persist::persisted() }.record(|line| {
+            self.write_record(line, record).ok();
+        });
+
+        self.write_record(writer, record)
+    }
+
+    /// Write a record out into the sink for its level, pushing it out right away if
+    /// its level is at or above [`flush_on`](LoggingConfig::flush_on)
+    fn output(&self, record: &Record) {
+        let mut writer = SinkWriter::new(record.level());
+
+        self.emit(&mut writer, record).ok(); //FIXME: UNWRAP
+
+        if matches!(self.flush_on, Some(flush_on) if record.level() <= flush_on) {
+            writer.flush();
+        }
+    }
+
+    /// Log how many records of the target and level were dropped by the rate limit
+    fn report_suppressed(&self, level: Level, target: &str, suppressed: u32) {
+        self.output(
+            &Record::builder()
+                .args(format_args!("{} messages suppressed", suppressed))
+                .level(level)
+                .target(target)
+                .build(),
+        );
+    }
+
+    /// Format a record into the provided writer
+    fn write_record(&self, writer: &mut impl Write, record: &Record) -> fmt::Result {
+        match self.format {
+            LogFormat::Human => self.write_human(writer, record),
+            LogFormat::JsonLine => json::write_record(
+                writer,
+                record,
+                self.show_timestamp.then(millis),
+                self.show_location,
+                self.max_message_len,
+                self.line_ending,
+            ),
+            LogFormat::Syslog => {
+                write!(writer, "<{}>", syslog_severity(record.level()))?;
+                self.write_human(writer, record)
+            }
+        }
+    }
+
+    /// Format a record into the provided writer in the human readable format
+    fn write_human(&self, writer: &mut impl Write, record: &Record) -> fmt::Result {
+        let level = record.level();
+        // Syslog collectors do not understand colors
+        let colored = self.format == LogFormat::Human;
+        // Everything after the opening bracket blinks, with the level's styles
+        // cleared but blinking started again right after it
+        let blink = colored && self.blink_errors && level == Level::Error;
+        let (level_styles, after_level): (&[Style], &[Style]) = if blink {
+            (&[Style::Blink], &[Style::Clear, Style::Blink])
+        } else {
+            (&[], &[Style::Clear])
+        };
+
+        writer.write_char('[')?;
+        if colored {
+            write!(
+                writer,
+                "{}{}{}",
+                EscapeSequence::new()
+                    .set_fg(self.theme.color(level))
+                    .set_styles(level_styles),
+                level,
+                EscapeSequence::new().set_styles(after_level),
+            )?;
+        } else {
+            write!(writer, "{}", level)?;
+        }
+
+        write!(writer, "{}{}", self.field_separator, record.target())?;
+
+        if self.show_timestamp {
+            write!(writer, "{}{}", self.field_separator, millis())?;
+        }
+
+        if self.show_location {
+            if let (Some(file), Some(line)) = (record.file(), record.line()) {
+                write!(writer, "{}{}:{}", self.field_separator, file, line)?;
+            }
+        }
+
+        writer.write_str("]: ")?;
+        truncate::write_message(writer, record.args(), self.max_message_len)?;
+
+        if colored && (self.reset_after_message || blink) {
+            write!(
+                writer,
+                "{}",
+                EscapeSequence::new().set_styles(&[Style::Clear])
+            )?;
+        }
+
+        writer.write_str(self.line_ending)
+    }
+}
+
+impl Log for USBLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.enabled // We're enabled
+                    && metadata.level() <= log::max_level() // The log level is appropriate
+                    && self.filtered(metadata) // The target is in the filter list
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            if self.min_interval_ms > 0 {
+                let key = ratelimit::key(record.target(), record.level());
+                let now = millis();
+                let rate_limiter = unsafe { ratelimit::rate_limiter() };
+
+                match rate_limiter.check(
+                    key,
+                    record.level(),
+                    record.target(),
+                    now,
+                    self.min_interval_ms,
+                    // A pair that is forgotten to make space for this one
+                    |level, target, count| self.report_suppressed(level, target, count),
+                ) {
+                    // Too soon since the last record
+                    None => return,
+                    Some(0) => {}
+                    Some(suppressed) => {
+                        self.report_suppressed(record.level(), record.target(), suppressed)
+                    }
+                }
+
+                // Floods of other records that have stopped since
+                rate_limiter.report(Some(now), self.min_interval_ms, |level, target, count| {
+                    self.report_suppressed(level, target, count)
+                });
+            }
+
+            if self.dedup
+                && self.format != LogFormat::JsonLine
+                && unsafe { dedup::dedup() }.repeat(record, |repeats| self.output(repeats))
+            {
+                return;
+            }
+
+            self.output(record);
+        }
+    }
+
+    fn flush(&self) {
+        if self.min_interval_ms > 0 {
+            unsafe { ratelimit::rate_limiter() }.report(
+                None,
+                self.min_interval_ms,
+                |level, target, count| self.report_suppressed(level, target, count),
+            );
+        }
+
+        if self.dedup {
+            unsafe { dedup::dedup() }.flush(|repeats| self.output(repeats));
+        }
+
+        sink::flush_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{boxed::Box, string::String, sync::Mutex};
+
+    use super::*;
+    use crate::test_support;
+
+    /// A writer that keeps everything written into it
+    pub(super) struct Capture(&'static Mutex<String>);
+
+    impl Write for Capture {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0.lock().unwrap().push_str(s);
+
+            Ok(())
+        }
+    }
+
+    /// A sink that keeps everything written into it, in the returned string
+    pub(super) fn capture() -> (&'static Mutex<String>, LogSink) {
+        let text = Box::leak(Box::new(Mutex::new(String::new())));
+
+        (text, LogSink::Writer(Box::leak(Box::new(Capture(text)))))
+    }
+
+    /// A logger with the syslog format, which has no colors, without a timestamp
+    fn plain() -> USBLogger {
+        USBLogger {
+            format: LogFormat::Syslog,
+            show_timestamp: false,
+            ..USBLogger::new()
+        }
+    }
+
+    /// A logger with the colored human readable format, without a timestamp
+    fn human() -> USBLogger {
+        USBLogger {
+            format: LogFormat::Human,
+            show_timestamp: false,
+            ..USBLogger::new()
+        }
+    }
+
+    /// Format a record with the logger, as it would be written out
+    fn format(logger: &USBLogger, record: &Record) -> String {
+        let mut line = String::new();
+        logger.write_record(&mut line, record).unwrap();
+
+        line
+    }
+
+    /// Format a record with the logger, with the colors taken out
+    fn format_stripped(logger: &USBLogger, record: &Record) -> String {
+        let line = format(logger, record);
+        let mut out = std::vec![0u8; line.len()];
+        let length = ansi::strip(&line, &mut out);
+        out.truncate(length);
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn location_is_shown_when_enabled() {
+        let format_at = |show_location| {
+            format_stripped(
+                &USBLogger {
+                    show_location,
+                    ..human()
+                },
+                &Record::builder()
+                    .args(format_args!("hello"))
+                    .level(Level::Info)
+                    .target("app")
+                    .file(Some("src/foo.rs"))
+                    .line(Some(42))
+                    .build(),
+            )
+        };
+
+        assert_eq!(format_at(true), "[INFO app src/foo.rs:42]: hello\r\n");
+        assert_eq!(format_at(false), "[INFO app]: hello\r\n");
+    }
+
+    #[test]
+    fn location_is_left_out_when_unknown() {
+        let logger = USBLogger {
+            show_location: true,
+            ..plain()
+        };
+        let record = Record::builder()
+            .args(format_args!("hello"))
+            .level(Level::Info)
+            .target("app")
+            .build();
+
+        assert_eq!(format(&logger, &record), "<6>[INFO app]: hello\r\n");
+    }
+
+    #[test]
+    #[cfg(not(feature = "no_color"))]
+    fn custom_theme_colors_the_level() {
+        let _lock = test_support::lock();
+        let logger = USBLogger {
+            format: LogFormat::Human,
+            show_timestamp: false,
+            theme: LogTheme {
+                error: Color::Cyan,
+                ..LogTheme::default()
+            },
+            ..USBLogger::new()
+        };
+        let line = |level| {
+            format(
+                &logger,
+                &Record::builder()
+                    .args(format_args!("hello"))
+                    .level(level)
+                    .target("app")
+                    .build(),
+            )
+        };
+
+        assert!(line(Level::Error).starts_with("[\u{1B}[36mERROR"));
+        // The other levels keep the default colors
+        assert!(line(Level::Warn).starts_with("[\u{1B}[93mWARN"));
+    }
+
+    #[test]
+    fn default_theme_is_unchanged() {
+        let theme = LogTheme::default();
+
+        assert_eq!(theme.color(Level::Error), Color::LightRed);
+        assert_eq!(theme.color(Level::Warn), Color::LightYellow);
+        assert_eq!(theme.color(Level::Info), Color::LightBlue);
+        assert_eq!(theme.color(Level::Debug), Color::Magenta);
+        assert_eq!(theme.color(Level::Trace), Color::LightBlack);
+    }
+
+    #[test]
+    fn fields_are_split_by_the_separator() {
+        let logger = USBLogger {
+            field_separator: "\t",
+            show_location: true,
+            show_timestamp: true,
+            ..human()
+        };
+        let record = Record::builder()
+            .args(format_args!("hello"))
+            .level(Level::Warn)
+            .target("app")
+            .file(Some("src/foo.rs"))
+            .line(Some(42))
+            .build();
+
+        assert_eq!(
+            format_stripped(&logger, &record),
+            std::format!(
+                "[WARN\tapp\t{}\tsrc/foo.rs:42]: hello\r\n",
+                test_support::MILLIS
+            )
+        );
+        // The syslog format is split the same way
+        assert_eq!(
+            format(
+                &USBLogger {
+                    format: LogFormat::Syslog,
+                    ..logger
+                },
+                &record
+            ),
+            std::format!(
+                "<4>[WARN\tapp\t{}\tsrc/foo.rs:42]: hello\r\n",
+                test_support::MILLIS
+            )
+        );
+    }
+
+    #[test]
+    fn syslog_lines_start_with_the_severity() {
+        let logger = plain();
+        let prefix = |level| {
+            let line = format(
+                &logger,
+                &Record::builder()
+                    .args(format_args!("hello"))
+                    .level(level)
+                    .target("app")
+                    .build(),
+            );
+
+            String::from(&line[..line.find('[').unwrap()])
+        };
+
+        assert_eq!(prefix(Level::Error), "<3>");
+        assert_eq!(prefix(Level::Warn), "<4>");
+        assert_eq!(prefix(Level::Info), "<6>");
+        assert_eq!(prefix(Level::Debug), "<7>");
+        assert_eq!(prefix(Level::Trace), "<7>");
+    }
+
+    #[test]
+    fn human_lines_have_no_severity() {
+        let logger = USBLogger {
+            format: LogFormat::Human,
+            ..plain()
+        };
+        let record = Record::builder()
+            .args(format_args!("hello"))
+            .level(Level::Error)
+            .target("app")
+            .build();
+
+        assert!(format(&logger, &record).starts_with('['));
+    }
+
+    #[test]
+    fn lines_are_written_into_the_sink() {
+        let _lock = test_support::lock();
+        let (text, sink) = capture();
+        unsafe { sink::set(sink, None) };
+
+        plain().output(
+            &Record::builder()
+                .args(format_args!("hello"))
+                .level(Level::Info)
+                .target("app")
+                .build(),
+        );
+        unsafe { sink::set(LogSink::Serial, None) };
+
+        assert_eq!(*text.lock().unwrap(), "<6>[INFO app]: hello\r\n");
+    }
+
+    #[test]
+    fn timestamp_is_left_out_when_disabled() {
+        let record = Record::builder()
+            .args(format_args!("hello"))
+            .level(Level::Info)
+            .target("app")
+            .build();
+        let line = |log_format, show_timestamp| {
+            format(
+                &USBLogger {
+                    format: log_format,
+                    show_timestamp,
+                    ..plain()
+                },
+                &record,
+            )
+        };
+
+        assert_eq!(
+            line(LogFormat::Syslog, true),
+            std::format!("<6>[INFO app {}]: hello\r\n", test_support::MILLIS)
+        );
+        assert_eq!(line(LogFormat::Syslog, false), "<6>[INFO app]: hello\r\n");
+
+        assert!(line(LogFormat::JsonLine, true).starts_with("{\"ts\":"));
+        assert!(!line(LogFormat::JsonLine, false).contains("\"ts\""));
+
+        let human_line = |show_timestamp| {
+            format_stripped(
+                &USBLogger {
+                    show_timestamp,
+                    ..human()
+                },
+                &record,
+            )
+        };
+        assert_eq!(
+            human_line(true),
+            std::format!("[INFO app {}]: hello\r\n", test_support::MILLIS)
+        );
+        assert_eq!(human_line(false), "[INFO app]: hello\r\n");
+    }
+
+    #[test]
+    fn severe_lines_are_pushed_out() {
+        use core::sync::atomic::Ordering;
+
+        let _lock = test_support::lock();
+        let logger = plain();
+        let flushes_after = |level| {
+            let before = test_support::FLUSHES.load(Ordering::Relaxed);
+            logger.output(
+                &Record::builder()
+                    .args(format_args!("hello"))
+                    .level(level)
+                    .target("app")
+                    .build(),
+            );
+
+            test_support::FLUSHES.load(Ordering::Relaxed) - before
+        };
+
+        // Flushing on `Error` by default
+        assert_eq!(flushes_after(Level::Error), 1);
+        assert_eq!(flushes_after(Level::Trace), 0);
+    }
+
+    #[test]
+    fn only_the_message_is_cut_off() {
+        let logger = USBLogger {
+            max_message_len: Some(3),
+            ..plain()
+        };
+        let record = Record::builder()
+            .args(format_args!("hello"))
+            .level(Level::Info)
+            .target("app")
+            .build();
+
+        assert_eq!(format(&logger, &record), "<6>[INFO app]: hel…\r\n");
+        assert_eq!(
+            format_stripped(
+                &USBLogger {
+                    format: LogFormat::Human,
+                    ..logger
+                },
+                &record
+            ),
+            "[INFO app]: hel…\r\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "no_color"))]
+    fn colors_in_the_message_are_reset() {
+        let _lock = test_support::lock();
+        let line = |reset_after_message| {
+            format(
+                &USBLogger {
+                    reset_after_message,
+                    ..human()
+                },
+                &Record::builder()
+                    .args(format_args!(
+                        "{}red",
+                        EscapeSequence::new().set_fg(Color::Red)
+                    ))
+                    .level(Level::Info)
+                    .target("app")
+                    .build(),
+            )
+        };
+
+        assert!(line(true).ends_with("]: \u{1B}[31mred\u{1B}[0m\r\n"));
+        assert!(line(false).ends_with("]: \u{1B}[31mred\r\n"));
+    }
+}
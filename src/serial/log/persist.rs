@@ -0,0 +1,218 @@
+//! Keeping the most recent log lines in memory that survives a reset, placed in
+//! the `.noinit` linker section, see
+//! [`USBLogger::dump_persisted`](super::USBLogger::dump_persisted)
+
+use core::{
+    fmt::{self, Display, Write},
+    ptr, str,
+};
+
+use super::history::{HistoryLine, HISTORY_CAPACITY, HISTORY_LINE_LENGTH};
+
+/// Marks the kept lines as written by this code, rather than left over garbage
+const MAGIC: u32 = 0x4C4F_4753;
+
+/// The kept lines. Only plain integers are used, as the memory is not initialized
+/// after power up, so any bit pattern has to be valid.
+#[repr(C)]
+pub(super) struct Persisted {
+    magic: u32,
+    /// The slot that the next line is written into
+    next: u32,
+    /// The amount of slots holding a line
+    len: u32,
+    /// One bit for each slot, set if its line was cut short
+    truncated: u32,
+    lengths: [u32; HISTORY_CAPACITY],
+    text: [[u8; HISTORY_LINE_LENGTH]; HISTORY_CAPACITY],
+}
+
+impl Persisted {
+    const fn new() -> Self {
+        Persisted {
+            magic: MAGIC,
+            next: 0,
+            len: 0,
+            truncated: 0,
+            lengths: [0; HISTORY_CAPACITY],
+            text: [[0; HISTORY_LINE_LENGTH]; HISTORY_CAPACITY],
+        }
+    }
+
+    /// Check if the lines were written by this code, and are in bounds
+    fn is_valid(&self) -> bool {
+        self.magic == MAGIC
+            && (self.next as usize) < HISTORY_CAPACITY
+            && self.len as usize <= HISTORY_CAPACITY
+            && self
+                .lengths
+                .iter()
+                .all(|&length| length as usize <= HISTORY_LINE_LENGTH)
+    }
+
+    /// Forget all of the kept lines
+    pub(super) fn clear(&mut self) {
+        *self = Persisted::new();
+    }
+
+    /// Keep a line, formatted by `format`, forgetting the oldest line if the buffer is full.
+    /// Garbage left in memory after power up is cleared first.
+    pub(super) fn record(&mut self, format: impl FnOnce(&mut HistoryLine)) {
+        if !self.is_valid() {
+            self.clear();
+        }
+
+        let mut line = HistoryLine::new();
+        format(&mut line);
+
+        // The line ending is written out when dumping
+        let text = line.as_str().trim_end_matches(['\r', '\n']).as_bytes();
+        let slot = self.next as usize;
+
+        self.text[slot][..text.len()].copy_from_slice(text);
+        self.lengths[slot] = text.len() as u32;
+        if line.is_truncated() {
+            self.truncated |= 1 << slot;
+        } else {
+            self.truncated &= !(1 << slot);
+        }
+
+        self.next = ((slot + 1) % HISTORY_CAPACITY) as u32;
+        self.len = (self.len + 1).min(HISTORY_CAPACITY as u32);
+    }
+
+    /// Write out the kept lines, from the oldest to the newest. Lines that were cut
+    /// short are ended with `reset`, so that a style left open can not leak. Nothing
+    /// is written out if the memory holds garbage.
+    pub(super) fn dump(
+        &self,
+        writer: &mut impl Write,
+        reset: impl Display,
+        line_ending: &str,
+    ) -> fmt::Result {
+        if !self.is_valid() {
+            return Ok(());
+        }
+
+        let len = self.len as usize;
+        // The oldest line is the one that will be overwritten next
+        let oldest = if len < HISTORY_CAPACITY {
+            0
+        } else {
+            self.next as usize
+        };
+
+        for index in 0..len {
+            let slot = (oldest + index) % HISTORY_CAPACITY;
+            let text = &self.text[slot][..self.lengths[slot] as usize];
+
+            // A line damaged by a reset part way through writing it is cut at the damage
+            let text = str::from_utf8(text).unwrap_or_else(|err| {
+                str::from_utf8(&text[..err.valid_up_to()]).unwrap_or_default()
+            });
+
+            writer.write_str(text)?;
+            if self.truncated & 1 << slot != 0 {
+                write!(writer, "{}", reset)?;
+            }
+            writer.write_str(line_ending)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[link_section = ".noinit"]
+static mut PERSISTED: Persisted = Persisted::new();
+
+/// Access the kept lines
+///
+/// # Safety
+/// The logger may only be used from one context at a time, so there
+/// must never be two live references to the kept lines
+pub(super) unsafe fn persisted() -> &'static mut Persisted {
+    &mut *ptr::addr_of_mut!(PERSISTED)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{format, string::String, vec::Vec};
+
+    use super::*;
+
+    /// Write out the kept lines, with `<reset>` after the ones cut short
+    fn dump(persisted: &Persisted) -> String {
+        let mut out = String::new();
+        persisted.dump(&mut out, "<reset>", "\n").unwrap();
+
+        out
+    }
+
+    #[test]
+    fn only_the_last_lines_are_kept() {
+        let mut persisted = Persisted::new();
+
+        for i in 0..HISTORY_CAPACITY + 3 {
+            persisted.record(|line| write!(line, "line {}\r\n", i).unwrap());
+        }
+
+        let expected: Vec<String> = (3..HISTORY_CAPACITY + 3)
+            .map(|i| format!("line {}\n", i))
+            .collect();
+        assert_eq!(dump(&persisted), expected.concat());
+    }
+
+    #[test]
+    fn cleared_lines_are_forgotten() {
+        let mut persisted = Persisted::new();
+
+        persisted.record(|line| line.write_str("old").unwrap());
+        persisted.clear();
+        persisted.record(|line| line.write_str("new").unwrap());
+
+        assert_eq!(dump(&persisted), "new\n");
+    }
+
+    #[test]
+    fn garbage_is_not_dumped_and_is_cleared_on_record() {
+        let mut persisted = Persisted::new();
+        persisted.record(|line| line.write_str("kept").unwrap());
+
+        // As if the memory came up with random contents
+        persisted.lengths[0] = u32::MAX;
+        assert_eq!(dump(&persisted), "");
+
+        persisted.record(|line| line.write_str("fresh").unwrap());
+        assert_eq!(dump(&persisted), "fresh\n");
+
+        persisted.magic = 0;
+        assert_eq!(dump(&persisted), "");
+    }
+
+    #[test]
+    fn lines_cut_short_are_reset() {
+        let mut persisted = Persisted::new();
+        let long = "a".repeat(HISTORY_LINE_LENGTH + 1);
+
+        persisted.record(|line| line.write_str(&long).unwrap());
+        persisted.record(|line| line.write_str("short").unwrap());
+
+        assert_eq!(
+            dump(&persisted),
+            format!("{}<reset>\nshort\n", &long[..HISTORY_LINE_LENGTH])
+        );
+    }
+
+    #[test]
+    fn damaged_lines_are_cut_at_the_damage() {
+        let mut persisted = Persisted::new();
+        persisted.record(|line| line.write_str("abcd").unwrap());
+
+        // As if a reset happened part way through writing a char
+        persisted.text[0][2] = 0xFF;
+
+        assert_eq!(dump(&persisted), "ab\n");
+    }
+}
@@ -0,0 +1,292 @@
+//! Dropping log records that repeat faster than a minimum interval
+
+use core::{ptr, str};
+
+use log::Level;
+
+/// The amount of target and level pairs that are tracked at once. When more
+/// pairs are logging, the least recently added pair is forgotten, once the
+/// records it dropped are reported.
+const SLOTS: usize = 8;
+
+/// The most bytes of a target that are kept, for reporting its dropped records
+const TARGET_LENGTH: usize = 32;
+
+/// The state of one target and level pair
+#[derive(Clone, Copy)]
+struct Slot {
+    key: u32,
+    last_millis: u32,
+    suppressed: u32,
+    level: Level,
+    target: [u8; TARGET_LENGTH],
+    target_len: usize,
+}
+
+impl Slot {
+    fn new(key: u32, now: u32, level: Level, target: &str) -> Self {
+        let mut end = target.len().min(TARGET_LENGTH);
+
+        // Do not split a char in half
+        while !target.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        let mut slot = Slot {
+            key,
+            last_millis: now,
+            suppressed: 0,
+            level,
+            target: [0; TARGET_LENGTH],
+            target_len: end,
+        };
+        slot.target[..end].copy_from_slice(&target.as_bytes()[..end]);
+
+        slot
+    }
+
+    fn target(&self) -> &str {
+        // Only whole chars are ever copied in
+        str::from_utf8(&self.target[..self.target_len]).unwrap_or_default()
+    }
+}
+
+/// Tracks when each target and level pair was last logged
+pub(super) struct RateLimiter {
+    slots: [Option<Slot>; SLOTS],
+    next: usize,
+}
+
+impl RateLimiter {
+    const fn new() -> Self {
+        RateLimiter {
+            slots: [None; SLOTS],
+            next: 0,
+        }
+    }
+
+    /// Check if a record with the key can be logged at `now`, if at least `interval`
+    /// milliseconds have passed since the last one. Returns the amount of records that
+    /// were dropped since the last one was logged, or `None` if this one is dropped.
+    ///
+    /// If a pair has to be forgotten to track this one, its level, target and count of
+    /// dropped records are first passed to `report`, as with [`report`](RateLimiter::report).
+    pub(super) fn check(
+        &mut self,
+        key: u32,
+        level: Level,
+        target: &str,
+        now: u32,
+        interval: u32,
+        report: impl FnOnce(Level, &str, u32),
+    ) -> Option<u32> {
+        let slot = self.slots.iter_mut().flatten().find(|slot| slot.key == key);
+
+        match slot {
+            Some(slot) if now.wrapping_sub(slot.last_millis) < interval => {
+                slot.suppressed += 1;
+
+                None
+            }
+            Some(slot) => {
+                let suppressed = slot.suppressed;
+
+                slot.last_millis = now;
+                slot.suppressed = 0;
+
+                Some(suppressed)
+            }
+            None => {
+                // The count would be lost along with the forgotten pair
+                if let Some(forgotten) = &self.slots[self.next] {
+                    if forgotten.suppressed > 0 {
+                        report(forgotten.level, forgotten.target(), forgotten.suppressed);
+                    }
+                }
+
+                self.slots[self.next] = Some(Slot::new(key, now, level, target));
+                self.next = (self.next + 1) % SLOTS;
+
+                Some(0)
+            }
+        }
+    }
+
+    /// Pass the level, target and count of the dropped records of each pair to `report`,
+    /// for the pairs that have not logged for `interval` milliseconds at `now`, so the flood
+    /// of records has stopped. With `now` as `None`, the dropped records of every pair are
+    /// reported. The counts then start over.
+    pub(super) fn report(
+        &mut self,
+        now: Option<u32>,
+        interval: u32,
+        mut report: impl FnMut(Level, &str, u32),
+    ) {
+        for slot in self.slots.iter_mut().flatten() {
+            let quiet = match now {
+                Some(now) => now.wrapping_sub(slot.last_millis) >= interval,
+                None => true,
+            };
+
+            if slot.suppressed > 0 && quiet {
+                report(slot.level, slot.target(), slot.suppressed);
+                slot.suppressed = 0;
+            }
+        }
+    }
+}
+
+/// Hash a target and level into the key a rate limiter tracks them by (FNV-1a)
+pub(super) fn key(target: &str, level: log::Level) -> u32 {
+    target
+        .bytes()
+        .chain(core::iter::once(level as u8))
+        .fold(0x811C_9DC5, |hash, byte| {
+            (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+        })
+}
+
+static mut RATE_LIMITER: RateLimiter = RateLimiter::new();
+
+/// Access the rate limiter
+///
+/// # Safety
+/// There must never be two live references to the rate limiter, so the
+/// logger may only be used from one context at a time
+pub(super) unsafe fn rate_limiter() -> &'static mut RateLimiter {
+    &mut *ptr::addr_of_mut!(RATE_LIMITER)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{string::String, vec::Vec};
+
+    use super::*;
+
+    #[test]
+    fn rapid_records_are_dropped() {
+        let mut limiter = RateLimiter::new();
+        let key = key("app", Level::Info);
+
+        // A record every millisecond, with a 10 millisecond interval
+        let passed = (0..100)
+            .filter(|&now| {
+                limiter
+                    .check(key, Level::Info, "app", now, 10, |_, _, _| {})
+                    .is_some()
+            })
+            .count();
+
+        assert_eq!(passed, 10);
+    }
+
+    #[test]
+    fn next_record_carries_the_dropped_count() {
+        let mut limiter = RateLimiter::new();
+        let key = key("app", Level::Info);
+
+        assert_eq!(
+            limiter.check(key, Level::Info, "app", 0, 10, |_, _, _| {}),
+            Some(0)
+        );
+        for now in 1..5 {
+            assert_eq!(
+                limiter.check(key, Level::Info, "app", now, 10, |_, _, _| {}),
+                None
+            );
+        }
+        assert_eq!(
+            limiter.check(key, Level::Info, "app", 10, 10, |_, _, _| {}),
+            Some(4)
+        );
+        assert_eq!(
+            limiter.check(key, Level::Info, "app", 20, 10, |_, _, _| {}),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn pairs_are_limited_separately() {
+        let mut limiter = RateLimiter::new();
+        let (info, warn) = (key("app", Level::Info), key("app", Level::Warn));
+
+        assert_eq!(
+            limiter.check(info, Level::Info, "app", 0, 10, |_, _, _| {}),
+            Some(0)
+        );
+        assert_eq!(
+            limiter.check(warn, Level::Warn, "app", 1, 10, |_, _, _| {}),
+            Some(0)
+        );
+        assert_eq!(
+            limiter.check(info, Level::Info, "app", 2, 10, |_, _, _| {}),
+            None
+        );
+    }
+
+    #[test]
+    fn stopped_floods_are_reported() {
+        let mut limiter = RateLimiter::new();
+        let key = key("app", Level::Warn);
+        limiter.check(key, Level::Warn, "app", 0, 10, |_, _, _| {});
+        limiter.check(key, Level::Warn, "app", 1, 10, |_, _, _| {});
+        limiter.check(key, Level::Warn, "app", 2, 10, |_, _, _| {});
+
+        let mut reports = Vec::new();
+        let mut report = |now| {
+            limiter.report(now, 10, |level, target: &str, count| {
+                reports.push((level, String::from(target), count))
+            })
+        };
+
+        // Still flooding
+        report(Some(5));
+        // The flood has stopped
+        report(Some(12));
+        // Already reported
+        report(None);
+
+        assert_eq!(reports, [(Level::Warn, String::from("app"), 2)]);
+    }
+
+    #[test]
+    fn flush_reports_every_count() {
+        let mut limiter = RateLimiter::new();
+        let key = key("app", Level::Warn);
+        let mut count = 0;
+
+        limiter.check(key, Level::Warn, "app", 0, 10, |_, _, _| {});
+        limiter.check(key, Level::Warn, "app", 1, 10, |_, _, _| {});
+        limiter.report(None, 10, |_, _, suppressed| count += suppressed);
+
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn forgotten_pairs_are_reported() {
+        let mut limiter = RateLimiter::new();
+        let first = key("first", Level::Info);
+        let mut reports = Vec::new();
+
+        limiter.check(first, Level::Info, "first", 0, 10, |_, _, _| {});
+        limiter.check(first, Level::Info, "first", 1, 10, |_, _, _| {});
+
+        // Filling up the slots forgets the first pair
+        for index in 0..SLOTS {
+            let target = std::format!("other{}", index);
+
+            limiter.check(
+                key(&target, Level::Info),
+                Level::Info,
+                &target,
+                2,
+                10,
+                |level, target, count| reports.push((level, String::from(target), count)),
+            );
+        }
+
+        assert_eq!(reports, [(Level::Info, String::from("first"), 1)]);
+    }
+}
@@ -0,0 +1,110 @@
+//! Loggers that label their lines with the name of a subsystem
+
+use core::ptr;
+
+use log::{Log, Metadata, Record};
+
+use super::{USBLogger, LOGGER};
+
+/// A logger that passes records on to the [`USBLogger`], with the name of a subsystem
+/// prepended to the message, as in `[INFO app 1234]: [wifi] connected`. This gives lines
+/// a stable label that does not depend on the module path in the target, such as when
+/// several libraries log through the same logger.
+///
+/// Records are filtered, formatted and rate limited by the [`USBLogger`], exactly as if they
+/// were logged through it directly, so the logger still has to be [`init`](USBLogger::init)ed.
+///
+/// ```ignore
+/// static WIFI: ScopedLogger = ScopedLogger::new("wifi");
+///
+/// log::info!(logger: WIFI, "connected");
+/// ```
+pub struct ScopedLogger {
+    name: &'static str,
+}
+
+impl ScopedLogger {
+    /// Create a logger labelling its lines with the name
+    pub const fn new(name: &'static str) -> Self {
+        ScopedLogger { name }
+    }
+
+    /// The name that lines are labelled with
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn logger() -> &'static USBLogger {
+        unsafe { &*ptr::addr_of!(LOGGER) }
+    }
+}
+
+impl Log for ScopedLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        Self::logger().enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        Self::logger().log(
+            &Record::builder()
+                .args(format_args!("[{}] {}", self.name, record.args()))
+                .metadata(record.metadata().clone())
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        );
+    }
+
+    fn flush(&self) {
+        Self::logger().flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use log::{Level, LevelFilter};
+
+    use super::{
+        super::{sink, tests::capture, LogFormat, LogSink},
+        *,
+    };
+    use crate::test_support;
+
+    #[test]
+    fn lines_are_labelled_with_the_name() {
+        let _lock = test_support::lock();
+        let (text, sink) = capture();
+        unsafe {
+            sink::set(sink, None);
+            *ptr::addr_of_mut!(LOGGER) = USBLogger {
+                enabled: true,
+                format: LogFormat::Syslog,
+                show_timestamp: false,
+                ..USBLogger::new()
+            };
+        }
+        log::set_max_level(LevelFilter::Info);
+
+        let wifi = ScopedLogger::new("wifi");
+        for level in [Level::Info, Level::Debug] {
+            wifi.log(
+                &Record::builder()
+                    .args(format_args!("connected"))
+                    .level(level)
+                    .target("app")
+                    .build(),
+            );
+        }
+
+        log::set_max_level(LevelFilter::Off);
+        unsafe {
+            sink::set(LogSink::Serial, None);
+            *ptr::addr_of_mut!(LOGGER) = USBLogger::new();
+        }
+
+        // Filtered by the logger's level
+        assert_eq!(*text.lock().unwrap(), "<6>[INFO app]: [wifi] connected\r\n");
+        assert_eq!(wifi.name(), "wifi");
+    }
+}
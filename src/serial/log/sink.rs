@@ -0,0 +1,120 @@
+//! Where the logger writes its lines to
+
+use core::{
+    fmt::{self, Write},
+    ptr,
+};
+
+use log::Level;
+
+use crate::serial::{USBSerialWriter, SERIAL};
+
+/// The destination of the logger's lines, see [`LoggingConfig::sink`](super::LoggingConfig::sink)
+#[derive(Default)]
+pub enum LogSink {
+    /// The usb serial port (default)
+    #[default]
+    Serial,
+    /// Any other writer, such as a hardware serial port, a buffer in memory or a
+    /// custom transport. Lines that fail to be written into it are dropped.
+    Writer(&'static mut dyn Write),
+}
+
+static mut SINK: LogSink = LogSink::Serial;
+static mut ERROR_SINK: Option<LogSink> = None;
+
+/// Set where the logger writes to, and where it writes `Error` and `Warn` lines to
+///
+/// # Safety
+/// The logger may only be used from one context at a time, so the sinks
+/// must not be in use
+pub(super) unsafe fn set(sink: LogSink, error_sink: Option<LogSink>) {
+    *ptr::addr_of_mut!(SINK) = sink;
+    *ptr::addr_of_mut!(ERROR_SINK) = error_sink;
+}
+
+/// Push the written lines out of every sink that holds them back
+pub(super) fn flush_all() {
+    SinkWriter::new(Level::Info).flush();
+
+    if unsafe { (*ptr::addr_of!(ERROR_SINK)).is_some() } {
+        SinkWriter::new(Level::Error).flush();
+    }
+}
+
+/// Writes lines of a level into the sink that the logger was initialized with for it
+pub(super) struct SinkWriter {
+    level: Level,
+}
+
+impl SinkWriter {
+    /// Create a writer for lines of the level
+    pub(super) fn new(level: Level) -> Self {
+        SinkWriter { level }
+    }
+
+    /// The sink for the level
+    ///
+    /// # Safety
+    /// The logger is only used from one context at a time, so the sink is never borrowed twice
+    unsafe fn sink(&self) -> &'static mut LogSink {
+        match &mut *ptr::addr_of_mut!(ERROR_SINK) {
+            Some(sink) if self.level <= Level::Warn => sink,
+            _ => &mut *ptr::addr_of_mut!(SINK),
+        }
+    }
+
+    /// Push the written lines out, if the sink holds them back
+    pub(super) fn flush(&self) {
+        if let LogSink::Serial = unsafe { self.sink() } {
+            SERIAL::send_now();
+        }
+    }
+}
+
+impl Write for SinkWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match unsafe { self.sink() } {
+            LogSink::Serial => USBSerialWriter {}.write_str(s),
+            LogSink::Writer(writer) => writer.write_str(s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+
+    use super::{super::tests::capture, *};
+    use crate::test_support;
+
+    /// Write a line of each level, returning what went into the sink and the error sink
+    fn route(with_error_sink: bool) -> (String, String) {
+        let _lock = test_support::lock();
+        let (text, sink) = capture();
+        let (error_text, error_sink) = capture();
+        unsafe { set(sink, with_error_sink.then_some(error_sink)) };
+
+        for level in [Level::Error, Level::Warn, Level::Info, Level::Debug] {
+            write!(SinkWriter::new(level), "{} ", level).unwrap();
+        }
+        unsafe { set(LogSink::Serial, None) };
+
+        let text = text.lock().unwrap().clone();
+        let error_text = error_text.lock().unwrap().clone();
+
+        (text, error_text)
+    }
+
+    #[test]
+    fn errors_and_warnings_go_to_the_error_sink() {
+        assert_eq!(route(true), ("INFO DEBUG ".into(), "ERROR WARN ".into()));
+    }
+
+    #[test]
+    fn everything_goes_to_the_sink_without_an_error_sink() {
+        assert_eq!(route(false), ("ERROR WARN INFO DEBUG ".into(), "".into()));
+    }
+}
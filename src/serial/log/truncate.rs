@@ -0,0 +1,98 @@
+//! Cutting off messages that are too long
+
+use core::fmt::{self, Arguments, Write};
+
+use crate::serial::layout::ELLIPSIS;
+
+/// Writes at most a limited amount of bytes into a writer, dropping the rest
+struct Truncate<'w, W: Write> {
+    writer: &'w mut W,
+    remaining: usize,
+    truncated: bool,
+}
+
+impl<W: Write> Write for Truncate<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.truncated {
+            return Ok(());
+        }
+
+        let mut end = s.len().min(self.remaining);
+
+        if end < s.len() {
+            self.truncated = true;
+
+            // Do not split a char in half
+            while !s.is_char_boundary(end) {
+                end -= 1;
+            }
+        }
+
+        self.remaining -= end;
+        self.writer.write_str(&s[..end])
+    }
+}
+
+/// Write a message, cut off after `limit` bytes with an ellipsis if there is a limit
+pub(super) fn write_message(
+    writer: &mut impl Write,
+    message: &Arguments,
+    limit: Option<usize>,
+) -> fmt::Result {
+    let limit = match limit {
+        Some(limit) => limit,
+        None => return writer.write_fmt(*message),
+    };
+
+    let mut truncate = Truncate {
+        writer,
+        remaining: limit,
+        truncated: false,
+    };
+    truncate.write_fmt(*message)?;
+
+    if truncate.truncated {
+        truncate.writer.write_str(ELLIPSIS)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+
+    use super::*;
+
+    /// Write the message with the limit
+    fn write(message: Arguments, limit: Option<usize>) -> String {
+        let mut out = String::new();
+        write_message(&mut out, &message, limit).unwrap();
+
+        out
+    }
+
+    #[test]
+    fn long_messages_are_cut_off_with_an_ellipsis() {
+        assert_eq!(write(format_args!("{}", "abcdefghij"), Some(4)), "abcd…");
+    }
+
+    #[test]
+    fn messages_within_the_limit_are_unchanged() {
+        assert_eq!(write(format_args!("abcd"), Some(4)), "abcd");
+        assert_eq!(write(format_args!("abcdefghij"), None), "abcdefghij");
+    }
+
+    #[test]
+    fn messages_are_cut_off_on_a_char_boundary() {
+        // `é` is two bytes, so it does not fit in the last byte
+        assert_eq!(write(format_args!("abcé"), Some(4)), "abc…");
+    }
+
+    #[test]
+    fn the_limit_spans_every_formatted_piece() {
+        assert_eq!(write(format_args!("{}-{}", 123, 456), Some(5)), "123-4…");
+    }
+}
@@ -0,0 +1,185 @@
+//! A numbered menu over the serial port, for bring up and diagnostics tools
+
+use core::fmt::{self, Write};
+
+#[cfg(feature = "ansi")]
+use super::ansi::{EscapeSequence, Style};
+use super::{USBSerial, USBSerialWriter};
+
+/// A menu of entries that each run a function when chosen, shown over the serial port
+///
+/// ```text
+/// Diagnostics
+///   1. Blink the LED
+///   2. Show the temperature
+///   0. Exit
+/// >
+/// ```
+///
+/// An entry is chosen by entering its number, or the first letter of its label (ignoring
+/// case, with the first matching entry chosen). The exit entry is always numbered 0.
+pub struct Menu<'a> {
+    title: &'a str,
+    entries: &'a [(&'a str, fn())],
+    exit_label: &'a str,
+}
+
+impl<'a> Menu<'a> {
+    /// Create a menu with a title and its entries
+    pub const fn new(title: &'a str, entries: &'a [(&'a str, fn())]) -> Self {
+        Menu {
+            title,
+            entries,
+            exit_label: "Exit",
+        }
+    }
+
+    /// Set the label of the exit entry. Defaults to `Exit`.
+    pub const fn exit_label(self, exit_label: &'a str) -> Self {
+        Menu { exit_label, ..self }
+    }
+
+    /// Show the menu and run the chosen entries, until the exit entry is chosen. The
+    /// menu is shown again after every entry that runs.
+    pub fn run(&self) {
+        let mut buffer = [0u8; 32];
+
+        self.show();
+
+        loop {
+            let selection = match USBSerial::read_line(&mut buffer) {
+                Ok(Some(line)) => line.trim(),
+                // Keep waiting for a selection
+                Ok(None) => continue,
+                Err(_) => "",
+            };
+
+            match self.select(selection) {
+                Some(0) => return,
+                Some(number) => {
+                    (self.entries[number - 1].1)();
+                    self.show();
+                }
+                None => {
+                    write!(
+                        USBSerialWriter {},
+                        "Unknown selection `{}`\r\n> ",
+                        selection
+                    )
+                    .ok();
+                    USBSerial::send_now();
+                }
+            }
+        }
+    }
+
+    /// Find the number of the entry that a selection chooses
+    fn select(&self, selection: &str) -> Option<usize> {
+        if let Ok(number) = selection.parse::<usize>() {
+            return Some(number).filter(|&number| number <= self.entries.len());
+        }
+
+        let mut chars = selection.chars();
+        let letter = match (chars.next(), chars.next()) {
+            (Some(letter), None) => letter.to_ascii_lowercase(),
+            _ => return None,
+        };
+        let matches =
+            |label: &str| label.chars().next().map(|c| c.to_ascii_lowercase()) == Some(letter);
+
+        self.entries
+            .iter()
+            .position(|&(label, _)| matches(label))
+            .map(|index| index + 1)
+            .or_else(|| Some(0).filter(|_| matches(self.exit_label)))
+    }
+
+    /// Show the menu over the serial port
+    fn show(&self) {
+        self.render(&mut USBSerialWriter {}).ok();
+        USBSerial::send_now();
+    }
+
+    /// Write the menu out, followed by the prompt
+    fn render(&self, f: &mut impl Write) -> fmt::Result {
+        write!(f, "{}\r\n", self.title)?;
+
+        for (index, &(label, _)) in self.entries.iter().enumerate() {
+            Self::entry(f, index + 1, label)?;
+        }
+        Self::entry(f, 0, self.exit_label)?;
+
+        f.write_str("> ")
+    }
+
+    /// Write an entry out, with its number in bold
+    fn entry(f: &mut impl Write, number: usize, label: &str) -> fmt::Result {
+        #[cfg(feature = "ansi")]
+        write!(
+            f,
+            "  {}{}.{} {}\r\n",
+            EscapeSequence::new().set_styles(&[Style::Bold]),
+            number,
+            EscapeSequence::new().set_styles(&[Style::Clear]),
+            label
+        )?;
+        #[cfg(not(feature = "ansi"))]
+        write!(f, "  {}. {}\r\n", number, label)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::string::String;
+
+    use super::*;
+    use crate::test_support;
+
+    static BLINKS: AtomicUsize = AtomicUsize::new(0);
+
+    fn blink() {
+        BLINKS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    const MENU: Menu = Menu::new("Diagnostics", &[("Blink", blink), ("Beep", blink)]);
+
+    #[test]
+    fn entries_are_chosen_by_number() {
+        assert_eq!(MENU.select("1"), Some(1));
+        assert_eq!(MENU.select("2"), Some(2));
+        assert_eq!(MENU.select("0"), Some(0));
+        // Out of range
+        assert_eq!(MENU.select("3"), None);
+        assert_eq!(MENU.select("-1"), None);
+    }
+
+    #[test]
+    fn entries_are_chosen_by_letter() {
+        // The first match wins
+        assert_eq!(MENU.select("b"), Some(1));
+        assert_eq!(MENU.select("E"), Some(0));
+        assert_eq!(MENU.select("x"), None);
+        assert_eq!(MENU.select("blink"), None);
+        assert_eq!(MENU.select(""), None);
+    }
+
+    #[test]
+    fn chosen_entries_are_run_until_exit() {
+        let _serial = test_support::serial();
+        BLINKS.store(0, Ordering::Relaxed);
+        test_support::receive(b"1\r\nnope\r\n 2 \r\n0\r\n");
+
+        MENU.run();
+
+        assert_eq!(BLINKS.load(Ordering::Relaxed), 2);
+        let sent = String::from_utf8(test_support::sent()).unwrap();
+        assert!(sent.starts_with("Diagnostics\r\n"));
+        assert!(sent.contains("Unknown selection `nope`\r\n> "));
+        assert_eq!(sent.matches("Diagnostics").count(), 3);
+    }
+}
@@ -3,13 +3,16 @@
 use core::{
     convert::TryInto,
     ffi::c_void,
-    fmt::{self, Write},
+    fmt::{self, Display, Write},
+    mem, ptr,
     str::{self, Utf8Error},
+    sync::atomic::AtomicBool,
     sync::atomic::AtomicU32,
+    sync::atomic::AtomicUsize,
     sync::atomic::Ordering,
 };
 
-use crate::millis;
+use crate::{delay, millis};
 
 #[cfg(feature = "usb_logging")]
 pub mod log;
@@ -17,6 +20,57 @@ pub mod log;
 #[cfg(feature = "ansi")]
 pub mod ansi;
 
+#[cfg(feature = "embedded-io")]
+pub mod embedded_io;
+
+#[cfg(feature = "ansi")]
+pub mod statusline;
+
+pub mod at;
+pub mod base64;
+pub mod cobs;
+pub mod crc16;
+pub mod json;
+pub mod table;
+
+mod autobaud;
+mod binary;
+mod checked;
+mod coding;
+mod crlf;
+mod csv;
+mod deadline;
+mod error;
+mod fixed;
+mod flow;
+mod fmtbuf;
+mod hex;
+mod layout;
+mod menu;
+mod prompt;
+mod reader;
+mod sparkline;
+mod spinner;
+mod stale;
+mod stats;
+mod uart;
+
+pub use autobaud::{auto_baud, BAUD_CANDIDATES};
+pub use binary::{BinarySerial, Endian, FrameError};
+pub use checked::{parse_checked_line, ChecksumError};
+pub use csv::CsvError;
+pub use deadline::Deadline;
+pub use error::SerialError;
+pub use flow::{FlowControl, XOFF, XON};
+pub use fmtbuf::FmtBuf;
+pub use hex::HexError;
+pub use layout::Align;
+pub use menu::Menu;
+pub use reader::{SerialReader, READER_BUFFER_SIZE};
+pub use sparkline::{sparkline, Sparkline};
+pub use spinner::{Spinner, BRAILLE_FRAMES, LINE_FRAMES};
+pub use uart::Uart;
+
 extern "C" {
     /// number of bytes available in the receive buffer
     fn usb_serial_available() -> usize;
@@ -41,6 +95,7 @@ const USB_SERIAL_DTR: u8 = 0x01;
 const USB_SERIAL_RTS: u8 = 0x02;
 
 /// A representation of the parity of a serial port
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 #[repr(u8)]
 pub enum Parity {
     /// No Parity
@@ -61,6 +116,30 @@ impl From<u8> for Parity {
     }
 }
 
+/// The line coding settings of the serial port, as set by the PC or Mac.
+/// See [`USBSerial::line_coding`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LineCoding {
+    /// The baud rate, see [`USBSerial::baud`]
+    pub baud: u32,
+    /// The number of stop bits, see [`USBSerial::stop_bits`]
+    pub stop_bits: u8,
+    /// The parity type, see [`USBSerial::parity_type`]
+    pub parity: Parity,
+    /// The number of bits, see [`USBSerial::num_bits`]
+    pub num_bits: u8,
+}
+
+/// The state of the control signals of the serial port, as set by the PC or Mac.
+/// See [`USBSerial::control_lines`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ControlLines {
+    /// The DTR signal, see [`USBSerial::dtr`]
+    pub dtr: bool,
+    /// The RTS signal, see [`USBSerial::rts`]
+    pub rts: bool,
+}
+
 /// A serial USB connection to a host device. Based off of the Arduino Serial class.
 /// Do not create an instance of this, instad use the provided SERIAL static
 ///
@@ -77,7 +156,149 @@ pub type SERIAL = USBSerial;
 /// - [Teensy Serial Reference](https://www.pjrc.com/teensy/td_serial.html)
 pub struct USBSerial {}
 
+/// An error encountered while reading in a line from the serial port
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LineError {
+    /// The line grew past the maximum line length, or the provided buffer,
+    /// before a line ending was received. The rest of the line is left
+    /// in the serial buffer.
+    ///
+    /// See [`USBSerial::set_max_line_length`]
+    Overflow,
+    /// The line read in was not valid UTF-8
+    Utf8(Utf8Error),
+}
+
+impl From<Utf8Error> for LineError {
+    fn from(err: Utf8Error) -> Self {
+        LineError::Utf8(err)
+    }
+}
+
+/// The maximum number of bytes that can be looked at ahead of
+/// the reader with [`USBSerial::peek_bytes`]
+pub const PEEK_BUFFER_SIZE: usize = 64;
+
+/// An error encountered during a [`USBSerial::transaction`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TxError {
+    /// The request could not be written out in full within the write timeout
+    WriteFailed,
+    /// The terminator did not arrive before the timeout. Holds the amount
+    /// of bytes of the response that did arrive.
+    Timeout(usize),
+    /// The response filled the buffer before the terminator arrived
+    Overflow,
+}
+
+/// The size of the chunks that [`USBSerial::write_iter`] and [`USBSerial::write_repeated`]
+/// collect bytes into before writing
+pub const WRITE_CHUNK_SIZE: usize = 32;
+
+/// The size of the transmit buffer of the usb serial C API, made up of 4 buffers of 2048
+/// bytes. This is what [`USBSerial::available_for_write`] returns when nothing is waiting
+/// to be sent.
+pub const TX_BUFFER_SIZE: usize = 8192;
+
+/// The time (in milliseconds) that [`USBSerial::with_retries`] waits between two attempts
+pub const RETRY_DELAY_MS: u32 = 10;
+
+/// Bytes that have been pulled out of the usb serial buffer to be peeked at,
+/// but have not yet been read. All reads consume these before the usb buffer.
+struct PeekBuffer {
+    buffer: [u8; PEEK_BUFFER_SIZE],
+    start: usize,
+    len: usize,
+}
+
+impl PeekBuffer {
+    const fn new() -> Self {
+        PeekBuffer {
+            buffer: [0; PEEK_BUFFER_SIZE],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    /// The bytes waiting to be read
+    fn bytes(&self) -> &[u8] {
+        &self.buffer[self.start..self.start + self.len]
+    }
+
+    /// Remove up to `buffer.len()` bytes from the front, returning the amount taken
+    fn take(&mut self, buffer: &mut [u8]) -> usize {
+        let count = self.len.min(buffer.len());
+
+        buffer[..count].copy_from_slice(&self.buffer[self.start..self.start + count]);
+        self.start += count;
+        self.len -= count;
+
+        if self.len == 0 {
+            self.start = 0;
+        }
+
+        count
+    }
+
+    /// Pull bytes in from the usb buffer until at least `wanted` bytes are held,
+    /// or the usb buffer is empty
+    fn fill(&mut self, wanted: usize) {
+        let wanted = wanted.min(PEEK_BUFFER_SIZE);
+
+        if self.len >= wanted {
+            return;
+        }
+
+        // Move the held bytes to the front to make space at the end
+        self.buffer
+            .copy_within(self.start..self.start + self.len, 0);
+        self.start = 0;
+
+        loop {
+            let missing = (wanted - self.len).min(unsafe { usb_serial_available() });
+            if missing == 0 {
+                return;
+            }
+
+            let new = &mut self.buffer[self.len..self.len + missing];
+            let read = unsafe { usb_serial_read(new.as_mut_ptr() as _, missing) };
+
+            if flow::xon_xoff() {
+                // Flow control bytes are applied, not kept
+                self.len += flow::filter(&mut new[..read]);
+            } else {
+                self.len += read;
+                return;
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+}
+
+static mut PEEKED: PeekBuffer = PeekBuffer::new();
+
+/// Access the peek buffer
+///
+/// # Safety
+/// The serial port may only be used from one context at a time, so there
+/// must never be two live references to the peek buffer
+unsafe fn peeked() -> &'static mut PeekBuffer {
+    &mut *ptr::addr_of_mut!(PEEKED)
+}
+
 static SERIAL_TIMEOUT: AtomicU32 = AtomicU32::new(1000);
+static SERIAL_WRITE_TIMEOUT: AtomicU32 = AtomicU32::new(1000);
+static SERIAL_MAX_LINE_LENGTH: AtomicUsize = AtomicUsize::new(256);
+/// Set by [`USBSerial::set_yield_hook`]
+static mut YIELD_HOOK: fn() = || {};
+/// Set by [`USBSerial::set_nonblocking`]
+static NONBLOCKING: AtomicBool = AtomicBool::new(false);
+/// Set by [`USBSerial::clear_output`] until [`USBSerial::resume_output`]
+static OUTPUT_CLEARED: AtomicBool = AtomicBool::new(false);
 
 impl USBSerial {
     /// Set the serial read in timeout
@@ -85,14 +306,93 @@ impl USBSerial {
         SERIAL_TIMEOUT.store(timeout, Ordering::Relaxed);
     }
 
+    /// Get the serial read in timeout
+    pub fn get_timeout() -> u32 {
+        SERIAL_TIMEOUT.load(Ordering::Relaxed)
+    }
+
+    /// Make the reads that wait for the read in timeout return at once with whatever is
+    /// available, for use in an event loop that must never block. This does not change the
+    /// timeout itself, so [`get_timeout`] still returns it and it is used again once
+    /// non-blocking mode is turned off.
+    ///
+    /// Functions that take their own timeout, such as [`transaction`], still wait for it,
+    /// and so do writes, which use the write timeout. The `embedded-io` traits also keep
+    /// blocking until at least one byte is read, as the traits require.
+    ///
+    /// [`get_timeout`]: USBSerial::get_timeout
+    /// [`transaction`]: USBSerial::transaction
+    pub fn set_nonblocking(nonblocking: bool) {
+        NONBLOCKING.store(nonblocking, Ordering::Relaxed);
+    }
+
+    /// Check if non-blocking mode is on, see [`set_nonblocking`](USBSerial::set_nonblocking)
+    pub fn is_nonblocking() -> bool {
+        NONBLOCKING.load(Ordering::Relaxed)
+    }
+
+    /// Set a function to run on every turn of the busy loops that the serial functions wait
+    /// in, such as for reads waiting for the timeout, so that a cooperative scheduler can run
+    /// other work in the meantime. Defaults to doing nothing.
+    ///
+    /// The hook should return quickly, as the waiting can only finish once it returns. It is
+    /// not run from [`auto_baud`], as that has to sample the pin as often as it can.
+    pub fn set_yield_hook(hook: fn()) {
+        unsafe { *ptr::addr_of_mut!(YIELD_HOOK) = hook };
+    }
+
+    /// Set the serial write out timeout, separate from the read in timeout. This bounds how long
+    /// writes wait for space in a full output buffer.
+    pub fn set_write_timeout(timeout: u32) {
+        SERIAL_WRITE_TIMEOUT.store(timeout, Ordering::Relaxed);
+    }
+
+    /// Get the serial write out timeout
+    pub fn get_write_timeout() -> u32 {
+        SERIAL_WRITE_TIMEOUT.load(Ordering::Relaxed)
+    }
+
+    /// Set the maximum length (in bytes, excluding the line ending) of a line
+    /// that the line readers will accept before giving up with a
+    /// [`LineError::Overflow`]. Defaults to 256.
+    ///
+    /// This guards against a misbehaving host flooding the device with a line
+    /// that never ends.
+    pub fn set_max_line_length(length: usize) {
+        SERIAL_MAX_LINE_LENGTH.store(length, Ordering::Relaxed);
+    }
+
     /// Get the number of bytes (characters) available for reading from the serial port.
     /// This is data that’s already arrived and stored in the serial receive buffer
     ///
+    /// With [XON/XOFF flow control](USBSerial::set_flow_control), flow control bytes that
+    /// have not been filtered out yet are counted too.
+    ///
     /// # See Also
     /// - [Serial.available() - Arduino Reference](https://www.arduino.cc/reference/en/language/functions/communication/serial/available/)
     pub fn avaliable() -> usize {
-        // Call the C API and directly return the data
-        unsafe { usb_serial_available() }
+        // Count any peeked bytes along with the bytes still in the usb buffer
+        let available = unsafe { peeked().len + usb_serial_available() };
+        stale::observe(available);
+
+        available
+    }
+
+    /// Wait until at least `n` bytes are available for reading, or `timeout_ms` milliseconds
+    /// pass, returning if the bytes became available. Useful before a fixed size read.
+    ///
+    /// This busy waits, so nothing else runs in the meantime.
+    pub fn wait_available(n: usize, timeout_ms: u32) -> bool {
+        // The start time, for timeout
+        let start_millis = millis();
+
+        while Self::avaliable() < n {
+            if spin_timed_out(start_millis, timeout_ms) {
+                return false;
+            }
+        }
+
+        true
     }
 
     /// Get the number of bytes (characters) available for writing in the serial buffer
@@ -104,10 +404,22 @@ impl USBSerial {
         unsafe { usb_serial_write_buffer_free() }
     }
 
+    /// How full the transmit buffer is, from 0.0 (empty) to 1.0 (full). Producers can
+    /// use this to throttle their output, such as logging less or sending telemetry less
+    /// often, before writes start to block.
+    pub fn output_pressure() -> f32 {
+        let free = Self::available_for_write().min(TX_BUFFER_SIZE);
+
+        1.0 - free as f32 / TX_BUFFER_SIZE as f32
+    }
+
     /// Clear the input buffer
     pub fn clear() {
-        // Call into the C API
-        unsafe { usb_serial_flush_input() }
+        // Drop any peeked bytes and call into the C API
+        unsafe {
+            peeked().clear();
+            usb_serial_flush_input();
+        }
     }
 
     /// Transmit any buffered data as soon as possible.
@@ -118,12 +430,53 @@ impl USBSerial {
         unsafe { usb_serial_flush_output() }
     }
 
+    /// Abandon the output that is being written, such as to cancel a long print
+    /// when a new command arrives.
+    ///
+    /// The usb serial C API has no way to throw away bytes that are already in
+    /// the transmit buffer, so those are still sent. Instead, writing is stopped
+    /// cooperatively: until [`resume_output`] is called, [`write_bytes`] and every
+    /// write built on it, [`write_char`] included, write nothing, the retrying
+    /// writes give up at once, and [`USBSerialWriter`] fails
+    /// with an `fmt::Error`, which stops a `write!` part way through its message.
+    /// This is mostly useful from an interrupt, or from a callback that is run
+    /// while the long print is going on.
+    ///
+    /// [`resume_output`]: USBSerial::resume_output
+    /// [`write_bytes`]: USBSerial::write_bytes
+    /// [`write_char`]: USBSerial::write_char
+    pub fn clear_output() {
+        OUTPUT_CLEARED.store(true, Ordering::Relaxed);
+    }
+
+    /// Allow writing again after [`clear_output`](USBSerial::clear_output)
+    pub fn resume_output() {
+        OUTPUT_CLEARED.store(false, Ordering::Relaxed);
+    }
+
+    /// Check if the output has been cleared with [`clear_output`](USBSerial::clear_output),
+    /// and not yet resumed
+    pub fn is_output_cleared() -> bool {
+        OUTPUT_CLEARED.load(Ordering::Relaxed)
+    }
+
     /// Returns the next byte (character) of incoming serial data without removing it from the internal serial buffer.
     /// That is, successive calls to peek() will return the same character, as will the next call to read().
     ///
     /// # See Also
     /// - [Serial.peek() - Arduino Reference](https://www.arduino.cc/reference/en/language/functions/communication/serial/peek/)
     pub fn peek() -> Option<char> {
+        // With flow control, the next byte has to be filtered into the peeked bytes
+        if flow::xon_xoff() {
+            let mut byte = [0u8];
+            return (Self::peek_bytes(&mut byte) == 1).then_some(byte[0] as char);
+        }
+
+        // Look at the peeked bytes first, as they will be read first
+        if let Some(&byte) = unsafe { peeked().bytes() }.first() {
+            return Some(byte as char);
+        }
+
         // Call into the C API and store the result
         let result = unsafe { usb_serial_peekchar() };
         // usb_serial_peekchar returns a -1 if there is no char to read, so return a None
@@ -137,37 +490,72 @@ impl USBSerial {
         }
     }
 
+    /// Copy up to `buffer.len()` of the upcoming bytes into the buffer without removing
+    /// them from the serial buffer, returning the amount of bytes copied. Successive
+    /// calls will return the same bytes, as will the next reads.
+    ///
+    /// At most [`PEEK_BUFFER_SIZE`] bytes can be peeked at once. Peeked bytes are moved
+    /// out of the usb buffer into a small internal buffer until they are read.
+    pub fn peek_bytes(buffer: &mut [u8]) -> usize {
+        let peeked = unsafe {
+            let peeked = peeked();
+
+            peeked.fill(buffer.len());
+            peeked.bytes()
+        };
+        let count = peeked.len().min(buffer.len());
+
+        buffer[..count].copy_from_slice(&peeked[..count]);
+
+        count
+    }
+
+    /// Read all of the line coding settings from the PC or Mac at once. Unlike calling
+    /// [`baud`], [`stop_bits`], [`parity_type`] and [`num_bits`] one after another, the
+    /// settings can not change part way through.
+    ///
+    /// [`baud`]: USBSerial::baud
+    /// [`stop_bits`]: USBSerial::stop_bits
+    /// [`parity_type`]: USBSerial::parity_type
+    /// [`num_bits`]: USBSerial::num_bits
+    pub fn line_coding() -> LineCoding {
+        // Copy the line coding out in one go
+        let [baud, format] = unsafe { ptr::read_volatile(ptr::addr_of!(usb_cdc_line_coding)) };
+        let format = format.to_be_bytes();
+
+        LineCoding {
+            baud,
+            // Make 0 = 1
+            stop_bits: if format[0] == 0 { 1 } else { format[0] },
+            parity: format[1].into(),
+            num_bits: format[2],
+        }
+    }
+
     /// Read the baud rate setting from the PC or Mac. Communication is always
     /// performed at full USB speed. The baud rate is useful if you intend to
     /// make a USB to serial bridge, where you need to know what speed the PC
     /// intends the serial communication to use.
     pub fn baud() -> u32 {
-        unsafe { usb_cdc_line_coding[0] }
+        Self::line_coding().baud
     }
 
     /// Read the stop bits setting from the PC or Mac. USB never uses stop bits.
     pub fn stop_bits() -> u8 {
-        // Read in the bytes
-        let b: u8 = unsafe { usb_cdc_line_coding[1].to_be_bytes()[0] };
-        // Make 0 = 1
-        if b == 0 {
-            1
-        } else {
-            b
-        }
+        Self::line_coding().stop_bits
     }
 
     /// Read the parity type setting from the PC or Mac. USB uses CRC checking on all
     /// bulk mode data packets and automatically retransmits corrupted data, so parity
     /// bits are never used.
     pub fn parity_type() -> Parity {
-        unsafe { usb_cdc_line_coding[1].to_be_bytes()[1] }.into()
+        Self::line_coding().parity
     }
 
     /// Read the number of bits setting from the PC or Mac.
     /// USB always communicates 8 bit bytes.
     pub fn num_bits() -> u8 {
-        unsafe { usb_cdc_line_coding[1].to_be_bytes()[2] }
+        Self::line_coding().num_bits
     }
 
     /// Read the DTR signal state. By default, DTR is low when no software has the serial
@@ -175,7 +563,7 @@ impl USBSerial {
     /// this behavior, but for normal software you can use DTR to know when a program is
     /// using the serial port.
     pub fn dtr() -> bool {
-        unsafe { usb_cdc_line_rtsdtr & USB_SERIAL_DTR != 0 }
+        Self::control_lines().dtr
     }
 
     /// Read the RTS signal state. USB includes flow control automatically, so you do not
@@ -187,7 +575,18 @@ impl USBSerial {
     /// For programs that use RTS to signal some useful information, you can read it with this
     /// function.
     pub fn rts() -> bool {
-        unsafe { usb_cdc_line_rtsdtr & USB_SERIAL_RTS != 0 }
+        Self::control_lines().rts
+    }
+
+    /// Read both the DTR and RTS signal states at once, so that they can not change
+    /// in between reading one and the other.
+    pub fn control_lines() -> ControlLines {
+        let lines = unsafe { ptr::read_volatile(ptr::addr_of!(usb_cdc_line_rtsdtr)) };
+
+        ControlLines {
+            dtr: lines & USB_SERIAL_DTR != 0,
+            rts: lines & USB_SERIAL_RTS != 0,
+        }
     }
 
     /// Read in the bytes from a serial buffer for the duration of the timeout, or until the buffer is full
@@ -201,7 +600,7 @@ impl USBSerial {
 
         loop {
             // Increment the read in bytes by the amount that had been filled into the buffer
-            count += unsafe { usb_serial_read(buffer.as_ptr().add(count) as _, length - count) };
+            count += Self::read_raw(&mut buffer[count..]);
 
             // Break the loop if the buffer is full
             if count >= length {
@@ -209,19 +608,92 @@ impl USBSerial {
             }
 
             // Stop the loop if the timeout is reached
-            if millis() - start_millis < SERIAL_TIMEOUT.load(Ordering::Relaxed) {
+            if spin_timed_out(start_millis, read_timeout()) {
                 return count;
             }
         }
     }
 
+    /// Read in bytes from the serial buffer until the buffer is full, or no new byte has
+    /// arrived for `gap_ms` milliseconds, returning the amount of bytes read in.
+    ///
+    /// Many serial protocols mark the end of a message with the line going idle, which
+    /// this detects. Unlike the serial timeout, the time limit restarts every time a byte
+    /// arrives, starting from when this is called.
+    pub fn read_bytes_interbyte(buffer: &mut [u8], gap_ms: u32) -> usize {
+        // The current count of read in bytes
+        let mut count = 0usize;
+        // The time the last byte arrived, for the gap
+        let mut last_millis = millis();
+
+        while count < buffer.len() {
+            let read = Self::read_raw(&mut buffer[count..]);
+
+            if read > 0 {
+                count += read;
+                last_millis = millis();
+            } else if spin_timed_out(last_millis, gap_ms) {
+                break;
+            }
+        }
+
+        count
+    }
+
+    /// Fill the buffer with bytes from the serial buffer, waiting for the duration of the timeout.
+    ///
+    /// Returns `Ok(())` if the buffer was filled, or `Err` with the amount of bytes that
+    /// were read in before the timeout was reached.
+    pub fn read_bytes_exact_timeout(buffer: &mut [u8]) -> Result<(), usize> {
+        let count = Self::read_bytes_timeout(buffer);
+
+        if count == buffer.len() {
+            Ok(())
+        } else {
+            Err(count)
+        }
+    }
+
     /// Read in the bytes from the serial buffer in one shot without a timeout
     pub fn read_bytes(buffer: &mut [u8]) -> usize {
         // Calculate the avaliable bytes to read in by taking the minimum of
         // the bytes in the serial buffer and in the provided buffer
         let avaliable_bytes = Self::avaliable().min(buffer.len());
 
-        unsafe { usb_serial_read(buffer.as_mut_ptr() as _, avaliable_bytes) }
+        Self::read_raw(&mut buffer[..avaliable_bytes])
+    }
+
+    /// Read in bytes from the peeked bytes and then the usb buffer, returning the amount read
+    fn read_raw(buffer: &mut [u8]) -> usize {
+        // With flow control, every byte has to be filtered through the peeked bytes
+        if flow::xon_xoff() {
+            let mut count = 0usize;
+
+            while count < buffer.len() {
+                let peeked = unsafe { peeked() };
+
+                peeked.fill(buffer.len() - count);
+                let taken = peeked.take(&mut buffer[count..]);
+                if taken == 0 {
+                    break;
+                }
+                count += taken;
+            }
+
+            return stats::count_read(count);
+        }
+
+        let peeked = unsafe { peeked().take(buffer) };
+
+        if peeked == buffer.len() {
+            stats::count_read(peeked)
+        } else {
+            let rest = &mut buffer[peeked..];
+
+            stats::count_read(
+                peeked + unsafe { usb_serial_read(rest.as_mut_ptr() as _, rest.len()) },
+            )
+        }
     }
 
     /// Read in a string from the usb buffer with retrying to fill the buffer all the way
@@ -251,47 +723,526 @@ impl USBSerial {
         }
     }
 
-    /// Read in one char of data from the serial port
-    pub fn read() -> Option<char> {
+    /// Read in a line of text terminated by a `\n` (or `\r\n`), waiting for the
+    /// duration of the timeout for the line to arrive. The line ending is
+    /// consumed but not included in the returned string.
+    ///
+    /// Returns `Ok(None)` if nothing was received before the timeout. If only part
+    /// of a line arrives before the timeout, that part is returned.
+    ///
+    /// The line may be at most [`set_max_line_length`] bytes long, or the length of
+    /// the provided buffer, whichever is smaller.
+    ///
+    /// [`set_max_line_length`]: USBSerial::set_max_line_length
+    pub fn read_line(buffer: &mut [u8]) -> Result<Option<&str>, LineError> {
+        match Self::read_line_raw(buffer)? {
+            Some(length) => Ok(Some(str::from_utf8(&buffer[..length])?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Read in a line as with [`read_line`], but only return it once no more bytes have
+    /// arrived for `quiet_ms` milliseconds after it. This keeps a pasted block of text that
+    /// arrives in fragments from being acted on before all of it is in, unlike the inter-byte
+    /// gap of [`read_bytes_interbyte`], which ends a read as soon as the line goes quiet.
+    ///
+    /// The bytes that arrive after the line are left in the serial buffer. If they keep on
+    /// arriving, the line is returned anyway once the serial timeout has passed since it was
+    /// read in. Returns `None` if no line was received, or it could not be read in.
+    ///
+    /// [`read_line`]: USBSerial::read_line
+    /// [`read_bytes_interbyte`]: USBSerial::read_bytes_interbyte
+    pub fn read_line_debounced(buffer: &mut [u8], quiet_ms: u32) -> Option<&str> {
+        let line = Self::read_line(buffer).ok()??;
+
+        // The start time, for timeout
+        let start_millis = millis();
+        let timeout = read_timeout();
+        // The time the amount of waiting bytes last changed, for the quiet time
+        let mut last_millis = start_millis;
+        let mut available = Self::avaliable();
+
+        while !timed_out(last_millis, quiet_ms) && !spin_timed_out(start_millis, timeout) {
+            let now_available = Self::avaliable();
+
+            if now_available != available {
+                available = now_available;
+                last_millis = millis();
+            }
+        }
+
+        Some(line)
+    }
+
+    /// Read in up to `max` lines (or `lines.len()`, whichever is smaller) one after another,
+    /// as with [`read_line`], storing them next to each other in the buffer. Each line is
+    /// put into `lines`, and the amount of lines read in is returned. This is handy for
+    /// receiving a block of lines, such as a pasted config, in one call.
+    ///
+    /// Every line gets the full timeout to arrive. Reading stops early when no line arrives
+    /// in time, when a line does not fit in the rest of the buffer (the rest of that line is
+    /// left in the serial buffer), or when a line is not valid UTF-8 (that line is dropped).
+    ///
+    /// [`read_line`]: USBSerial::read_line
+    pub fn read_lines<'a>(buffer: &'a mut [u8], lines: &mut [&'a str], max: usize) -> usize {
+        let limit = max.min(lines.len());
+        let mut rest = buffer;
+        let mut count = 0usize;
+
+        while count < limit {
+            let length = match Self::read_line_raw(rest) {
+                Ok(Some(length)) => length,
+                _ => break,
+            };
+
+            let (line, after) = mem::take(&mut rest).split_at_mut(length);
+            rest = after;
+
+            match str::from_utf8(line) {
+                Ok(line) => lines[count] = line,
+                Err(_) => break,
+            }
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Read in a line into the buffer as with [`read_line`](USBSerial::read_line),
+    /// returning its length without checking that it is valid UTF-8
+    fn read_line_raw(buffer: &mut [u8]) -> Result<Option<usize>, LineError> {
+        // The longest line that can be accepted
+        let limit = buffer
+            .len()
+            .min(SERIAL_MAX_LINE_LENGTH.load(Ordering::Relaxed));
+        // The current count of read in bytes
+        let mut count = 0usize;
+        // A `\r` that has been read in but not stored, as it could be part of a `\r\n`
+        let mut carriage_return = false;
+        // The start time and duration, for timeout
+        let start_millis = millis();
+        let timeout = read_timeout();
+
+        loop {
+            // Look at the byte first, so a byte that does not fit is left unread
+            let mut next = [0u8];
+            if Self::peek_bytes(&mut next) == 0 {
+                if spin_timed_out(start_millis, timeout) {
+                    if count == 0 && !carriage_return {
+                        return Ok(None);
+                    }
+
+                    break;
+                }
+
+                continue;
+            }
+
+            match next[0] {
+                b'\n' => {
+                    Self::read_byte();
+                    break;
+                }
+                byte => {
+                    // The held back `\r` turned out to be part of the line
+                    let needed = if byte == b'\r' { 0 } else { 1 } + usize::from(carriage_return);
+
+                    if count + needed > limit {
+                        return Err(LineError::Overflow);
+                    }
+
+                    if carriage_return {
+                        buffer[count] = b'\r';
+                        count += 1;
+                    }
+
+                    carriage_return = byte == b'\r';
+                    if !carriage_return {
+                        buffer[count] = byte;
+                        count += 1;
+                    }
+
+                    Self::read_byte();
+                }
+            }
+        }
+
+        // The carriage return of a `\r\n` line ending, or a trailing one, is left out
+        Ok(Some(count))
+    }
+
+    /// Send a request and wait for the response, for request/response protocols such as the
+    /// AT commands of a modem.
+    ///
+    /// Clears the input buffer, writes out (retrying for the duration of the write timeout)
+    /// and flushes the request, then reads the response
+    /// into the buffer until the `terminator` byte arrives or `timeout_ms` milliseconds pass.
+    /// On success, returns the length of the response, including the terminator.
+    pub fn transaction(
+        request: &[u8],
+        response: &mut [u8],
+        terminator: u8,
+        timeout_ms: u32,
+    ) -> Result<usize, TxError> {
+        // Drop anything left over so it is not mistaken for the response
+        Self::clear();
+
+        if Self::write_bytes_timeout(request) != request.len() {
+            return Err(TxError::WriteFailed);
+        }
+        Self::send_now();
+
+        // The current count of read in bytes
+        let mut count = 0usize;
+        // The start time, for timeout
+        let start_millis = millis();
+
+        loop {
+            match Self::read_byte() {
+                Some(byte) => {
+                    if count >= response.len() {
+                        return Err(TxError::Overflow);
+                    }
+
+                    response[count] = byte;
+                    count += 1;
+
+                    if byte == terminator {
+                        return Ok(count);
+                    }
+                }
+                None => {
+                    if spin_timed_out(start_millis, timeout_ms) {
+                        return Err(TxError::Timeout(count));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run a serial operation, such as a [`transaction`](USBSerial::transaction), up to
+    /// `attempts` times until it succeeds, for devices that occasionally miss a command.
+    /// Returns the first success, or the error of the last attempt.
+    ///
+    /// Before each retry, this waits [`RETRY_DELAY_MS`] milliseconds and then clears the
+    /// input buffer, so that a late response to the failed attempt is not mistaken for the
+    /// response to the next one. The operation is always run at least once.
+    pub fn with_retries<R, E>(attempts: u32, mut f: impl FnMut() -> Result<R, E>) -> Result<R, E> {
+        let mut result = f();
+
+        for _ in 1..attempts {
+            if result.is_ok() {
+                break;
+            }
+
+            delay(RETRY_DELAY_MS);
+            Self::clear();
+
+            result = f();
+        }
+
+        result
+    }
+
+    /// Synchronize with the host at startup, so that early output is not lost while the
+    /// terminal is still connecting.
+    ///
+    /// Writes out and flushes the banner, then waits for the host to send the `ready_byte`,
+    /// throwing away any other bytes that arrive first. Returns whether the ready byte
+    /// arrived within `timeout_ms` milliseconds.
+    pub fn handshake(banner: &str, ready_byte: u8, timeout_ms: u32) -> bool {
+        Self::write_bytes_timeout(banner.as_bytes());
+        Self::send_now();
+
+        Self::skip_until(ready_byte, timeout_ms)
+    }
+
+    /// Read in and throw away bytes until the marker byte arrives, for getting back in
+    /// step with a protocol whose messages start with a known byte. The marker is consumed
+    /// too. Returns whether the marker arrived within `timeout_ms` milliseconds.
+    pub fn skip_until(marker: u8, timeout_ms: u32) -> bool {
+        // The start time, for timeout
+        let start_millis = millis();
+
+        loop {
+            if Self::read_byte() == Some(marker) {
+                return true;
+            }
+
+            if spin_timed_out(start_millis, timeout_ms) {
+                return false;
+            }
+        }
+    }
+
+    /// Echo everything that is received back out, passing each byte through `on_byte` on the
+    /// way. This is a diagnostic utility for bringing up a serial link; the closure can be used
+    /// to transform (such as uppercasing), filter or count the bytes.
+    ///
+    /// Loops until `stop` returns true, which is checked before each batch of received bytes.
+    /// Every batch is flushed out as soon as it is echoed.
+    pub fn run_echo(mut on_byte: impl FnMut(u8) -> u8, mut stop: impl FnMut() -> bool) {
+        let mut buffer = [0u8; WRITE_CHUNK_SIZE];
+
+        while !stop() {
+            let count = Self::read_bytes(&mut buffer);
+
+            if count > 0 {
+                for byte in &mut buffer[..count] {
+                    *byte = on_byte(*byte);
+                }
+
+                Self::write_bytes(&buffer[..count]);
+                Self::send_now();
+            } else {
+                yield_now();
+            }
+        }
+    }
+
+    /// Pass every byte that is waiting to be read through `f`, and write back out the bytes
+    /// that it returns, dropping those that it returns `None` for. This generalizes
+    /// [`run_echo`](USBSerial::run_echo) to filtering and translating bytes, such as when
+    /// selectively forwarding bytes while debugging a device.
+    ///
+    /// This makes a single pass without waiting, so call it from the main loop. Returns the
+    /// amount of bytes successfully written out.
+    pub fn relay<F: FnMut(u8) -> Option<u8>>(mut f: F) -> usize {
+        let mut input = [0u8; WRITE_CHUNK_SIZE];
+        let mut output = [0u8; WRITE_CHUNK_SIZE];
+        let mut total = 0usize;
+        // Only the bytes waiting at the start, so bytes that keep coming can not hold this up
+        let mut remaining = Self::avaliable();
+
+        loop {
+            let count = Self::read_bytes(&mut input[..remaining.min(WRITE_CHUNK_SIZE)]);
+            if count == 0 {
+                return total;
+            }
+            remaining -= count.min(remaining);
+
+            let mut length = 0usize;
+            for byte in input[..count].iter().filter_map(|&byte| f(byte)) {
+                output[length] = byte;
+                length += 1;
+            }
+
+            total += Self::write_bytes(&output[..length]);
+        }
+    }
+
+    /// Read in one byte of data from the serial port
+    fn read_byte() -> Option<u8> {
+        let mut byte = [0u8];
+
+        if flow::xon_xoff() {
+            return (Self::read_raw(&mut byte) == 1).then_some(byte[0]);
+        }
+
+        // Take from the peeked bytes first
+        if unsafe { peeked().take(&mut byte) } == 1 {
+            stats::count_read(1);
+            return Some(byte[0]);
+        }
+
         // Call into the C API and store the result
         let result = unsafe { usb_serial_getchar() };
-        // usb_serial_getchar returns a -1 if there is no char to read, so return a None
+        // usb_serial_getchar returns a -1 if there is no byte to read, so return a None
         if result == -1 {
             None
         } else {
-            // If there is a char to read, get it, in a u8
-            let result: u8 = result.try_into().unwrap();
-            // Return the u8 as a char
-            Some(result as char)
+            stats::count_read(1);
+            // If there is a byte to read, get it, in a u8
+            Some(result.try_into().unwrap())
         }
     }
 
+    /// Read in one char of data from the serial port
+    pub fn read() -> Option<char> {
+        Self::read_byte().map(|byte| byte as char)
+    }
+
     /// Write a single char out onto the serial port, returning if the write was successful or not
     pub fn write_char(c: char) -> bool {
-        // Call into the C API with an ascii byte
-        unsafe { usb_serial_write(&c as *const char as _, 1) == 1 }
+        let mut buffer = [0u8; 4];
+        let bytes = c.encode_utf8(&mut buffer).as_bytes();
+
+        // Go through write_bytes, so the char waits out flow control like any other write
+        Self::write_bytes(bytes) == bytes.len()
     }
 
     /// Write a whole string out onto the serial port, returning the amount of bytes successfully written out
+    ///
+    /// The line endings may be translated, see [`set_crlf_translation`](USBSerial::set_crlf_translation)
     pub fn write(string: &str) -> usize {
-        // Get the string length
-        let size = string.len();
-        // Get the pointer to the string
-        let ptr = string.as_ptr();
+        if crlf::enabled() {
+            crlf::write_translated(string.as_bytes(), Self::write_bytes)
+        } else {
+            Self::write_bytes(string.as_bytes())
+        }
+    }
+
+    /// Write out `true` or `false`, returning if it was all written out
+    pub fn write_bool(b: bool) -> bool {
+        Self::write_bool_as(b, "true", "false")
+    }
+
+    /// Write out one of two strings for a bool, such as `on` or `off`, returning if it was
+    /// all written out
+    pub fn write_bool_as(b: bool, when_true: &str, when_false: &str) -> bool {
+        USBSerialWriter {}
+            .write_str(if b { when_true } else { when_false })
+            .is_ok()
+    }
+
+    /// Write out the value if there is one, or `-` if there is not, returning if it was all
+    /// written out
+    pub fn write_option<T: Display>(opt: Option<T>) -> bool {
+        Self::write_option_or(opt, "-")
+    }
+
+    /// Write out the value if there is one, or the string, such as `none`, if there is not,
+    /// returning if it was all written out
+    pub fn write_option_or<T: Display>(opt: Option<T>, when_none: &str) -> bool {
+        match opt {
+            Some(value) => write!(USBSerialWriter {}, "{}", value).is_ok(),
+            None => USBSerialWriter {}.write_str(when_none).is_ok(),
+        }
+    }
+
+    /// Write a buffer of bytes out onto the serial port, returning the amount of bytes successfully written out
+    ///
+    /// Nothing is written out while the other end has paused writing, see [`set_flow_control`],
+    /// or while the output is cleared, see [`clear_output`]
+    ///
+    /// [`set_flow_control`]: USBSerial::set_flow_control
+    /// [`clear_output`]: USBSerial::clear_output
+    pub fn write_bytes(buffer: &[u8]) -> usize {
+        if Self::is_output_cleared() || (flow::xon_xoff() && Self::is_flow_paused()) {
+            return 0;
+        }
+
+        // Get the buffer length
+        let size = buffer.len();
+        // Get the pointer to the buffer
+        let ptr = buffer.as_ptr();
 
         // Call the C API
-        unsafe { usb_serial_write(ptr as _, size) }
+        stats::count_written(unsafe { usb_serial_write(ptr as _, size) })
+    }
+
+    /// Write a buffer of bytes out onto the serial port, retrying for the duration of the write
+    /// timeout until it is all written out. Returns the amount of bytes successfully written out.
+    pub fn write_bytes_timeout(buffer: &[u8]) -> usize {
+        // The current count of written out bytes
+        let mut count = 0usize;
+        // The start time, for timeout
+        let start_millis = millis();
+
+        loop {
+            count += Self::write_bytes(&buffer[count..]);
+
+            // Break the loop once everything is written out
+            if count >= buffer.len() {
+                return count;
+            }
+
+            // Stop the loop if the timeout is reached or the output is cleared
+            if spin_timed_out(start_millis, SERIAL_WRITE_TIMEOUT.load(Ordering::Relaxed))
+                || Self::is_output_cleared()
+            {
+                return count;
+            }
+        }
+    }
+
+    /// Write out the bytes produced by an iterator, returning the amount of bytes successfully
+    /// written out. This avoids needing the whole output in one contiguous slice.
+    ///
+    /// Bytes are collected into a [`WRITE_CHUNK_SIZE`] byte buffer on the stack, which is
+    /// written out whenever it fills up, retrying for the duration of the write timeout as
+    /// with [`write_bytes_timeout`](USBSerial::write_bytes_timeout). If a chunk still can
+    /// not be written out in full, the write is stopped and the rest of the iterator is left
+    /// unconsumed, but the unwritten rest of that chunk, up to [`WRITE_CHUNK_SIZE`] bytes,
+    /// has already been taken from the iterator and is lost.
+    pub fn write_iter(iter: impl Iterator<Item = u8>) -> usize {
+        let mut iter = iter;
+        let mut chunk = [0u8; WRITE_CHUNK_SIZE];
+        // The total count of written out bytes
+        let mut total = 0usize;
+
+        loop {
+            // Fill the chunk from the iterator
+            let mut length = 0usize;
+            for (slot, byte) in chunk.iter_mut().zip(&mut iter) {
+                *slot = byte;
+                length += 1;
+            }
+
+            // Stop once the iterator is exhausted
+            if length == 0 {
+                return total;
+            }
+
+            let written = Self::write_bytes_timeout(&chunk[..length]);
+            total += written;
+
+            // Stop if the chunk could not be written out in full
+            if written < length {
+                return total;
+            }
+        }
     }
 }
 
+/// The timeout for reads, which is 0 in non-blocking mode
+fn read_timeout() -> u32 {
+    if USBSerial::is_nonblocking() {
+        0
+    } else {
+        SERIAL_TIMEOUT.load(Ordering::Relaxed)
+    }
+}
+
+/// Check if `timeout` milliseconds have passed since `start_millis`,
+/// accounting for the millisecond counter overflowing
+fn timed_out(start_millis: u32, timeout: u32) -> bool {
+    millis().wrapping_sub(start_millis) >= timeout
+}
+
+/// Run the [yield hook](USBSerial::set_yield_hook), for each turn of a busy loop
+fn yield_now() {
+    // The hook is only ever replaced whole, and fn pointers are copied in one go
+    let hook = unsafe { ptr::read_volatile(ptr::addr_of!(YIELD_HOOK)) };
+
+    hook();
+}
+
+/// Run the [yield hook](USBSerial::set_yield_hook), then check if the timeout has passed,
+/// as with [`timed_out`], for busy loops
+fn spin_timed_out(start_millis: u32, timeout: u32) -> bool {
+    yield_now();
+    timed_out(start_millis, timeout)
+}
+
 /// A ZST that can be constructed to use the write! and writeln! macros with the global SERIAL output
+///
+/// When the output buffer is full, writes are retried for the duration of the write timeout
+/// (see [`USBSerial::set_write_timeout`]), so a burst of output is not cut short. Only output
+/// that still can not be written out after that fails with an `fmt::Error`.
 pub struct USBSerialWriter;
 
 impl Write for USBSerialWriter {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         let len = s.len();
 
-        if SERIAL::write(s) != len {
+        let written = if crlf::enabled() {
+            crlf::write_translated(s.as_bytes(), SERIAL::write_bytes_timeout)
+        } else {
+            SERIAL::write_bytes_timeout(s.as_bytes())
+        };
+
+        if written != len {
             Err(fmt::Error)
         } else {
             Ok(())
@@ -299,10 +1250,269 @@ impl Write for USBSerialWriter {
     }
 
     fn write_char(&mut self, c: char) -> fmt::Result {
-        if SERIAL::write_char(c) {
-            Ok(())
-        } else {
-            Err(fmt::Error)
+        self.write_str(c.encode_utf8(&mut [0u8; 4]))
+    }
+}
+
+/// A [`USBSerialWriter`] that pushes out the buffered output with [`USBSerial::send_now`]
+/// when it is dropped, so the end of a formatted message is never left waiting in the
+/// buffer for the next write
+///
+/// ```ignore
+/// {
+///     let mut writer = FlushOnDropWriter;
+///     writeln!(writer, "Done")?;
+/// } // Sent here
+/// ```
+pub struct FlushOnDropWriter;
+
+impl Write for FlushOnDropWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        USBSerialWriter {}.write_str(s)
+    }
+
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        USBSerialWriter {}.write_char(c)
+    }
+}
+
+impl Drop for FlushOnDropWriter {
+    fn drop(&mut self) {
+        SERIAL::send_now();
+    }
+}
+
+/// Allows the much smaller `ufmt` machinery to be used for formatting,
+/// with the `uwrite!` and `uwriteln!` macros
+///
+/// **Requires the feature `ufmt`**
+#[cfg(feature = "ufmt")]
+impl ufmt::uWrite for USBSerialWriter {
+    type Error = fmt::Error;
+
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        Write::write_str(self, s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn line_past_the_max_length_overflows() {
+        let _serial = test_support::serial();
+        USBSerial::set_max_line_length(4);
+        test_support::receive(b"hello\n");
+
+        let mut buffer = [0u8; 16];
+        assert_eq!(USBSerial::read_line(&mut buffer), Err(LineError::Overflow));
+        // The byte that did not fit is left unread
+        assert_eq!(USBSerial::read_line(&mut buffer), Ok(Some("o")));
+    }
+
+    #[test]
+    fn line_past_the_buffer_overflows() {
+        let _serial = test_support::serial();
+        test_support::receive(b"hello\n");
+
+        let mut buffer = [0u8; 4];
+        assert_eq!(USBSerial::read_line(&mut buffer), Err(LineError::Overflow));
+    }
+
+    #[test]
+    fn line_that_exactly_fits_is_read() {
+        let _serial = test_support::serial();
+        USBSerial::set_max_line_length(5);
+        test_support::receive(b"hello\r\nworld\n");
+
+        let mut buffer = [0u8; 5];
+        assert_eq!(USBSerial::read_line(&mut buffer), Ok(Some("hello")));
+        // The line ending is consumed, but does not count towards the length
+        assert_eq!(USBSerial::read_line(&mut buffer), Ok(Some("world")));
+    }
+
+    #[test]
+    fn peeked_bytes_are_read_after() {
+        let _serial = test_support::serial();
+        test_support::receive(b"abcdef");
+
+        let mut peeked = [0u8; 3];
+        assert_eq!(USBSerial::peek_bytes(&mut peeked), 3);
+        assert_eq!(&peeked, b"abc");
+        // Peeking again sees the same bytes
+        assert_eq!(USBSerial::peek_bytes(&mut peeked), 3);
+        assert_eq!(&peeked, b"abc");
+
+        // The peeked bytes are still counted as available
+        assert_eq!(USBSerial::avaliable(), 6);
+        assert_eq!(USBSerial::peek(), Some('a'));
+
+        let mut read = [0u8; 6];
+        assert_eq!(USBSerial::read_bytes(&mut read), 6);
+        assert_eq!(&read, b"abcdef");
+        assert_eq!(USBSerial::avaliable(), 0);
+    }
+
+    #[test]
+    fn iterator_is_written_in_chunks() {
+        let _serial = test_support::serial();
+
+        assert_eq!(USBSerial::write_iter(0..100), 100);
+        assert_eq!(
+            test_support::sent(),
+            (0..100).collect::<std::vec::Vec<u8>>()
+        );
+    }
+
+    #[test]
+    fn iterator_stops_at_a_short_write() {
+        let _serial = test_support::serial();
+        test_support::set_write_limit(WRITE_CHUNK_SIZE + 8);
+
+        let mut iter = 0..100;
+        assert_eq!(USBSerial::write_iter(&mut iter), WRITE_CHUNK_SIZE + 8);
+        // The whole second chunk was taken, but the rest was left
+        assert_eq!(iter.next(), Some(2 * WRITE_CHUNK_SIZE as u8));
+    }
+
+    #[test]
+    fn exact_read_fills_the_buffer() {
+        let _serial = test_support::serial();
+        test_support::receive(b"abcdef");
+
+        let mut buffer = [0u8; 4];
+        assert_eq!(USBSerial::read_bytes_exact_timeout(&mut buffer), Ok(()));
+        assert_eq!(&buffer, b"abcd");
+    }
+
+    #[test]
+    fn exact_read_times_out_with_the_partial_count() {
+        let _serial = test_support::serial();
+        test_support::receive(b"ab");
+
+        let mut buffer = [0u8; 4];
+        assert_eq!(USBSerial::read_bytes_exact_timeout(&mut buffer), Err(2));
+        assert_eq!(&buffer[..2], b"ab");
+    }
+
+    #[test]
+    #[cfg(feature = "ufmt")]
+    fn ufmt_writes_out_integers() {
+        let _serial = test_support::serial();
+
+        ufmt::uwrite!(USBSerialWriter {}, "{} {} ", 42u32, -7i16).unwrap();
+        ufmt::uwriteln!(USBSerialWriter {}, "{}", u8::MAX).unwrap();
+        assert_eq!(test_support::sent(), b"42 -7 255\n");
+    }
+
+    #[test]
+    fn operations_are_retried_until_they_succeed() {
+        let _serial = test_support::serial();
+        let mut attempts = 0;
+
+        let result = USBSerial::with_retries(5, || {
+            attempts += 1;
+            if attempts < 3 {
+                Err(attempts)
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(result, Ok(3));
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn last_error_is_returned() {
+        let _serial = test_support::serial();
+        let mut attempts = 0;
+
+        let result = USBSerial::with_retries(4, || -> Result<(), u32> {
+            attempts += 1;
+            // A late response, which is thrown away before the next attempt
+            test_support::receive(b"late");
+            Err(attempts)
+        });
+        assert_eq!(result, Err(4));
+        assert_eq!(attempts, 4);
+        assert_eq!(USBSerial::avaliable(), 4);
+
+        // The operation is run even with no attempts
+        assert_eq!(USBSerial::with_retries(0, || Err::<(), _>(())), Err(()));
+    }
+
+    #[test]
+    fn writer_is_flushed_when_dropped() {
+        use core::sync::atomic::Ordering;
+
+        let _serial = test_support::serial();
+        let before = test_support::FLUSHES.load(Ordering::Relaxed);
+
+        {
+            let mut writer = FlushOnDropWriter;
+            write!(writer, "{}", 42).unwrap();
+            writer.write_char('!').unwrap();
+            assert_eq!(test_support::FLUSHES.load(Ordering::Relaxed), before);
         }
+
+        assert_eq!(test_support::FLUSHES.load(Ordering::Relaxed), before + 1);
+        assert_eq!(test_support::sent(), b"42!");
+    }
+
+    #[test]
+    fn at_most_max_lines_are_read() {
+        let _serial = test_support::serial();
+        test_support::receive(b"one\ntwo\nthree\n");
+
+        let mut buffer = [0u8; 32];
+        let mut lines = [""; 4];
+        assert_eq!(USBSerial::read_lines(&mut buffer, &mut lines, 2), 2);
+        assert_eq!(lines[..2], ["one", "two"]);
+
+        // The rest is left unread
+        assert_eq!(USBSerial::read_line(&mut buffer), Ok(Some("three")));
+    }
+
+    #[test]
+    fn fewer_lines_are_read_on_timeout() {
+        let _serial = test_support::serial();
+        test_support::receive(b"one\ntwo\n");
+
+        let mut buffer = [0u8; 32];
+        let mut lines = [""; 4];
+        assert_eq!(USBSerial::read_lines(&mut buffer, &mut lines, 4), 2);
+        assert_eq!(lines[..2], ["one", "two"]);
+    }
+
+    #[test]
+    fn lines_stop_when_the_buffer_runs_out() {
+        let _serial = test_support::serial();
+        test_support::receive(b"one\ntwo\nthree\n");
+
+        // Fits the first two lines, but not the third
+        let mut buffer = [0u8; 8];
+        let mut lines = [""; 4];
+        assert_eq!(USBSerial::read_lines(&mut buffer, &mut lines, 4), 2);
+        assert_eq!(lines[..2], ["one", "two"]);
+    }
+
+    #[test]
+    fn output_pressure_follows_the_free_space() {
+        let _serial = test_support::serial();
+        let pressure = |free| {
+            test_support::set_write_buffer_free(free);
+
+            USBSerial::output_pressure()
+        };
+
+        assert_eq!(pressure(TX_BUFFER_SIZE), 0.0);
+        assert_eq!(pressure(0), 1.0);
+        assert_eq!(pressure(TX_BUFFER_SIZE / 4), 0.75);
+        // More free space than the buffer holds
+        assert_eq!(pressure(TX_BUFFER_SIZE + 1808), 0.0);
     }
 }
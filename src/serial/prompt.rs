@@ -0,0 +1,112 @@
+//! Prompting for a line of input that can be edited while it is typed, for interactive consoles
+
+use core::{str, sync::atomic::Ordering};
+
+use crate::millis;
+
+use super::{read_timeout, spin_timed_out, USBSerial, SERIAL_MAX_LINE_LENGTH};
+
+/// The ascii backspace, sent by some terminals for the backspace key
+const BACKSPACE: u8 = 0x08;
+/// The ascii delete, sent by most terminals for the backspace key
+const DELETE: u8 = 0x7F;
+/// Move back over the erased character, blank it out, then move back again
+const ERASE: &[u8] = b"\x08 \x08";
+
+impl USBSerial {
+    /// Write out a prompt, such as `"> "`, and read in the line that is typed after it,
+    /// as the building block of an interactive console.
+    ///
+    /// Anything received before the prompt is thrown away, so stale input is not mistaken
+    /// for the answer. The prompt is flushed out, and every typed character is echoed back,
+    /// since serial terminals do not show what is typed. Backspace (`0x08` or `0x7F`) erases
+    /// the last character, other control characters are ignored, and characters past
+    /// [`set_max_line_length`] or the length of the buffer are dropped. The line ends at a
+    /// `\r` or `\n`, which is not included in the returned string.
+    ///
+    /// The serial timeout starts over with every key press, so a line that is being typed
+    /// is not cut off. Returns `None` if nothing was typed before the timeout, or if the line
+    /// is not valid UTF-8. If the timeout passes part way through a line, what was typed so
+    /// far is returned.
+    ///
+    /// ```ignore
+    /// let mut buffer = [0u8; 64];
+    ///
+    /// loop {
+    ///     if let Some(command) = USBSerial::prompt("> ", &mut buffer) {
+    ///         run_command(command);
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// [`set_max_line_length`]: USBSerial::set_max_line_length
+    pub fn prompt<'a>(prompt: &str, buffer: &'a mut [u8]) -> Option<&'a str> {
+        Self::clear();
+        Self::write_bytes_timeout(prompt.as_bytes());
+        Self::send_now();
+
+        // The longest line that can be accepted
+        let limit = buffer
+            .len()
+            .min(SERIAL_MAX_LINE_LENGTH.load(Ordering::Relaxed));
+        // The current count of read in bytes
+        let mut count = 0usize;
+        // The time of the last key press, for timeout
+        let mut last_millis = millis();
+        let timeout = read_timeout();
+
+        loop {
+            let byte = match Self::read_byte() {
+                Some(byte) => byte,
+                None => {
+                    if spin_timed_out(last_millis, timeout) {
+                        if count == 0 {
+                            return None;
+                        }
+
+                        break;
+                    }
+
+                    continue;
+                }
+            };
+
+            last_millis = millis();
+
+            match byte {
+                b'\r' | b'\n' => break,
+                BACKSPACE | DELETE => {
+                    if count == 0 {
+                        continue;
+                    }
+
+                    // Erase a whole character, along with the continuation bytes of utf-8
+                    count -= 1;
+                    while count > 0 && buffer[count] & 0xC0 == 0x80 {
+                        count -= 1;
+                    }
+
+                    Self::write_bytes(ERASE);
+                }
+                byte if byte.is_ascii_control() => continue,
+                byte => {
+                    if count >= limit {
+                        continue;
+                    }
+
+                    buffer[count] = byte;
+                    count += 1;
+
+                    Self::write_bytes(&[byte]);
+                }
+            }
+
+            Self::send_now();
+        }
+
+        Self::write_bytes(b"\r\n");
+        Self::send_now();
+
+        str::from_utf8(&buffer[..count]).ok()
+    }
+}
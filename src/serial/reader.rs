@@ -0,0 +1,146 @@
+//! Reading in a record made of several fields, with one buffer and timeout
+
+use core::convert::TryInto;
+
+use super::{read_timeout, Deadline, Endian, USBSerial};
+
+/// The size of the buffer that a [`SerialReader`] reads into
+pub const READER_BUFFER_SIZE: usize = 256;
+
+/// A reader for parsing a record out of several typed reads, given out by
+/// [`USBSerial::with_reader`]. All of the reads share one buffer, and one timeout
+/// that starts when the reader is created, so a record that stalls part way
+/// through gives up as a whole.
+///
+/// Every byte read in is consumed, even if the read that it was part of fails.
+pub struct SerialReader<'b> {
+    buffer: &'b mut [u8],
+    consumed: usize,
+    deadline: Deadline,
+}
+
+impl<'b> SerialReader<'b> {
+    fn new(buffer: &'b mut [u8]) -> Self {
+        SerialReader {
+            buffer,
+            consumed: 0,
+            deadline: Deadline::new(read_timeout()),
+        }
+    }
+
+    /// The amount of bytes read in so far
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// The amount of bytes that can still be read in before the buffer is full
+    pub fn remaining(&self) -> usize {
+        self.buffer.len() - self.consumed
+    }
+
+    /// Read in the next `count` bytes, returning `None` if they did not all
+    /// arrive before the timeout, or do not fit in the buffer
+    pub fn read_exact(&mut self, count: usize) -> Option<&[u8]> {
+        if count > self.remaining() {
+            return None;
+        }
+
+        let start = self.consumed;
+        while self.consumed < start + count {
+            self.consumed += USBSerial::read_raw(&mut self.buffer[self.consumed..start + count]);
+
+            if self.consumed < start + count && self.deadline.spin_expired() {
+                return None;
+            }
+        }
+
+        Some(&self.buffer[start..self.consumed])
+    }
+
+    /// Read in a byte with the shared timeout, without keeping it in the buffer, for
+    /// decoders that can take in more bytes than the buffer holds
+    pub(super) fn next_byte(&mut self) -> Option<u8> {
+        loop {
+            if let Some(byte) = USBSerial::read_byte() {
+                return Some(byte);
+            }
+
+            if self.deadline.spin_expired() {
+                return None;
+            }
+        }
+    }
+
+    /// Read in a byte
+    pub fn read_u8(&mut self) -> Option<u8> {
+        self.read_exact(1).map(|bytes| bytes[0])
+    }
+
+    /// Read in a `u16` sent in the provided byte order
+    pub fn read_u16(&mut self, endian: Endian) -> Option<u16> {
+        let bytes = self.read_exact(2)?.try_into().ok()?;
+
+        Some(match endian {
+            Endian::Big => u16::from_be_bytes(bytes),
+            Endian::Little => u16::from_le_bytes(bytes),
+        })
+    }
+
+    /// Read in a `u32` sent in the provided byte order
+    pub fn read_u32(&mut self, endian: Endian) -> Option<u32> {
+        let bytes = self.read_exact(4)?.try_into().ok()?;
+
+        Some(match endian {
+            Endian::Big => u32::from_be_bytes(bytes),
+            Endian::Little => u32::from_le_bytes(bytes),
+        })
+    }
+
+    /// Read in bytes up to the delimiter, returning them without the delimiter, which is
+    /// consumed. Returns `None` if the delimiter did not arrive before the timeout, or
+    /// before the buffer filled up.
+    pub fn read_until(&mut self, delimiter: u8) -> Option<&[u8]> {
+        let start = self.consumed;
+
+        loop {
+            if self.remaining() == 0 {
+                return None;
+            }
+
+            match USBSerial::read_byte() {
+                Some(byte) => {
+                    self.buffer[self.consumed] = byte;
+                    self.consumed += 1;
+
+                    if byte == delimiter {
+                        return Some(&self.buffer[start..self.consumed - 1]);
+                    }
+                }
+                None => {
+                    if self.deadline.spin_expired() {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl USBSerial {
+    /// Parse a record out of several typed reads with a [`SerialReader`], which reads into a
+    /// [`READER_BUFFER_SIZE`] byte buffer on the stack. The whole record has to arrive within
+    /// the serial timeout, from when this is called.
+    ///
+    /// ```ignore
+    /// let record = SERIAL::with_reader(|reader| {
+    ///     let id = reader.read_u8()?;
+    ///     let value = reader.read_u16(Endian::Big)?;
+    ///     Some((id, value))
+    /// });
+    /// ```
+    pub fn with_reader<R>(f: impl FnOnce(&mut SerialReader) -> R) -> R {
+        let mut buffer = [0u8; READER_BUFFER_SIZE];
+
+        f(&mut SerialReader::new(&mut buffer))
+    }
+}
@@ -0,0 +1,101 @@
+//! Small inline charts of recent values, drawn with text
+
+use core::fmt::{self, Display, Write};
+
+/// The characters that the bars are drawn with, from the lowest to the highest
+#[cfg(not(feature = "ascii_degrees"))]
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+/// The characters that the bars are drawn with, from the lowest to the highest
+#[cfg(feature = "ascii_degrees")]
+const BARS: [char; 8] = ['_', '.', '-', ':', '=', '+', '*', '#'];
+
+/// Draw the values as a sparkline, one bar for each value, scaled so that the lowest
+/// value gets the lowest bar and the highest value the highest bar. Only the last
+/// `width` values are drawn, so a history buffer can be passed in as it is.
+///
+/// An empty slice draws nothing, values that are all equal draw as middle bars, and
+/// values that are not finite (such as NaN) draw as a space. With the `ascii_degrees`
+/// feature, for serial monitors that do not handle UTF-8, the bars are drawn with ascii
+/// characters (`_.-:=+*#`) instead of blocks (`▁▂▃▄▅▆▇█`).
+///
+/// ```ignore
+/// log::info!("temperature {}", sparkline(&history, 16));
+/// ```
+pub fn sparkline(values: &[f32], width: usize) -> Sparkline<'_> {
+    Sparkline {
+        values: &values[values.len().saturating_sub(width)..],
+    }
+}
+
+/// A sparkline, displayed as a line of bars. Created with [`sparkline`].
+#[derive(Clone, Copy, Debug)]
+pub struct Sparkline<'a> {
+    values: &'a [f32],
+}
+
+impl Sparkline<'_> {
+    /// The lowest and highest of the finite values, if there are any
+    fn range(&self) -> Option<(f32, f32)> {
+        self.values
+            .iter()
+            .copied()
+            .filter(|value| value.is_finite())
+            .fold(None, |range, value| match range {
+                None => Some((value, value)),
+                Some((low, high)) => Some((low.min(value), high.max(value))),
+            })
+    }
+}
+
+impl Display for Sparkline<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (low, high) = match self.range() {
+            Some(range) => range,
+            None => return self.values.iter().try_for_each(|_| f.write_char(' ')),
+        };
+        let top = (BARS.len() - 1) as f32;
+
+        for &value in self.values {
+            let bar = if !value.is_finite() {
+                ' '
+            } else if high == low {
+                BARS[BARS.len() / 2]
+            } else {
+                // Round to the nearest bar
+                let level = (value - low) / (high - low) * top + 0.5;
+
+                BARS[(level as usize).min(BARS.len() - 1)]
+            };
+
+            f.write_char(bar)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::format;
+
+    use super::*;
+
+    #[test]
+    #[cfg(not(feature = "ascii_degrees"))]
+    fn values_are_drawn_as_bars() {
+        assert_eq!(format!("{}", sparkline(&[0.0, 1.0, 7.0, 3.5], 8)), "▁▂█▅");
+        assert_eq!(format!("{}", sparkline(&[2.0, f32::NAN, 2.0], 8)), "▅ ▅");
+        // Only the last values
+        assert_eq!(format!("{}", sparkline(&[9.0, 0.0, 7.0], 2)), "▁█");
+        assert_eq!(format!("{}", sparkline(&[], 8)), "");
+    }
+
+    #[test]
+    #[cfg(feature = "ascii_degrees")]
+    fn values_are_drawn_as_ascii_bars() {
+        assert_eq!(format!("{}", sparkline(&[0.0, 1.0, 7.0, 3.5], 8)), "_.#=");
+        assert_eq!(format!("{}", sparkline(&[2.0, f32::NAN, 2.0], 8)), "= =");
+    }
+}
@@ -0,0 +1,106 @@
+//! An animated "working…" indicator
+
+use core::fmt::{self, Write};
+
+use crate::millis;
+
+/// The classic spinner frames, which work on any terminal
+pub const LINE_FRAMES: &[&str] = &["|", "/", "-", "\\"];
+
+/// Spinner frames drawn with braille dots, for terminals that support unicode
+pub const BRAILLE_FRAMES: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// A spinner that animates in place at the start of the current line, to show
+/// that the program is busy during a blocking operation.
+///
+/// The frame shown is picked by how much time has passed, so the animation runs at
+/// the same speed no matter how often [`tick`](Spinner::tick) is called. Only the
+/// current line is ever touched, so a spinner can be used alongside a
+/// [`StatusLine`](super::statusline::StatusLine).
+pub struct Spinner {
+    frames: &'static [&'static str],
+    interval_ms: u32,
+    start_millis: u32,
+    shown: Option<usize>,
+}
+
+impl Spinner {
+    /// Create a spinner with the [`LINE_FRAMES`], advancing every 100 milliseconds
+    pub fn new() -> Spinner {
+        Self::with_frames(LINE_FRAMES, 100)
+    }
+
+    /// Create a spinner with custom frames, advancing every `interval_ms` milliseconds
+    pub fn with_frames(frames: &'static [&'static str], interval_ms: u32) -> Spinner {
+        Spinner {
+            frames,
+            interval_ms: interval_ms.max(1),
+            start_millis: millis(),
+            shown: None,
+        }
+    }
+
+    /// The index of the frame to show, `elapsed` milliseconds after starting
+    fn frame_index(&self, elapsed: u32) -> usize {
+        (elapsed / self.interval_ms) as usize % self.frames.len()
+    }
+
+    /// Draw the current frame, if it is not already shown
+    pub fn tick(&mut self, writer: &mut impl Write) -> fmt::Result {
+        if self.frames.is_empty() {
+            return Ok(());
+        }
+
+        let index = self.frame_index(millis().wrapping_sub(self.start_millis));
+
+        if self.shown != Some(index) {
+            self.shown = Some(index);
+
+            write!(writer, "\r{}", self.frames[index])?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Spinner {
+    fn default() -> Spinner {
+        Spinner::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn frames_advance_with_the_time() {
+        let spinner = Spinner::new();
+        let frames = [0, 99, 100, 250, 399, 400, 1234]
+            .iter()
+            .map(|&elapsed| spinner.frame_index(elapsed))
+            .collect::<std::vec::Vec<_>>();
+
+        assert_eq!(frames, [0, 0, 1, 2, 3, 0, 0]);
+    }
+
+    #[test]
+    fn each_frame_is_drawn_once() {
+        let mut spinner = Spinner::with_frames(BRAILLE_FRAMES, 50);
+        let mut out = String::new();
+
+        spinner.tick(&mut out).unwrap();
+        spinner.tick(&mut out).unwrap();
+        assert_eq!(out, "\r⠋");
+
+        // As if 120 milliseconds have passed since it started
+        spinner.start_millis = test_support::MILLIS - 120;
+        spinner.tick(&mut out).unwrap();
+        assert_eq!(out, "\r⠋\r⠹");
+    }
+}
@@ -0,0 +1,45 @@
+//! Throwing away input that has been waiting for too long
+
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use crate::millis;
+
+use super::{timed_out, USBSerial};
+
+/// The amount of waiting bytes last seen, and when it last changed
+static LAST_AVAILABLE: AtomicUsize = AtomicUsize::new(0);
+static LAST_CHANGE_MILLIS: AtomicU32 = AtomicU32::new(0);
+
+/// Note the amount of waiting bytes, keeping the time if it changed
+pub(super) fn observe(available: usize) {
+    if LAST_AVAILABLE.swap(available, Ordering::Relaxed) != available {
+        LAST_CHANGE_MILLIS.store(millis(), Ordering::Relaxed);
+    }
+}
+
+impl USBSerial {
+    /// Clear the input buffer if nothing has arrived or been read for `max_age_ms`
+    /// milliseconds, so that commands sent during a long blocking operation are not acted
+    /// on late. Returns whether the input was cleared.
+    ///
+    /// # Approximation
+    /// The time that bytes arrive is not known, so instead the time that the amount of
+    /// waiting bytes was seen to change is kept. It is checked by this and by every call to
+    /// [`avaliable`](USBSerial::avaliable), so bytes that arrived while neither was called
+    /// are only seen to arrive at the next call, and count as fresh. Call this or
+    /// `avaliable` regularly for the ages to be accurate.
+    pub fn discard_stale(max_age_ms: u32) -> bool {
+        if Self::avaliable() == 0 {
+            return false;
+        }
+
+        if timed_out(LAST_CHANGE_MILLIS.load(Ordering::Relaxed), max_age_ms) {
+            Self::clear();
+            observe(0);
+
+            true
+        } else {
+            false
+        }
+    }
+}
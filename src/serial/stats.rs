@@ -0,0 +1,98 @@
+//! Counters of the bytes passing through the serial port
+//!
+//! The counters are only kept with the feature `serial_stats`; without it,
+//! counting compiles away to nothing.
+
+#[cfg(feature = "serial_stats")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "serial_stats")]
+use super::USBSerial;
+
+#[cfg(feature = "serial_stats")]
+static BYTES_READ: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature = "serial_stats")]
+static BYTES_WRITTEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Count bytes that have been read, passing the count through
+#[cfg(feature = "serial_stats")]
+pub(super) fn count_read(count: usize) -> usize {
+    BYTES_READ.fetch_add(count, Ordering::Relaxed);
+
+    count
+}
+
+/// Count bytes that have been written, passing the count through
+#[cfg(feature = "serial_stats")]
+pub(super) fn count_written(count: usize) -> usize {
+    BYTES_WRITTEN.fetch_add(count, Ordering::Relaxed);
+
+    count
+}
+
+/// Count bytes that have been read, passing the count through
+#[cfg(not(feature = "serial_stats"))]
+pub(super) fn count_read(count: usize) -> usize {
+    count
+}
+
+/// Count bytes that have been written, passing the count through
+#[cfg(not(feature = "serial_stats"))]
+pub(super) fn count_written(count: usize) -> usize {
+    count
+}
+
+#[cfg(feature = "serial_stats")]
+impl USBSerial {
+    /// Get the amount of bytes that have been read from the serial port
+    /// since startup, or since the last [`reset_stats`](USBSerial::reset_stats)
+    ///
+    /// **Requires the feature `serial_stats`**
+    pub fn bytes_read() -> usize {
+        BYTES_READ.load(Ordering::Relaxed)
+    }
+
+    /// Get the amount of bytes that have been written to the serial port
+    /// since startup, or since the last [`reset_stats`](USBSerial::reset_stats)
+    ///
+    /// **Requires the feature `serial_stats`**
+    pub fn bytes_written() -> usize {
+        BYTES_WRITTEN.load(Ordering::Relaxed)
+    }
+
+    /// Reset the read and written byte counters to zero
+    ///
+    /// **Requires the feature `serial_stats`**
+    pub fn reset_stats() {
+        BYTES_READ.store(0, Ordering::Relaxed);
+        BYTES_WRITTEN.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(all(test, feature = "serial_stats"))]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn counters_rise_by_the_bytes_passed_through() {
+        let _serial = test_support::serial();
+        USBSerial::reset_stats();
+
+        assert_eq!(USBSerial::write("hello"), 5);
+        assert!(USBSerial::write_char('é'));
+        assert_eq!(USBSerial::bytes_written(), 7);
+
+        test_support::receive(b"abcd");
+        let mut buffer = [0u8; 3];
+        assert_eq!(USBSerial::read_bytes(&mut buffer), 3);
+        assert_eq!(USBSerial::read(), Some('d'));
+        assert_eq!(USBSerial::bytes_read(), 4);
+
+        USBSerial::reset_stats();
+        assert_eq!(
+            (USBSerial::bytes_read(), USBSerial::bytes_written()),
+            (0, 0)
+        );
+    }
+}
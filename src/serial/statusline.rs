@@ -0,0 +1,71 @@
+//! A status line pinned to the bottom of the terminal, which stays in place
+//! while other output (such as logs) scrolls past above it
+//!
+//! **Requires the feature `ansi`**
+//!
+//! # Terminal Compatibility
+//! The status line is made with the VT100 scroll region (`DECSTBM`) and cursor
+//! save/restore (`DECSC`/`DECRC`) escapes. These are supported by xterm and the
+//! terminals that follow it (PuTTY, GNOME Terminal, Windows Terminal, screen,
+//! tmux, ...), but serial monitors that do not interpret escapes at all, such as
+//! the Arduino IDE's, will show them as garbage.
+//!
+//! The height of the terminal has to be known up front, and the status line
+//! is not moved if the terminal is resized.
+
+use core::fmt::{self, Write};
+
+use super::USBSerialWriter;
+
+/// A line reserved at the bottom of the terminal for showing live information,
+/// such as the temperature or uptime. Dropping it (or calling [`teardown`])
+/// gives the line back to the rest of the output.
+///
+/// [`teardown`]: StatusLine::teardown
+pub struct StatusLine {
+    rows: u16,
+}
+
+impl StatusLine {
+    /// Reserve the bottom line of a terminal that is `rows` rows tall
+    pub fn new(rows: u16) -> StatusLine {
+        let rows = rows.max(2);
+
+        // Limit scrolling to the rows above the status line. Setting the scroll
+        // region moves the cursor home, so move it back to the bottom of the region.
+        write!(USBSerialWriter {}, "\u{1B}[1;{0}r\u{1B}[{0};1H", rows - 1).ok();
+
+        StatusLine { rows }
+    }
+
+    /// Replace the text on the status line. The cursor is saved and restored around
+    /// the update, so the output scrolling above is not disturbed.
+    ///
+    /// The text should fit on one line, and not contain line breaks.
+    pub fn set(&self, text: &str) -> fmt::Result {
+        write!(
+            USBSerialWriter {},
+            "\u{1B}7\u{1B}[{};1H\u{1B}[2K{}\u{1B}8",
+            self.rows,
+            text
+        )
+    }
+
+    /// Clear the status line and restore the full scroll region
+    pub fn teardown(self) {
+        drop(self)
+    }
+}
+
+impl Drop for StatusLine {
+    fn drop(&mut self) {
+        // Restoring the scroll region moves the cursor home, so do it between
+        // saving and restoring the cursor
+        write!(
+            USBSerialWriter {},
+            "\u{1B}7\u{1B}[r\u{1B}[{};1H\u{1B}[2K\u{1B}8",
+            self.rows
+        )
+        .ok();
+    }
+}
@@ -0,0 +1,257 @@
+//! Drawing tables of text with box drawing characters, such as for showing
+//! sensor readings or settings
+//!
+//! ```text
+//! ┌────────┬───────┐
+//! │ Sensor │ Value │
+//! ├────────┼───────┤
+//! │ temp   │ 45.2  │
+//! │ hum    │ 31    │
+//! └────────┴───────┘
+//! ```
+
+use core::fmt::{self, Write};
+
+#[cfg(feature = "ansi")]
+use super::ansi::{Color, EscapeSequence, Style};
+use super::layout::{write_padded_to, write_repeated_to};
+use super::{Align, USBSerial};
+
+/// The most columns that a table can have
+pub const MAX_COLUMNS: usize = 16;
+
+/// A table with a header row, whose columns are sized to fit their contents
+#[derive(Clone, Copy, Debug)]
+pub struct Table<'a> {
+    headers: &'a [&'a str],
+    max_width: Option<usize>,
+    #[cfg(feature = "ansi")]
+    border_color: Option<Color>,
+}
+
+impl<'a> Table<'a> {
+    /// Create a table with the column headers. A table can have at most [`MAX_COLUMNS`] columns.
+    pub const fn new(headers: &'a [&'a str]) -> Self {
+        Table {
+            headers,
+            max_width: None,
+            #[cfg(feature = "ansi")]
+            border_color: None,
+        }
+    }
+
+    /// Limit the table to `width` characters wide, including the borders. The widest
+    /// columns are narrowed until the table fits, cutting their text short with a `…`.
+    pub const fn max_width(self, width: usize) -> Self {
+        Table {
+            max_width: Some(width),
+            ..self
+        }
+    }
+
+    /// Draw the borders of the table in a color
+    ///
+    /// **Requires the feature `ansi`**
+    #[cfg(feature = "ansi")]
+    pub const fn border_color(self, color: Color) -> Self {
+        Table {
+            border_color: Some(color),
+            ..self
+        }
+    }
+
+    /// Draw the table with the rows into the writer. Rows with fewer cells than there are
+    /// columns are padded with empty cells, and extra cells are left out.
+    ///
+    /// Fails without writing anything if the table has more than [`MAX_COLUMNS`] columns.
+    pub fn render(&self, f: &mut impl Write, rows: &[&[&str]]) -> fmt::Result {
+        let columns = self.headers.len();
+        if columns > MAX_COLUMNS {
+            return Err(fmt::Error);
+        }
+
+        let mut widths = [0usize; MAX_COLUMNS];
+        let widths = &mut widths[..columns];
+        for (column, width) in widths.iter_mut().enumerate() {
+            *width = rows
+                .iter()
+                .filter_map(|row| row.get(column))
+                .chain(core::iter::once(&self.headers[column]))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0);
+        }
+
+        if let Some(max_width) = self.max_width {
+            // Each column takes a border and a space on both sides, shared with the next column
+            let borders = 3 * columns + 1;
+
+            while widths.iter().sum::<usize>() + borders > max_width {
+                match widths.iter_mut().max() {
+                    Some(widest) if *widest > 1 => *widest -= 1,
+                    _ => break,
+                }
+            }
+        }
+
+        self.rule(f, widths, '┌', '┬', '┐')?;
+        self.row(f, widths, self.headers)?;
+        self.rule(f, widths, '├', '┼', '┤')?;
+        for row in rows {
+            self.row(f, widths, row)?;
+        }
+        self.rule(f, widths, '└', '┴', '┘')
+    }
+
+    /// Draw a horizontal border
+    fn rule(
+        &self,
+        f: &mut impl Write,
+        widths: &[usize],
+        left: char,
+        middle: char,
+        right: char,
+    ) -> fmt::Result {
+        self.begin_border(f)?;
+
+        f.write_char(left)?;
+        for (column, &width) in widths.iter().enumerate() {
+            if column > 0 {
+                f.write_char(middle)?;
+            }
+            write_repeated_to(f, '─', width + 2)?;
+        }
+        f.write_char(right)?;
+
+        self.end_border(f)?;
+        f.write_str("\r\n")
+    }
+
+    /// Draw a row of cells
+    fn row(&self, f: &mut impl Write, widths: &[usize], cells: &[&str]) -> fmt::Result {
+        for (column, &width) in widths.iter().enumerate() {
+            self.border(f, "│")?;
+            f.write_char(' ')?;
+            write_padded_to(
+                f,
+                cells.get(column).copied().unwrap_or(""),
+                width,
+                Align::Left,
+            )?;
+            f.write_char(' ')?;
+        }
+
+        self.border(f, "│")?;
+        f.write_str("\r\n")
+    }
+
+    /// Draw a piece of the borders
+    fn border(&self, f: &mut impl Write, s: &str) -> fmt::Result {
+        self.begin_border(f)?;
+        f.write_str(s)?;
+        self.end_border(f)
+    }
+
+    #[cfg(feature = "ansi")]
+    fn begin_border(&self, f: &mut impl Write) -> fmt::Result {
+        match self.border_color {
+            Some(color) => write!(f, "{}", EscapeSequence::new().set_fg(color)),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(feature = "ansi")]
+    fn end_border(&self, f: &mut impl Write) -> fmt::Result {
+        match self.border_color {
+            Some(_) => write!(f, "{}", EscapeSequence::new().set_styles(&[Style::Clear])),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(feature = "ansi"))]
+    fn begin_border(&self, _: &mut impl Write) -> fmt::Result {
+        Ok(())
+    }
+
+    #[cfg(not(feature = "ansi"))]
+    fn end_border(&self, _: &mut impl Write) -> fmt::Result {
+        Ok(())
+    }
+}
+
+impl USBSerial {
+    /// Write out one row of a table, with each cell padded (or cut short) to its width, as in
+    /// `│ temp   │ 45.2  │`, followed by a line ending. This streams out a table one row at a
+    /// time, such as a live log of sensor readings, with no need to know all of the rows
+    /// ahead of time like a [`Table`] does. The rows line up with a `Table` of the same
+    /// column widths.
+    ///
+    /// Returns the amount of bytes successfully written out.
+    pub fn print_table_row(cols: &[(&str, usize)]) -> usize {
+        let mut count = 0usize;
+
+        for &(cell, width) in cols {
+            count += Self::write("│ ");
+            count += Self::write_padded(cell, width, Align::Left);
+            count += Self::write(" ");
+        }
+
+        count + Self::write("│\r\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+
+    use super::*;
+
+    /// Draw the table into a new string
+    fn render(table: Table, rows: &[&[&str]]) -> Result<String, fmt::Error> {
+        let mut out = String::new();
+        table.render(&mut out, rows)?;
+
+        Ok(out)
+    }
+
+    #[test]
+    fn columns_fit_their_contents() {
+        let rows: &[&[&str]] = &[&["temp", "45.2"], &["hum"]];
+
+        assert_eq!(
+            render(Table::new(&["Sensor", "Value"]), rows).unwrap(),
+            "┌────────┬───────┐\r\n\
+             │ Sensor │ Value │\r\n\
+             ├────────┼───────┤\r\n\
+             │ temp   │ 45.2  │\r\n\
+             │ hum    │       │\r\n\
+             └────────┴───────┘\r\n"
+        );
+    }
+
+    #[test]
+    fn wide_columns_are_narrowed_to_fit() {
+        let rows: &[&[&str]] = &[&["a long value", "b"]];
+
+        assert_eq!(
+            render(Table::new(&["Key", "Value"]).max_width(17), rows).unwrap(),
+            "┌───────┬───────┐\r\n\
+             │ Key   │ Value │\r\n\
+             ├───────┼───────┤\r\n\
+             │ a lo… │ b     │\r\n\
+             └───────┴───────┘\r\n"
+        );
+    }
+
+    #[test]
+    fn too_many_columns_fail() {
+        let headers = [""; MAX_COLUMNS + 1];
+        let mut out = String::new();
+
+        assert_eq!(Table::new(&headers).render(&mut out, &[]), Err(fmt::Error));
+        assert!(out.is_empty());
+        assert!(render(Table::new(&headers[..MAX_COLUMNS]), &[]).is_ok());
+    }
+}
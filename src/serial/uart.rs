@@ -0,0 +1,215 @@
+//! Bridging the usb serial port to a hardware serial port
+//!
+//! This crate does not drive the hardware serial ports itself, so they are
+//! described by the [`Uart`] trait, to be implemented over whichever driver
+//! is in use.
+
+use core::{
+    ptr,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use super::{flow, BinarySerial, USBSerial, WRITE_CHUNK_SIZE};
+
+/// A byte oriented serial port, such as one of the Teensy's hardware serial ports.
+/// None of the methods may wait for data or space.
+pub trait Uart {
+    /// Read in the bytes that have already arrived, returning the amount read in
+    fn read(&mut self, buffer: &mut [u8]) -> usize;
+
+    /// Write out as many of the bytes as there is space for, returning the amount written out
+    fn write(&mut self, buffer: &[u8]) -> usize;
+
+    /// The amount of bytes that can be written out without waiting
+    fn available_for_write(&self) -> usize;
+
+    /// Change the baud rate of the port
+    fn set_baud(&mut self, baud: u32);
+}
+
+/// The usb serial port, as a [`Uart`]. Its baud rate is set by the host, so it can not
+/// be changed.
+impl Uart for BinarySerial {
+    fn read(&mut self, buffer: &mut [u8]) -> usize {
+        BinarySerial::read(self, buffer)
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> usize {
+        BinarySerial::write(self, buffer)
+    }
+
+    fn available_for_write(&self) -> usize {
+        USBSerial::available_for_write()
+    }
+
+    fn set_baud(&mut self, _baud: u32) {}
+}
+
+/// The host's baud rate that was last applied to the piped port
+static PIPED_BAUD: AtomicU32 = AtomicU32::new(0);
+
+/// Bytes read from the piped port that the usb serial port did not take, as the port
+/// can not be peeked into to leave them unread
+struct Pending {
+    bytes: [u8; WRITE_CHUNK_SIZE],
+    len: usize,
+}
+
+static mut PENDING: Pending = Pending {
+    bytes: [0; WRITE_CHUNK_SIZE],
+    len: 0,
+};
+
+/// Access the bytes waiting to be written to the usb serial port
+///
+/// # Safety
+/// The serial port may only be used from one context at a time, so there
+/// must never be two live references to the pending bytes
+unsafe fn pending() -> &'static mut Pending {
+    &mut *ptr::addr_of_mut!(PENDING)
+}
+
+impl USBSerial {
+    /// Forward the bytes waiting on each side between the usb serial port and the other port,
+    /// without waiting, returning the amount of bytes forwarded to the port and to the usb
+    /// serial port. Calling this in a loop turns the Teensy into a usb to serial bridge.
+    ///
+    /// Only as many bytes are forwarded as the receiving side has space for; the rest wait for
+    /// the next call. Nothing is forwarded to the usb serial port while it is paused by an
+    /// [`XOFF`](super::XOFF) or [cleared](USBSerial::clear_output), so no bytes are lost.
+    /// Whenever the host changes the baud rate of the usb serial port (see
+    /// [`baud`](USBSerial::baud)), the port is set to the same baud rate.
+    pub fn pipe(uart: &mut impl Uart) -> (usize, usize) {
+        let baud = Self::baud();
+        if baud != 0 && PIPED_BAUD.swap(baud, Ordering::Relaxed) != baud {
+            uart.set_baud(baud);
+        }
+
+        let mut chunk = [0u8; WRITE_CHUNK_SIZE];
+
+        // From the host to the port. The bytes are only peeked at, and then read once they
+        // are written, so any the port does not take are left for the next call.
+        let mut to_uart = 0usize;
+        loop {
+            let space = uart.available_for_write().min(WRITE_CHUNK_SIZE);
+            let count = Self::peek_bytes(&mut chunk[..space]);
+            if count == 0 {
+                break;
+            }
+
+            let written = uart.write(&chunk[..count]);
+            Self::read_bytes(&mut chunk[..written]);
+            to_uart += written;
+
+            if written < count {
+                break;
+            }
+        }
+
+        // From the port to the host, starting with the bytes left over from the last call
+        let pending = unsafe { pending() };
+        let mut to_usb = Self::write_bytes(&pending.bytes[..pending.len]);
+        pending.bytes.copy_within(to_usb..pending.len, 0);
+        pending.len -= to_usb;
+
+        // Nothing is read from the port while no more can be written out, so it stays there
+        while pending.len == 0
+            && !Self::is_output_cleared()
+            && !(flow::xon_xoff() && Self::is_flow_paused())
+        {
+            let space = Self::available_for_write().min(WRITE_CHUNK_SIZE);
+            let count = uart.read(&mut chunk[..space]);
+            if count == 0 {
+                break;
+            }
+
+            let written = Self::write_bytes(&chunk[..count]);
+            to_usb += written;
+
+            // Keep the rest for the next call
+            pending.bytes[..count - written].copy_from_slice(&chunk[written..count]);
+            pending.len = count - written;
+        }
+
+        (to_uart, to_usb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        serial::{FlowControl, XOFF, XON},
+        test_support::{self, MockUart},
+    };
+
+    #[test]
+    fn bytes_are_forwarded_both_ways() {
+        let _serial = test_support::serial();
+        test_support::set_line_coding(9600, 0, 0, 8);
+        let mut uart = MockUart::new(b"to host", 64);
+        test_support::receive(b"to port");
+
+        assert_eq!(USBSerial::pipe(&mut uart), (7, 7));
+        assert_eq!(uart.outgoing, b"to port");
+        assert_eq!(test_support::sent(), b"to host");
+
+        // Nothing is left on either side
+        assert_eq!(USBSerial::pipe(&mut uart), (0, 0));
+    }
+
+    #[test]
+    fn baud_rate_follows_the_host() {
+        let _serial = test_support::serial();
+        let mut uart = MockUart::new(b"", 64);
+
+        test_support::set_line_coding(115_200, 0, 0, 8);
+        USBSerial::pipe(&mut uart);
+        assert_eq!(uart.baud, Some(115_200));
+
+        // Only changes are applied
+        uart.baud = None;
+        USBSerial::pipe(&mut uart);
+        assert_eq!(uart.baud, None);
+
+        test_support::set_line_coding(9600, 0, 0, 8);
+        USBSerial::pipe(&mut uart);
+        assert_eq!(uart.baud, Some(9600));
+    }
+
+    #[test]
+    fn bytes_wait_for_space() {
+        let _serial = test_support::serial();
+        test_support::set_line_coding(9600, 0, 0, 8);
+        let mut uart = MockUart::new(b"abcd", 3);
+        test_support::receive(b"12345");
+        test_support::set_write_limit(2);
+
+        assert_eq!(USBSerial::pipe(&mut uart), (3, 2));
+        assert_eq!(uart.outgoing, b"123");
+        assert_eq!(test_support::sent(), b"ab");
+
+        // The rest goes once there is space
+        uart.space = 64;
+        test_support::set_write_limit(usize::MAX);
+        assert_eq!(USBSerial::pipe(&mut uart), (2, 2));
+        assert_eq!(uart.outgoing, b"12345");
+        assert_eq!(test_support::sent(), b"cd");
+    }
+
+    #[test]
+    fn nothing_is_read_while_output_is_paused() {
+        let _serial = test_support::serial();
+        test_support::set_line_coding(9600, 0, 0, 8);
+        let mut uart = MockUart::new(b"held", 64);
+        USBSerial::set_flow_control(FlowControl::XonXoff);
+
+        test_support::receive(&[XOFF]);
+        assert_eq!(USBSerial::pipe(&mut uart), (0, 0));
+        assert_eq!(uart.incoming.len(), 4);
+
+        test_support::receive(&[XON]);
+        assert_eq!(USBSerial::pipe(&mut uart), (0, 4));
+        assert_eq!(test_support::sent(), b"held");
+    }
+}
@@ -1,5 +1,12 @@
 //! Utilities for interfacing with the teensy 4's integrated temperature monitor
 
+#[cfg(feature = "usb_logging")]
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::{
+    fmt::{self, Display, Formatter},
+    ptr,
+};
+
 extern "C" {
     fn tempmonGetTemp() -> f32;
 }
@@ -8,3 +15,205 @@ extern "C" {
 pub fn get_temp() -> f32 {
     unsafe { tempmonGetTemp() }
 }
+
+/// The Temperature Sensor Control Register 0, holding the latest measurement
+const TEMPMON_TEMPSENSE0: *const u32 = 0x400D_8180 as *const u32;
+/// The fuses holding the temperature monitor's factory calibration
+const OCOTP_ANA1: *const u32 = 0x401F_44E0 as *const u32;
+
+/// The temperature count field of TEMPSENSE0, bits 19:8
+const TEMPSENSE0_TEMP_CNT_SHIFT: u32 = 8;
+const TEMPSENSE0_TEMP_CNT_MASK: u32 = 0xFFF;
+
+/// Get the raw count of the last temperature measurement, before it is calibrated into
+/// degrees, for doing your own calibration or logging the uncalibrated value. The count
+/// is 12 bits, and goes down as the temperature goes up.
+///
+/// This reads the measurement that the C layer's temperature monitor has started, the
+/// same one that [`get_temp`] converts.
+pub fn get_raw() -> u16 {
+    let tempsense0 = unsafe { ptr::read_volatile(TEMPMON_TEMPSENSE0) };
+
+    ((tempsense0 >> TEMPSENSE0_TEMP_CNT_SHIFT) & TEMPSENSE0_TEMP_CNT_MASK) as u16
+}
+
+/// Get the teensy's temperature in thousandths of a degree celsius, with integer math
+/// only, for builds that avoid floats.
+///
+/// This calibrates [`get_raw`] with the factory calibration fuses, the same way as the
+/// C layer does for [`get_temp`]. If the fuses are blank, so that there is no calibration,
+/// `i32::MIN` is returned, which is far out of the plausible range.
+pub fn get_temp_millicelsius() -> i32 {
+    let calibration = unsafe { ptr::read_volatile(OCOTP_ANA1) };
+
+    // The count measured at a hot temperature, and at room temperature (25 degrees)
+    let hot_temp = i64::from(calibration & 0xFF);
+    let hot_count = i64::from((calibration >> 8) & 0xFFF);
+    let room_count = i64::from((calibration >> 20) & 0xFFF);
+
+    let count = i64::from(get_raw());
+
+    // How far below the hot temperature the measurement is, in thousandths of a degree
+    match ((count - hot_count) * (hot_temp - 25) * 1000).checked_div(room_count - hot_count) {
+        Some(offset) => (hot_temp * 1000 - offset) as i32,
+        None => i32::MIN,
+    }
+}
+
+/// The lowest temperature (in degrees celsius) that is considered a plausible
+/// reading, the bottom of the chip's operating range
+pub const MIN_PLAUSIBLE_TEMP: f32 = -40.0;
+
+/// The highest temperature (in degrees celsius) that is considered a plausible
+/// reading, the top of the chip's operating range
+pub const MAX_PLAUSIBLE_TEMP: f32 = 125.0;
+
+/// Check if the temperature monitor is usable, by checking that it gives a plausible
+/// reading: a number between [`MIN_PLAUSIBLE_TEMP`] and [`MAX_PLAUSIBLE_TEMP`]. A monitor
+/// that is missing or not calibrated yet gives NaN or a reading far out of that range.
+pub fn is_available() -> bool {
+    // NaN is never contained in a range
+    (MIN_PLAUSIBLE_TEMP..=MAX_PLAUSIBLE_TEMP).contains(&get_temp())
+}
+
+/// A temperature in degrees celsius, which displays with its unit, such as `45.2°C`.
+/// The precision defaults to one decimal place, and can be set with the format,
+/// such as `{:.0}`.
+///
+/// With the feature `ascii_degrees`, the unit is displayed as ` deg C` instead, for
+/// serial monitors that do not handle UTF-8.
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub struct Temperature(pub f32);
+
+impl Temperature {
+    /// The temperature in degrees celsius
+    pub const fn celsius(self) -> f32 {
+        self.0
+    }
+}
+
+/// The unit that temperatures are displayed with
+#[cfg(not(feature = "ascii_degrees"))]
+const UNIT: &str = "°C";
+#[cfg(feature = "ascii_degrees")]
+const UNIT: &str = " deg C";
+
+impl Display for Temperature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.*}{}", f.precision().unwrap_or(1), self.0, UNIT)
+    }
+}
+
+/// Get the teensy's temperature, to be displayed
+pub fn get_temperature() -> Temperature {
+    Temperature(get_temp())
+}
+
+#[cfg(feature = "usb_logging")]
+static OVER_THRESHOLD: AtomicBool = AtomicBool::new(false);
+
+/// Check the teensy's temperature against a threshold (in degrees celsius),
+/// logging a warning when it rises above it. Call this periodically, such
+/// as once per loop.
+///
+/// Only one warning is logged each time the threshold is crossed. The temperature
+/// then has to fall `hysteresis` degrees below the threshold before it can warn
+/// again, so that a temperature hovering around the threshold does not flood the
+/// log. Returns if the temperature is currently considered over the threshold.
+///
+/// **Requires the feature `usb_logging`**
+#[cfg(feature = "usb_logging")]
+pub fn log_on_threshold(celsius: f32, hysteresis: f32) -> bool {
+    let temp = get_temp();
+    let (over, warn) = threshold_step(
+        OVER_THRESHOLD.load(Ordering::Relaxed),
+        temp,
+        celsius,
+        hysteresis,
+    );
+
+    OVER_THRESHOLD.store(over, Ordering::Relaxed);
+
+    if warn {
+        log::warn!(
+            "Temperature of {:.1}C is over the threshold of {:.1}C",
+            temp,
+            celsius
+        );
+    }
+
+    over
+}
+
+/// Move the state of [`log_on_threshold`] on with a new temperature, returning if
+/// the temperature is now over the threshold, and if it just crossed it
+#[cfg(feature = "usb_logging")]
+fn threshold_step(over: bool, temp: f32, celsius: f32, hysteresis: f32) -> (bool, bool) {
+    if over {
+        // Stays over until it falls below the band under the threshold
+        if temp < celsius - hysteresis {
+            (false, false)
+        } else {
+            (true, false)
+        }
+    } else if temp > celsius {
+        (true, true)
+    } else {
+        (false, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::format;
+
+    use super::*;
+
+    #[test]
+    #[cfg(not(feature = "ascii_degrees"))]
+    fn temperatures_are_shown_in_degrees() {
+        assert_eq!(format!("{}", Temperature(42.46)), "42.5°C");
+        assert_eq!(format!("{:.2}", Temperature(-5.0)), "-5.00°C");
+        assert_eq!(format!("{:.0}", Temperature(0.4)), "0°C");
+    }
+
+    #[test]
+    #[cfg(feature = "ascii_degrees")]
+    fn temperatures_are_shown_in_ascii_degrees() {
+        assert_eq!(format!("{}", Temperature(42.46)), "42.5 deg C");
+        assert_eq!(format!("{:.2}", Temperature(-5.0)), "-5.00 deg C");
+    }
+
+    #[test]
+    #[cfg(feature = "usb_logging")]
+    fn threshold_warns_once_per_crossing() {
+        let mut over = false;
+        let mut warnings = 0;
+
+        // Crossing up, hovering in the band, crossing back down and up again
+        for &temp in &[70.0, 81.0, 85.0, 79.0, 81.0, 76.0, 74.0, 79.0, 82.0] {
+            let (now_over, warn) = threshold_step(over, temp, 80.0, 5.0);
+
+            over = now_over;
+            warnings += usize::from(warn);
+        }
+
+        assert!(over);
+        assert_eq!(warnings, 2);
+    }
+
+    #[test]
+    #[cfg(feature = "usb_logging")]
+    fn threshold_clears_below_the_band() {
+        assert_eq!(threshold_step(false, 80.0, 80.0, 5.0), (false, false));
+        assert_eq!(threshold_step(false, 80.5, 80.0, 5.0), (true, true));
+        // In the band under the threshold
+        assert_eq!(threshold_step(true, 79.0, 80.0, 5.0), (true, false));
+        assert_eq!(threshold_step(true, 75.0, 80.0, 5.0), (true, false));
+        assert_eq!(threshold_step(true, 74.9, 80.0, 5.0), (false, false));
+        // A broken reading does not count as cooling down
+        assert_eq!(threshold_step(true, f32::NAN, 80.0, 5.0), (true, false));
+    }
+}
@@ -0,0 +1,225 @@
+//! Stand-ins for the teensy's C core, so that the unit tests can link and run on the host
+
+extern crate std;
+
+use core::{
+    cell::{Cell, RefCell},
+    ffi::c_void,
+    ptr, slice,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, MutexGuard},
+    vec::Vec,
+};
+
+use crate::serial::{FlowControl, USBSerial, Uart, TX_BUFFER_SIZE};
+
+/// The time since boot that the tests see, in milliseconds
+pub(crate) const MILLIS: u32 = 1234;
+
+/// The amount of times the usb serial output has been pushed out
+pub(crate) static FLUSHES: AtomicUsize = AtomicUsize::new(0);
+
+#[no_mangle]
+static systick_millis_count: u32 = MILLIS;
+
+/// The line coding that the host has set, see [`set_line_coding`]
+#[no_mangle]
+static mut usb_cdc_line_coding: [u32; 2] = [0; 2];
+
+std::thread_local! {
+    /// The bytes waiting to be read from the usb serial port
+    static RECEIVED: RefCell<VecDeque<u8>> = const { RefCell::new(VecDeque::new()) };
+    /// The bytes received once something is next written out, see [`reply`]
+    static REPLY: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    /// The bytes written out to the usb serial port
+    static SENT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    /// The amount of bytes that can still be written out, before writes write nothing
+    static WRITE_LIMIT: Cell<usize> = const { Cell::new(usize::MAX) };
+    /// What `usb_serial_write_buffer_free` returns
+    static WRITE_BUFFER_FREE: Cell<usize> = const { Cell::new(TX_BUFFER_SIZE) };
+}
+
+#[no_mangle]
+extern "C" fn usb_serial_available() -> usize {
+    RECEIVED.with(|received| received.borrow().len())
+}
+
+#[no_mangle]
+extern "C" fn usb_serial_flush_input() {
+    RECEIVED.with(|received| received.borrow_mut().clear());
+}
+
+#[no_mangle]
+extern "C" fn usb_serial_getchar() -> i16 {
+    RECEIVED.with(|received| received.borrow_mut().pop_front().map_or(-1, i16::from))
+}
+
+#[no_mangle]
+extern "C" fn usb_serial_peekchar() -> i16 {
+    RECEIVED.with(|received| {
+        received
+            .borrow()
+            .front()
+            .map_or(-1, |&byte| i16::from(byte))
+    })
+}
+
+#[no_mangle]
+extern "C" fn usb_serial_read(buffer: *mut c_void, size: usize) -> usize {
+    let buffer = unsafe { slice::from_raw_parts_mut(buffer as *mut u8, size) };
+
+    RECEIVED.with(|received| {
+        let mut received = received.borrow_mut();
+        let count = size.min(received.len());
+
+        for (slot, byte) in buffer.iter_mut().zip(received.drain(..count)) {
+            *slot = byte;
+        }
+
+        count
+    })
+}
+
+/// Writes are kept, see [`sent`], up to the [write limit](set_write_limit)
+#[no_mangle]
+extern "C" fn usb_serial_write(buffer: *const c_void, size: usize) -> usize {
+    let buffer = unsafe { slice::from_raw_parts(buffer as *const u8, size) };
+    let count = size.min(WRITE_LIMIT.with(Cell::get));
+
+    WRITE_LIMIT.with(|limit| limit.set(limit.get() - count));
+    SENT.with(|sent| sent.borrow_mut().extend_from_slice(&buffer[..count]));
+    receive(&REPLY.with(RefCell::take));
+
+    count
+}
+
+#[no_mangle]
+extern "C" fn usb_serial_write_buffer_free() -> usize {
+    WRITE_BUFFER_FREE.with(Cell::get)
+}
+
+#[no_mangle]
+extern "C" fn usb_serial_flush_output() {
+    FLUSHES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The time never moves on in the tests, so there is nothing to wait for
+#[no_mangle]
+extern "C" fn delay(_ms: u32) {}
+
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Keep the tests that change global state, such as the logger's sinks, from running at once
+pub(crate) fn lock() -> MutexGuard<'static, ()> {
+    LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Take the [lock](lock) for a test of the usb serial port, with nothing received or sent,
+/// and every setting back to its default, except for the timeouts, which are 0 as the time
+/// never moves on
+pub(crate) fn serial() -> MutexGuard<'static, ()> {
+    let guard = lock();
+
+    USBSerial::clear();
+    SENT.with(|sent| sent.borrow_mut().clear());
+    REPLY.with(|reply| reply.borrow_mut().clear());
+    set_write_limit(usize::MAX);
+    set_write_buffer_free(TX_BUFFER_SIZE);
+
+    USBSerial::resume_output();
+    USBSerial::set_flow_control(FlowControl::None);
+    USBSerial::set_crlf_translation(false);
+    USBSerial::set_nonblocking(false);
+    USBSerial::set_timeout(0);
+    USBSerial::set_write_timeout(0);
+    USBSerial::set_max_line_length(256);
+
+    guard
+}
+
+/// Queue up bytes to be read from the usb serial port
+pub(crate) fn receive(bytes: &[u8]) {
+    RECEIVED.with(|received| received.borrow_mut().extend(bytes));
+}
+
+/// Queue up bytes to be read once something is next written out, as the other end
+/// would answer a query, since the input is often cleared before one is sent
+pub(crate) fn reply(bytes: &[u8]) {
+    REPLY.with(|reply| reply.borrow_mut().extend_from_slice(bytes));
+}
+
+/// Take the bytes written out to the usb serial port so far
+pub(crate) fn sent() -> Vec<u8> {
+    SENT.with(|sent| sent.take())
+}
+
+/// Only let the next `limit` bytes be written out, after which writes write nothing
+pub(crate) fn set_write_limit(limit: usize) {
+    WRITE_LIMIT.with(|write_limit| write_limit.set(limit));
+}
+
+/// Set the free space that the output buffer reports
+pub(crate) fn set_write_buffer_free(free: usize) {
+    WRITE_BUFFER_FREE.with(|write_buffer_free| write_buffer_free.set(free));
+}
+
+/// Set the line coding as the host would: the baud rate, and then the stop bits,
+/// parity and number of bits
+pub(crate) fn set_line_coding(baud: u32, stop_bits: u8, parity: u8, num_bits: u8) {
+    let coding = [baud, u32::from_be_bytes([stop_bits, parity, num_bits, 0])];
+
+    unsafe { ptr::write_volatile(ptr::addr_of_mut!(usb_cdc_line_coding), coding) };
+}
+
+/// A hardware serial port that reads from and writes into memory
+pub(crate) struct MockUart {
+    /// The bytes waiting to be read
+    pub(crate) incoming: VecDeque<u8>,
+    /// The bytes written out
+    pub(crate) outgoing: Vec<u8>,
+    /// The amount of bytes that can still be written out
+    pub(crate) space: usize,
+    /// The baud rate last set
+    pub(crate) baud: Option<u32>,
+}
+
+impl MockUart {
+    pub(crate) fn new(incoming: &[u8], space: usize) -> Self {
+        MockUart {
+            incoming: incoming.iter().copied().collect(),
+            outgoing: Vec::new(),
+            space,
+            baud: None,
+        }
+    }
+}
+
+impl Uart for MockUart {
+    fn read(&mut self, buffer: &mut [u8]) -> usize {
+        let count = buffer.len().min(self.incoming.len());
+        for (slot, byte) in buffer.iter_mut().zip(self.incoming.drain(..count)) {
+            *slot = byte;
+        }
+
+        count
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> usize {
+        let count = buffer.len().min(self.space);
+        self.outgoing.extend_from_slice(&buffer[..count]);
+        self.space -= count;
+
+        count
+    }
+
+    fn available_for_write(&self) -> usize {
+        self.space
+    }
+
+    fn set_baud(&mut self, baud: u32) {
+        self.baud = Some(baud);
+    }
+}
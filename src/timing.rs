@@ -0,0 +1,46 @@
+//! Timing of pulses on an input, such as from ultrasonic distance sensors
+//! and RC receivers
+
+use crate::micros;
+
+/// Time how long (in microseconds) an input stays at a level, like Arduino's `pulseIn`.
+/// `read_level` reads the input, such as with [`Pin::digital_read`](crate::Pin::digital_read).
+///
+/// If the input is already at the level, that pulse is skipped, as its start was
+/// missed. Then this waits for the input to change to the level, and times how long
+/// it stays there. Returns `None` if no whole pulse arrives within `timeout_us`
+/// microseconds of the call.
+///
+/// # Notes and Warnings
+/// The timing is done with [`micros`], so the width is only accurate to a microsecond
+/// or so, on top of the time that `read_level` takes. Interrupts that fire during the
+/// pulse can make it seem longer. The microsecond counter overflows after about 70
+/// minutes, so timeouts longer than that can not be used.
+pub fn measure_pulse(read_level: impl Fn() -> bool, level: bool, timeout_us: u32) -> Option<u32> {
+    let start_micros = micros();
+    let timed_out = || micros().wrapping_sub(start_micros) >= timeout_us;
+
+    // Skip the pulse that is in progress
+    while read_level() == level {
+        if timed_out() {
+            return None;
+        }
+    }
+
+    // Wait for the pulse to start
+    while read_level() != level {
+        if timed_out() {
+            return None;
+        }
+    }
+    let pulse_micros = micros();
+
+    // Wait for the pulse to end
+    while read_level() == level {
+        if timed_out() {
+            return None;
+        }
+    }
+
+    Some(micros().wrapping_sub(pulse_micros))
+}
@@ -0,0 +1,109 @@
+//! Utilities for using the teensy 4's watchdog timer (WDOG1), which resets
+//! the board if the program stops feeding it, such as after locking up
+//!
+//! The watchdog can not be disabled once it is enabled, until the board
+//! resets, so there is no way to disable it here.
+//!
+//! Unlike the rest of the crate, this does not call into the teensy's C core,
+//! as the core has no functions for the watchdog (its startup code only turns
+//! the watchdogs off). Instead, the WDOG1 registers are written directly, as
+//! described in the i.MX RT1060 reference manual.
+
+use core::{
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// The shortest timeout (in milliseconds) that the watchdog supports
+pub const MIN_TIMEOUT_MS: u32 = 500;
+
+/// The longest timeout (in milliseconds) that the watchdog supports
+pub const MAX_TIMEOUT_MS: u32 = 128_000;
+
+/// The Watchdog Control Register
+const WDOG1_WCR: *mut u16 = 0x400B_8000 as *mut u16;
+/// The Watchdog Service Register
+const WDOG1_WSR: *mut u16 = 0x400B_8002 as *mut u16;
+/// The Watchdog Miscellaneous Control Register
+const WDOG1_WMCR: *mut u16 = 0x400B_8008 as *mut u16;
+/// The clock gating register holding the watchdog's clock gate
+const CCM_CCGR3: *mut u32 = 0x400F_C074 as *mut u32;
+
+/// The watchdog enable bit of WCR
+const WCR_WDE: u16 = 1 << 2;
+/// The bit of WCR that must be set to not reset the board straight away
+const WCR_SRS: u16 = 1 << 4;
+/// The bit of WCR that must be set to not assert the WDOG_B pin
+const WCR_WDA: u16 = 1 << 5;
+/// The watchdog's clock gate in CCGR3
+const CCGR3_WDOG1: u32 = 0b11 << 16;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Enable the watchdog, resetting the board if it is not [`feed`]ed for `timeout_ms`
+/// milliseconds. Calling this again changes the timeout.
+///
+/// The watchdog counts in steps of half a second, so the timeout is rounded up
+/// to the next step, and limited to between [`MIN_TIMEOUT_MS`] and [`MAX_TIMEOUT_MS`].
+/// Once enabled, the watchdog can not be disabled until the board resets.
+pub fn enable(timeout_ms: u32) {
+    unsafe {
+        // Turn on the watchdog's clock
+        ptr::write_volatile(CCM_CCGR3, ptr::read_volatile(CCM_CCGR3) | CCGR3_WDOG1);
+        // Turn off the power down counter, which otherwise resets the board after 16 seconds
+        ptr::write_volatile(WDOG1_WMCR, 0);
+
+        // Reload the counter so the new timeout starts fresh
+        feed();
+        ptr::write_volatile(WDOG1_WCR, control_value(timeout_ms));
+    }
+
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// The value of WCR that enables the watchdog with the timeout, see [`enable`]
+fn control_value(timeout_ms: u32) -> u16 {
+    let timeout_ms = timeout_ms.clamp(MIN_TIMEOUT_MS, MAX_TIMEOUT_MS);
+    // The timeout field holds the amount of half seconds (rounded up), minus one,
+    // which is the same as the amount of whole half seconds before the last millisecond
+    let steps = ((timeout_ms - 1) / MIN_TIMEOUT_MS) as u16;
+
+    steps << 8 | WCR_WDA | WCR_SRS | WCR_WDE
+}
+
+/// The values written to WSR, in order, to reload the counter
+const SERVICE_SEQUENCE: [u16; 2] = [0x5555, 0xAAAA];
+
+/// Reset the watchdog's countdown. Call this regularly once the watchdog is
+/// [`enable`]d, more often than the timeout.
+pub fn feed() {
+    for &value in &SERVICE_SEQUENCE {
+        unsafe { ptr::write_volatile(WDOG1_WSR, value) };
+    }
+}
+
+/// Check if the watchdog has been [`enable`]d
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeout_is_rounded_up_to_half_seconds() {
+        // The enable, no reset and no WDOG_B bits are always set
+        assert_eq!(control_value(500), 0x0034);
+        assert_eq!(control_value(501), 0x0134);
+        assert_eq!(control_value(1000), 0x0134);
+        assert_eq!(control_value(1001), 0x0234);
+        assert_eq!(control_value(MAX_TIMEOUT_MS), 0xFF34);
+    }
+
+    #[test]
+    fn timeout_is_limited() {
+        assert_eq!(control_value(0), control_value(MIN_TIMEOUT_MS));
+        assert_eq!(control_value(u32::MAX), control_value(MAX_TIMEOUT_MS));
+    }
+}